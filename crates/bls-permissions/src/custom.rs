@@ -0,0 +1,539 @@
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::ser::SerializeMap;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+
+use crate::error::type_error;
+use crate::lint::lint_options;
+use crate::AnyError;
+use crate::ChildUnaryPermissionArg;
+use crate::PermissionLint;
+use crate::PermissionState;
+use crate::Permissions;
+use crate::QueryDescriptor;
+use crate::SharedClock;
+use crate::UnaryPermission;
+
+/// The names of the kinds built into `Permissions`, which custom kinds can't
+/// reuse.
+const BUILTIN_KINDS: [&str; 10] = [
+    "read",
+    "write",
+    "net",
+    "env",
+    "sys",
+    "run",
+    "ffi",
+    "import",
+    "unix_socket",
+    "all",
+];
+
+/// A permission kind defined by the runtime, e.g. `kv` or `llm`. The kind's
+/// name is its `QueryDescriptor::flag_name`, and its allow and deny
+/// descriptors are the descriptor itself.
+pub trait CustomPermissionDescriptor:
+    QueryDescriptor<AllowDesc = Self, DenyDesc = Self>
+    + Clone
+    + Eq
+    + Hash
+    + Serialize
+    + DeserializeOwned
+    + Send
+    + Sync
+    + 'static
+{
+    fn parse(text: &str) -> Result<Self, AnyError>;
+}
+
+/// The options of a custom kind, the same as the `allow_<kind>` and
+/// `deny_<kind>` options of the built-in ones.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CustomPermissionOptions {
+    pub allow: Option<Vec<String>>,
+    pub deny: Option<Vec<String>>,
+}
+
+type BoxedPermission = Box<dyn CustomPermission>;
+
+#[derive(Clone, Copy)]
+struct CustomKind {
+    new: fn(&CustomPermissionOptions, bool) -> Result<BoxedPermission, AnyError>,
+    allow_all: fn() -> BoxedPermission,
+    restore: fn(serde_json::Value) -> Result<BoxedPermission, serde_json::Error>,
+    parse: fn(&str) -> Result<(), AnyError>,
+    lint: fn(&CustomPermissionOptions, &mut Vec<PermissionLint>),
+}
+
+/// The custom permission kinds known to a `Permissions`, see `register`.
+#[derive(Clone, Default)]
+pub struct CustomKinds(BTreeMap<&'static str, CustomKind>);
+
+impl CustomKinds {
+    /// Registers a custom permission kind. Permissions created from options
+    /// holding these kinds include the kind.
+    pub fn register<D: CustomPermissionDescriptor>(&mut self) -> Result<(), AnyError> {
+        let name = D::flag_name();
+        if BUILTIN_KINDS.contains(&name) {
+            return Err(type_error(format!(
+                "Permission kind \"{name}\" is built-in"
+            )));
+        }
+        if self.0.contains_key(name) {
+            return Err(type_error(format!(
+                "Permission kind \"{name}\" is already registered"
+            )));
+        }
+        self.0.insert(
+            name,
+            CustomKind {
+                new: |opts, prompt| {
+                    let parse = |items: Option<&Vec<String>>| {
+                        items
+                            .map(|items| items.iter().map(|item| D::parse(item)).collect())
+                            .transpose()
+                    };
+                    let perm = Permissions::new_unary::<D>(
+                        parse(opts.allow.as_ref())?,
+                        parse(opts.deny.as_ref())?,
+                        prompt,
+                    )?;
+                    Ok(Box::new(perm))
+                },
+                allow_all: || Box::new(UnaryPermission::<D>::allow_all()),
+                restore: |value| {
+                    let mut perm = serde_json::from_value::<UnaryPermission<D>>(value)?;
+                    perm.drop_used_up();
+                    Ok(Box::new(perm))
+                },
+                parse: |text| D::parse(text).map(|_| ()),
+                lint: |opts, lints| {
+                    lint_options::<D>(
+                        lints,
+                        opts.allow.as_ref(),
+                        opts.deny.as_ref(),
+                        |text| D::parse(text).map(Some),
+                        D::parse,
+                    )
+                },
+            },
+        );
+        Ok(())
+    }
+
+    /// The names of every registered kind.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.0.keys().copied().collect()
+    }
+
+    /// Parses an entry of the kind `name`, failing if the kind isn't
+    /// registered.
+    pub(crate) fn parse_entry(&self, name: &str, text: &str) -> Result<(), AnyError> {
+        let kind = self
+            .0
+            .get(name)
+            .ok_or_else(|| type_error(format!("Unknown permission kind \"{name}\"")))?;
+        (kind.parse)(text)
+    }
+
+    /// Lints the options of every registered kind in `options`.
+    pub(crate) fn lint(
+        &self,
+        options: &BTreeMap<String, CustomPermissionOptions>,
+        lints: &mut Vec<PermissionLint>,
+    ) {
+        for (name, opts) in options {
+            if let Some(kind) = self.0.get(name.as_str()) {
+                (kind.lint)(opts, lints);
+            }
+        }
+    }
+}
+
+impl Debug for CustomKinds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("CustomKinds").field(&self.names()).finish()
+    }
+}
+
+impl PartialEq for CustomKinds {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.keys().eq(other.0.keys())
+    }
+}
+
+impl Eq for CustomKinds {}
+
+impl Permissions {
+    /// Registers a custom permission kind on these permissions. The kind
+    /// takes over its restored state if these permissions come from a
+    /// snapshot, otherwise it's granted if all access is and not otherwise.
+    pub fn register_permission_kind<D: CustomPermissionDescriptor>(
+        &mut self,
+    ) -> Result<(), AnyError> {
+        self.custom_kinds.register::<D>()?;
+        let allow_all = self.all.query() == PermissionState::Granted;
+        self.custom.add_kind(
+            &self.custom_kinds,
+            D::flag_name(),
+            allow_all,
+            self.read.prompt,
+        )?;
+        if let Some(perm) = self.custom.get_mut::<D>() {
+            perm.prompt_grant_ttl = self.read.prompt_grant_ttl;
+            perm.clock = self.read.clock.clone();
+        }
+        Ok(())
+    }
+}
+
+impl<D: CustomPermissionDescriptor> UnaryPermission<D> {
+    /// Checks a descriptor of a custom kind, prompting if needed. Custom
+    /// descriptors can implement `QueryDescriptor::check_in_permission` with it.
+    pub fn check(&mut self, desc: &D, api_name: Option<&str>) -> Result<(), AnyError> {
+        if self.is_allow_all() {
+            return Ok(());
+        }
+        self.check_desc(Some(desc), false, api_name)
+    }
+}
+
+/// A `UnaryPermission` of a custom kind, with its descriptors erased so that
+/// kinds can be looked up by name.
+trait CustomPermission: Debug + Send + Sync {
+    fn clone_box(&self) -> Box<dyn CustomPermission>;
+    fn eq_box(&self, other: &dyn CustomPermission) -> bool;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn to_value(&self) -> Result<serde_json::Value, serde_json::Error>;
+    fn is_allow_all(&self) -> bool;
+    fn set_prompt_grant_ttl(&mut self, ttl: Option<Duration>);
+    fn set_clock(&mut self, clock: SharedClock);
+    fn grant_for(&mut self, value: Option<&str>, ttl: Duration) -> Result<(), AnyError>;
+    fn check(&mut self, value: Option<&str>, api_name: Option<&str>) -> Result<(), AnyError>;
+    fn query(&self, value: Option<&str>) -> Result<PermissionState, AnyError>;
+    fn remaining_uses(&self, value: Option<&str>) -> Result<Option<u32>, AnyError>;
+    fn request(&mut self, value: Option<&str>) -> Result<PermissionState, AnyError>;
+    fn revoke(&mut self, value: Option<&str>) -> Result<PermissionState, AnyError>;
+    fn create_child_permissions(
+        &mut self,
+        arg: ChildUnaryPermissionArg,
+    ) -> Result<Box<dyn CustomPermission>, AnyError>;
+}
+
+impl<D: CustomPermissionDescriptor> CustomPermission for UnaryPermission<D> {
+    fn clone_box(&self) -> Box<dyn CustomPermission> {
+        Box::new(self.clone())
+    }
+
+    fn eq_box(&self, other: &dyn CustomPermission) -> bool {
+        other
+            .as_any()
+            .downcast_ref::<Self>()
+            .is_some_and(|other| self == other)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn to_value(&self) -> Result<serde_json::Value, serde_json::Error> {
+        serde_json::to_value(self)
+    }
+
+    fn is_allow_all(&self) -> bool {
+        UnaryPermission::is_allow_all(self)
+    }
+
+    fn set_prompt_grant_ttl(&mut self, ttl: Option<Duration>) {
+        self.prompt_grant_ttl = ttl;
+    }
+
+    fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock;
+    }
+
+    fn grant_for(&mut self, value: Option<&str>, ttl: Duration) -> Result<(), AnyError> {
+        let desc = value.map(D::parse).transpose()?;
+        UnaryPermission::<D>::grant_for(self, desc, ttl);
+        Ok(())
+    }
+
+    fn check(&mut self, value: Option<&str>, api_name: Option<&str>) -> Result<(), AnyError> {
+        match value {
+            Some(value) => UnaryPermission::<D>::check(self, &D::parse(value)?, api_name),
+            None => {
+                if UnaryPermission::is_allow_all(self) {
+                    return Ok(());
+                }
+                self.check_desc(None, false, api_name)
+            }
+        }
+    }
+
+    fn query(&self, value: Option<&str>) -> Result<PermissionState, AnyError> {
+        let desc = value.map(D::parse).transpose()?;
+        Ok(self.query_desc(desc.as_ref(), crate::AllowPartial::TreatAsPartialGranted))
+    }
+
+    fn remaining_uses(&self, value: Option<&str>) -> Result<Option<u32>, AnyError> {
+        let desc = value.map(D::parse).transpose()?;
+        Ok(UnaryPermission::remaining_uses(self, desc.as_ref()))
+    }
+
+    fn request(&mut self, value: Option<&str>) -> Result<PermissionState, AnyError> {
+        let desc = value.map(D::parse).transpose()?;
+        Ok(self.request_desc(desc.as_ref()))
+    }
+
+    fn revoke(&mut self, value: Option<&str>) -> Result<PermissionState, AnyError> {
+        let desc = value.map(D::parse).transpose()?;
+        Ok(self.revoke_desc(desc.as_ref()))
+    }
+
+    fn create_child_permissions(
+        &mut self,
+        arg: ChildUnaryPermissionArg,
+    ) -> Result<Box<dyn CustomPermission>, AnyError> {
+        let perm =
+            UnaryPermission::create_child_permissions(self, arg, |text| Ok(Some(D::parse(text)?)))?;
+        Ok(Box::new(perm))
+    }
+}
+
+/// The permissions of every custom kind, keyed by kind name.
+#[derive(Debug, Default)]
+pub struct CustomPermissions {
+    perms: BTreeMap<&'static str, Box<dyn CustomPermission>>,
+    /// The restored state of kinds that aren't registered yet, taken over
+    /// when they are.
+    pending: BTreeMap<String, serde_json::Value>,
+}
+
+impl CustomPermissions {
+    pub(crate) fn from_options(
+        kinds: &CustomKinds,
+        options: &BTreeMap<String, CustomPermissionOptions>,
+        prompt: bool,
+    ) -> Result<Self, AnyError> {
+        if let Some(name) = options
+            .keys()
+            .find(|name| !kinds.0.contains_key(name.as_str()))
+        {
+            return Err(type_error(format!("Unknown permission kind \"{name}\"")));
+        }
+        let default = CustomPermissionOptions::default();
+        let mut perms = BTreeMap::new();
+        for (name, kind) in kinds.0.iter() {
+            let opts = options.get(*name).unwrap_or(&default);
+            perms.insert(*name, (kind.new)(opts, prompt)?);
+        }
+        Ok(Self {
+            perms,
+            pending: BTreeMap::new(),
+        })
+    }
+
+    /// Adds the permission of a newly registered kind `name`: the restored
+    /// state if there's one, otherwise everything if `allow_all` is set and
+    /// nothing if not.
+    pub(crate) fn add_kind(
+        &mut self,
+        kinds: &CustomKinds,
+        name: &str,
+        allow_all: bool,
+        prompt: bool,
+    ) -> Result<(), AnyError> {
+        let Some((name, kind)) = kinds.0.get_key_value(name) else {
+            return Err(type_error(format!("Unknown permission kind \"{name}\"")));
+        };
+        let perm = match self.pending.remove(*name) {
+            Some(value) => (kind.restore)(value)?,
+            None if allow_all => (kind.allow_all)(),
+            None => (kind.new)(&CustomPermissionOptions::default(), prompt)?,
+        };
+        self.perms.insert(*name, perm);
+        Ok(())
+    }
+
+    /// The names of the kinds in these permissions.
+    pub fn kinds(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.perms.keys().copied()
+    }
+
+    pub fn get<D: CustomPermissionDescriptor>(&self) -> Option<&UnaryPermission<D>> {
+        self.perms
+            .get(D::flag_name())
+            .and_then(|perm| perm.as_any().downcast_ref())
+    }
+
+    pub fn get_mut<D: CustomPermissionDescriptor>(&mut self) -> Option<&mut UnaryPermission<D>> {
+        self.perms
+            .get_mut(D::flag_name())
+            .and_then(|perm| perm.as_any_mut().downcast_mut())
+    }
+
+    pub fn is_allow_all(&self, kind: &str) -> Result<bool, AnyError> {
+        Ok(self.kind(kind)?.is_allow_all())
+    }
+
+    pub fn check(
+        &mut self,
+        kind: &str,
+        value: Option<&str>,
+        api_name: Option<&str>,
+    ) -> Result<(), AnyError> {
+        self.kind_mut(kind)?.check(value, api_name)
+    }
+
+    pub fn query(&self, kind: &str, value: Option<&str>) -> Result<PermissionState, AnyError> {
+        self.kind(kind)?.query(value)
+    }
+
+    pub fn remaining_uses(&self, kind: &str, value: Option<&str>) -> Result<Option<u32>, AnyError> {
+        self.kind(kind)?.remaining_uses(value)
+    }
+
+    pub fn request(
+        &mut self,
+        kind: &str,
+        value: Option<&str>,
+    ) -> Result<PermissionState, AnyError> {
+        self.kind_mut(kind)?.request(value)
+    }
+
+    pub fn revoke(&mut self, kind: &str, value: Option<&str>) -> Result<PermissionState, AnyError> {
+        self.kind_mut(kind)?.revoke(value)
+    }
+
+    pub fn grant_for(
+        &mut self,
+        kind: &str,
+        value: Option<&str>,
+        ttl: Duration,
+    ) -> Result<(), AnyError> {
+        self.kind_mut(kind)?.grant_for(value, ttl)
+    }
+
+    pub(crate) fn set_prompt_grant_ttl(&mut self, ttl: Option<Duration>) {
+        for perm in self.perms.values_mut() {
+            perm.set_prompt_grant_ttl(ttl);
+        }
+    }
+
+    pub(crate) fn set_clock(&mut self, clock: SharedClock) {
+        for perm in self.perms.values_mut() {
+            perm.set_clock(clock.clone());
+        }
+    }
+
+    /// Creates the child permissions of every kind. Kinds missing from
+    /// `args` get `default`.
+    pub(crate) fn create_child_permissions(
+        &mut self,
+        mut args: BTreeMap<String, ChildUnaryPermissionArg>,
+        default: &ChildUnaryPermissionArg,
+    ) -> Result<Self, AnyError> {
+        let mut perms = BTreeMap::new();
+        for (name, perm) in self.perms.iter_mut() {
+            let arg = args.remove(*name).unwrap_or_else(|| default.clone());
+            perms.insert(*name, perm.create_child_permissions(arg)?);
+        }
+        if let Some(name) = args.keys().next() {
+            return Err(type_error(format!("Unknown permission kind \"{name}\"")));
+        }
+        Ok(Self {
+            perms,
+            pending: BTreeMap::new(),
+        })
+    }
+
+    /// Fails with the same error as the other methods if `kind` isn't
+    /// registered.
+    pub(crate) fn ensure_kind(&self, kind: &str) -> Result<(), AnyError> {
+        self.kind(kind).map(|_| ())
+    }
+
+    fn kind(&self, kind: &str) -> Result<&dyn CustomPermission, AnyError> {
+        match self.perms.get(kind) {
+            Some(perm) => Ok(perm.as_ref()),
+            None => Err(type_error(format!("Unknown permission kind \"{kind}\""))),
+        }
+    }
+
+    fn kind_mut(&mut self, kind: &str) -> Result<&mut Box<dyn CustomPermission>, AnyError> {
+        self.perms
+            .get_mut(kind)
+            .ok_or_else(|| type_error(format!("Unknown permission kind \"{kind}\"")))
+    }
+}
+
+impl Clone for CustomPermissions {
+    fn clone(&self) -> Self {
+        Self {
+            perms: self
+                .perms
+                .iter()
+                .map(|(name, perm)| (*name, perm.clone_box()))
+                .collect(),
+            pending: self.pending.clone(),
+        }
+    }
+}
+
+impl PartialEq for CustomPermissions {
+    fn eq(&self, other: &Self) -> bool {
+        self.pending == other.pending
+            && self.perms.len() == other.perms.len()
+            && self.perms.iter().all(|(name, perm)| {
+                other
+                    .perms
+                    .get(name)
+                    .is_some_and(|other| perm.eq_box(other.as_ref()))
+            })
+    }
+}
+
+impl Eq for CustomPermissions {}
+
+impl Serialize for CustomPermissions {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(self.perms.len() + self.pending.len()))?;
+        for (name, perm) in &self.perms {
+            let value = perm.to_value().map_err(serde::ser::Error::custom)?;
+            map.serialize_entry(name, &value)?;
+        }
+        for (name, value) in &self.pending {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for CustomPermissions {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(Self {
+            perms: BTreeMap::new(),
+            pending: BTreeMap::deserialize(deserializer)?,
+        })
+    }
+}
@@ -0,0 +1,278 @@
+// Glob patterns in path descriptors, e.g. `/data/**/*.json` or `/**/.env`.
+//
+// A component containing `*`, `?` or `[...]` is a pattern, `**` matches any
+// number of components. Paths without glob syntax keep the plain prefix
+// semantics of `Path::starts_with`.
+//
+// This changes the meaning of existing entries naming a file or directory
+// whose name contains one of these characters: they're now read as
+// patterns. Such names are written with the characters escaped, as `\*`,
+// `\?`, `\[` or `\]`, or as a one-character class, e.g. `[[]`. On Windows,
+// where `\` separates components, only the class works.
+
+use std::borrow::Cow;
+use std::path::Component;
+use std::path::Path;
+
+/// Gets if any component of the path contains glob syntax, escaped or not.
+pub fn is_glob(path: &Path) -> bool {
+    path.components().any(|component| match component {
+        Component::Normal(s) => s.to_string_lossy().contains(['*', '?', '[']),
+        _ => false,
+    })
+}
+
+/// Gets if `path` or one of its ancestors is matched by `pattern`.
+pub fn path_matches(pattern: &Path, path: &Path) -> bool {
+    if !is_glob(pattern) {
+        return path.starts_with(pattern);
+    }
+    match_components(&components(pattern), &components(path))
+}
+
+/// Gets if `path` is a directory holding something matched by `pattern`, or
+/// is matched itself. Only the part of the pattern before the first `**` is
+/// considered, any directory below it could hold a match.
+pub fn path_contains_match(path: &Path, pattern: &Path) -> bool {
+    contains_match(path, pattern, true)
+}
+
+/// Same as `path_contains_match`, but a path below the first `**` of the
+/// pattern isn't considered to hold a match. Any path could be a directory
+/// holding one, so checks of whole paths use this to not deny all of them.
+pub fn path_contains_fixed_match(path: &Path, pattern: &Path) -> bool {
+    contains_match(path, pattern, false)
+}
+
+fn contains_match(path: &Path, pattern: &Path, below_globstar: bool) -> bool {
+    if !is_glob(pattern) {
+        return pattern.starts_with(path);
+    }
+    let pattern = components(pattern);
+    let path = components(path);
+    let fixed = pattern
+        .iter()
+        .position(|p| p == "**")
+        .unwrap_or(pattern.len());
+    (path.len() <= fixed || (below_globstar && fixed < pattern.len()))
+        && path
+            .iter()
+            .take(fixed)
+            .zip(&pattern)
+            .all(|(c, p)| match_component(p, c))
+}
+
+fn components(path: &Path) -> Vec<Cow<'_, str>> {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect()
+}
+
+/// Matches `path` or any of its ancestors against the pattern.
+fn match_components(pattern: &[Cow<str>], path: &[Cow<str>]) -> bool {
+    match (pattern.first(), path.first()) {
+        (None, _) => true,
+        (Some(_), None) => pattern.iter().all(|p| p == "**"),
+        (Some(p), Some(_)) if p == "**" => {
+            match_components(&pattern[1..], path) || match_components(pattern, &path[1..])
+        }
+        (Some(p), Some(c)) => match_component(p, c) && match_components(&pattern[1..], &path[1..]),
+    }
+}
+
+fn match_component(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut pi, mut ti) = (0, 0);
+    // position of the last `*` and the text index it's currently matched up to
+    let mut star = None;
+    while ti < text.len() {
+        let next = match pattern.get(pi) {
+            Some('*') => {
+                star = Some((pi, ti));
+                pi += 1;
+                continue;
+            }
+            Some('\\') if matches!(pattern.get(pi + 1), Some('*' | '?' | '[' | ']')) => {
+                (pattern[pi + 1] == text[ti]).then_some(pi + 2)
+            }
+            Some('?') => Some(pi + 1),
+            Some('[') => match match_class(&pattern, pi, text[ti]) {
+                Some((matched, end)) => matched.then_some(end),
+                // an unclosed bracket is a literal
+                None => (text[ti] == '[').then_some(pi + 1),
+            },
+            Some(c) => (*c == text[ti]).then_some(pi + 1),
+            None => None,
+        };
+        if let Some(next) = next {
+            pi = next;
+            ti += 1;
+        } else if let Some((star_pi, star_ti)) = star {
+            pi = star_pi + 1;
+            ti = star_ti + 1;
+            star = Some((star_pi, star_ti + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[pi..].iter().all(|c| *c == '*')
+}
+
+/// Matches `c` against the class starting at `pattern[start] == '['`.
+/// Returns whether it matched and the index after the class, or `None` if
+/// the class is not closed.
+fn match_class(pattern: &[char], start: usize, c: char) -> Option<(bool, usize)> {
+    let mut i = start + 1;
+    let negate = matches!(pattern.get(i), Some('!' | '^'));
+    if negate {
+        i += 1;
+    }
+    let first = i;
+    let mut matched = false;
+    while i < pattern.len() {
+        if pattern[i] == ']' && i > first {
+            return Some((matched != negate, i + 1));
+        }
+        if i + 2 < pattern.len() && pattern[i + 1] == '-' && pattern[i + 2] != ']' {
+            matched |= pattern[i] <= c && c <= pattern[i + 2];
+            i += 3;
+        } else {
+            matched |= pattern[i] == c;
+            i += 1;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_component() {
+        let cases = [
+            ("*.json", "a.json", true),
+            ("*.json", ".json", true),
+            ("*.json", "a.json5", false),
+            ("a*b*c", "aXXbYYc", true),
+            ("a*b*c", "aXXbYY", false),
+            ("?.txt", "a.txt", true),
+            ("?.txt", "ab.txt", false),
+            ("[abc].rs", "b.rs", true),
+            ("[!abc].rs", "b.rs", false),
+            ("[a-z]1", "q1", true),
+            ("[a-z]1", "Q1", false),
+            ("[]]", "]", true),
+            ("[ab", "[ab", true),
+            (".env", ".env", true),
+            ("*", "", true),
+            ("\\[draft\\].md", "[draft].md", true),
+            ("\\[draft\\].md", "d].md", false),
+            ("what\\?", "what?", true),
+            ("what\\?", "whats", false),
+            ("\\*", "*", true),
+            ("\\*", "a", false),
+            ("*\\*", "a*", true),
+            ("[[]draft].md", "[draft].md", true),
+            ("a\\b", "a\\b", true),
+        ];
+        for (pattern, text, expected) in cases {
+            assert_eq!(match_component(pattern, text), expected, "{pattern} {text}");
+        }
+    }
+
+    #[test]
+    fn test_path_matches() {
+        let cases = [
+            ("/data", "/data/x/y", true),
+            ("/data", "/database", false),
+            ("/data/**/*.json", "/data/a.json", true),
+            ("/data/**/*.json", "/data/a/b/c.json", true),
+            ("/data/**/*.json", "/data/a/b/c.json/inner", true),
+            ("/data/**/*.json", "/data/a/b/c.txt", false),
+            ("/data/**/*.json", "/data", false),
+            ("/**/.env", "/.env", true),
+            ("/**/.env", "/home/user/project/.env", true),
+            ("/**/.env", "/home/user/project/.envrc", false),
+            ("/home/*/.ssh", "/home/user/.ssh/id_rsa", true),
+            ("/home/*/.ssh", "/home/user/sub/.ssh", false),
+            ("/data/**", "/data", true),
+            ("/data/[[]x]", "/data/[x]/y", true),
+            ("/data/[[]x]", "/data/x/y", false),
+        ];
+        for (pattern, path, expected) in cases {
+            assert_eq!(
+                path_matches(Path::new(pattern), Path::new(path)),
+                expected,
+                "{pattern} {path}"
+            );
+        }
+    }
+
+    // `\` separates components on Windows, so escapes only work elsewhere
+    #[cfg(unix)]
+    #[test]
+    fn test_path_matches_escapes() {
+        let cases = [
+            ("/data/\\[x\\]", "/data/[x]/y", true),
+            ("/data/\\[x\\]", "/data/x/y", false),
+            ("/data/\\*", "/data/*", true),
+            ("/data/\\*", "/data/a", false),
+        ];
+        for (pattern, path, expected) in cases {
+            assert_eq!(
+                path_matches(Path::new(pattern), Path::new(path)),
+                expected,
+                "{pattern} {path}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_path_contains_match() {
+        let cases = [
+            ("/data", "/data/x", true),
+            ("/data/x", "/data", false),
+            ("/", "/**/.env", true),
+            ("/home/user", "/**/.env", true),
+            ("/", "/data/**/*.json", true),
+            ("/data", "/data/**/*.json", true),
+            ("/data/a", "/data/**/*.json", true),
+            ("/data/a/b", "/data/**/*.json", true),
+            ("/other", "/data/**/*.json", false),
+            ("/other/a", "/data/**/*.json", false),
+            ("/home", "/home/*/.ssh", true),
+            ("/home/user", "/home/*/.ssh", true),
+            ("/home/user/.ssh", "/home/*/.ssh", true),
+            ("/home/user/docs", "/home/*/.ssh", false),
+        ];
+        for (path, pattern, expected) in cases {
+            assert_eq!(
+                path_contains_match(Path::new(path), Path::new(pattern)),
+                expected,
+                "{path} {pattern}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_path_contains_fixed_match() {
+        let cases = [
+            ("/", "/**/.env", true),
+            ("/home/user", "/**/.env", false),
+            ("/data", "/data/**/*.json", true),
+            ("/data/a", "/data/**/*.json", false),
+            ("/home/user", "/home/*/.ssh", true),
+            ("/home/user/docs", "/home/*/.ssh", false),
+            ("/data", "/data/x", true),
+        ];
+        for (path, pattern, expected) in cases {
+            assert_eq!(
+                path_contains_fixed_match(Path::new(path), Path::new(pattern)),
+                expected,
+                "{path} {pattern}"
+            );
+        }
+    }
+}
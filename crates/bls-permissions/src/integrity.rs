@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::de;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::error::type_error;
+use crate::AnyError;
+
+const SHA256_PREFIX: &str = "sha256:";
+
+/// A SHA-256 digest pinning the content of a file. It's written as
+/// `sha256:<hex>`, and appended to descriptors with an `@`, e.g.
+/// `/usr/bin/git@sha256:<hex>`.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub struct Sha256Digest(pub [u8; 32]);
+
+impl Sha256Digest {
+    /// Parses `sha256:<hex>`.
+    pub fn parse(text: &str) -> Result<Self, AnyError> {
+        let invalid = || type_error(format!("Invalid sha256 digest '{text}'"));
+        let hex = text.strip_prefix(SHA256_PREFIX).ok_or_else(invalid)?;
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut bytes = [0; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(Self(bytes))
+    }
+
+    /// Splits a descriptor like `/usr/bin/git@sha256:<hex>` into the part
+    /// before the digest and the digest, if any.
+    pub fn split_descriptor(text: &str) -> Result<(&str, Option<Self>), AnyError> {
+        match text.rsplit_once(&format!("@{SHA256_PREFIX}")) {
+            Some((desc, hex)) => {
+                let digest = Self::parse(&format!("{SHA256_PREFIX}{hex}"))?;
+                Ok((desc, Some(digest)))
+            }
+            None => Ok((text, None)),
+        }
+    }
+
+    pub fn of_bytes(bytes: &[u8]) -> Self {
+        Self(Sha256::digest(bytes).into())
+    }
+
+    /// Hashes the content of a file. Digests are cached until the file's
+    /// inode, size, modification or status change time change. The status
+    /// change time can't be set back like the modification time can.
+    pub fn of_file(path: &Path) -> io::Result<Self> {
+        let key = FileKey::new(path)?;
+        if let Some(digest) = FILE_DIGESTS.lock().get(&key) {
+            return Ok(*digest);
+        }
+        let mut hasher = Sha256::new();
+        io::copy(&mut File::open(path)?, &mut hasher)?;
+        let digest = Self(hasher.finalize().into());
+        let mut cache = FILE_DIGESTS.lock();
+        if cache.len() >= MAX_CACHED_DIGESTS {
+            cache.clear();
+        }
+        cache.insert(key, digest);
+        Ok(digest)
+    }
+
+    /// Gets if the content of the file at `path` has this digest. Files that
+    /// can't be read never match.
+    pub fn matches_file(&self, path: &Path) -> bool {
+        Self::of_file(path).is_ok_and(|digest| digest == *self)
+    }
+}
+
+impl fmt::Display for Sha256Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{SHA256_PREFIX}")?;
+        for byte in self.0 {
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for Sha256Digest {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Sha256Digest {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let text = String::deserialize(deserializer)?;
+        Sha256Digest::parse(&text).map_err(de::Error::custom)
+    }
+}
+
+/// Checks a file against the digests of the grants allowing it. It passes
+/// if one of the grants isn't pinned, or if the file matches one of the
+/// digests.
+pub(crate) fn pinned_digests_match<'a>(
+    pins: impl IntoIterator<Item = Option<&'a Sha256Digest>>,
+    path: &Path,
+) -> bool {
+    pinned_digests_accept(pins, |digest| digest.matches_file(path))
+}
+
+/// Like `pinned_digests_match`, with content that was already hashed.
+pub(crate) fn pinned_digests_include<'a>(
+    pins: impl IntoIterator<Item = Option<&'a Sha256Digest>>,
+    digest: &Sha256Digest,
+) -> bool {
+    pinned_digests_accept(pins, |pin| pin == digest)
+}
+
+fn pinned_digests_accept<'a>(
+    pins: impl IntoIterator<Item = Option<&'a Sha256Digest>>,
+    accepts: impl Fn(&Sha256Digest) -> bool,
+) -> bool {
+    let mut digests = Vec::new();
+    for pin in pins {
+        match pin {
+            Some(digest) => digests.push(digest),
+            None => return true,
+        }
+    }
+    digests.is_empty() || digests.into_iter().any(accepts)
+}
+
+const MAX_CACHED_DIGESTS: usize = 256;
+
+static FILE_DIGESTS: Lazy<Mutex<HashMap<FileKey, Sha256Digest>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Eq, PartialEq, Hash)]
+struct FileKey {
+    path: PathBuf,
+    dev: u64,
+    ino: u64,
+    len: u64,
+    modified: Option<SystemTime>,
+    changed: (i64, i64),
+}
+
+impl FileKey {
+    fn new(path: &Path) -> io::Result<Self> {
+        let metadata = std::fs::metadata(path)?;
+        #[cfg(unix)]
+        let (dev, ino, changed) = {
+            use std::os::unix::fs::MetadataExt;
+            (
+                metadata.dev(),
+                metadata.ino(),
+                (metadata.ctime(), metadata.ctime_nsec()),
+            )
+        };
+        #[cfg(not(unix))]
+        let (dev, ino, changed) = (0, 0, (0, 0));
+        Ok(Self {
+            path: path.to_path_buf(),
+            dev,
+            ino,
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            changed,
+        })
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::HashSet;
+
+use crate::PermissionsOptions;
+
+/// One source of permission options, e.g. node-wide defaults, a project
+/// policy or the command line flags.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PermissionsLayer {
+    pub name: String,
+    pub options: PermissionsOptions,
+    /// Kinds (`read`, `net`, ...) whose allow list may not be widened by the
+    /// layers above this one.
+    pub locked: HashSet<String>,
+}
+
+impl PermissionsLayer {
+    pub fn new(name: impl Into<String>, options: PermissionsOptions) -> Self {
+        Self {
+            name: name.into(),
+            options,
+            locked: HashSet::new(),
+        }
+    }
+
+    pub fn with_locked(mut self, kind: impl Into<String>) -> Self {
+        self.locked.insert(kind.into());
+        self
+    }
+}
+
+/// Where an entry of a merged `PermissionsOptions` came from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PermissionsEntryOrigin {
+    /// The option field, e.g. `allow_net` or `allow_all`.
+    pub field: String,
+    /// The entry, or `None` when the whole kind was allowed or denied.
+    pub entry: Option<String>,
+    /// The name of the layer that contributed the entry.
+    pub layer: String,
+}
+
+/// The result of `PermissionsOptions::merge_layers`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LayeredPermissionsOptions {
+    pub options: PermissionsOptions,
+    /// The origin of every effective entry in `options`.
+    pub origins: Vec<PermissionsEntryOrigin>,
+    /// Allow entries dropped because a lower layer locked their kind.
+    pub rejected: Vec<PermissionsEntryOrigin>,
+}
+
+impl LayeredPermissionsOptions {
+    /// Gets the name of the layer that contributed `entry` to `field`. Use
+    /// `None` to look up a grant or denial of the whole kind.
+    pub fn origin(&self, field: &str, entry: Option<&str>) -> Option<&str> {
+        self.origins
+            .iter()
+            .find(|origin| origin.field == field && origin.entry.as_deref() == entry)
+            .map(|origin| origin.layer.as_str())
+    }
+
+    fn insert(&mut self, origin: PermissionsEntryOrigin) {
+        let Some(list) = self.options.list_mut(&origin.field) else {
+            return;
+        };
+        // an empty list covers the whole kind, so it subsumes every entry
+        let is_global = list.as_ref().is_some_and(|items| items.is_empty());
+        match &origin.entry {
+            None => {
+                if is_global {
+                    return;
+                }
+                *list = Some(vec![]);
+                self.origins.retain(|other| other.field != origin.field);
+            }
+            Some(entry) => {
+                if is_global || list.as_ref().is_some_and(|items| items.contains(entry)) {
+                    return;
+                }
+                list.get_or_insert_with(Vec::new).push(entry.clone());
+            }
+        }
+        self.origins.push(origin);
+    }
+}
+
+impl PermissionsOptions {
+    /// Merges several option layers, lowest precedence first (for example
+    /// system, then project, then command line flags).
+    ///
+    /// - Allow lists are the union of every layer, an empty list allows the
+    ///   whole kind.
+    /// - Deny lists are the union of every layer as well. Since denials always
+    ///   take precedence over grants, no layer can undo a denial made by
+    ///   another one.
+    /// - A kind in `PermissionsLayer::locked` can't be widened by any layer
+    ///   above the locking one: their allow entries for that kind are dropped
+    ///   and reported in `rejected`. Deny entries are still accepted.
+    /// - `allow_all` is honored only if no lower layer locked a kind.
+    /// - `prompt` is enabled if any layer enables it.
+    /// - The specifier policy keeps the strictest action of every layer.
+    /// - The shortest `prompt_grant_ttl` of any layer is kept, and so is the
+    ///   lowest of each limit of the quotas. A quota limits whatever its entry
+    ///   covers, so a broader allow entry of another layer doesn't lift it.
+    pub fn merge_layers(layers: &[PermissionsLayer]) -> LayeredPermissionsOptions {
+        let mut merged = LayeredPermissionsOptions::default();
+        let mut locked = HashSet::new();
+        for layer in layers {
+            for (kind, allow, deny) in layer.options.lists() {
+                for (prefix, items) in [("allow", allow), ("deny", deny)] {
+                    let Some(items) = items else {
+                        continue;
+                    };
+                    let field = format!("{prefix}_{kind}");
+                    let entries = if items.is_empty() {
+                        vec![None]
+                    } else {
+                        items.iter().cloned().map(Some).collect()
+                    };
+                    for entry in entries {
+                        let origin = PermissionsEntryOrigin {
+                            field: field.clone(),
+                            entry,
+                            layer: layer.name.clone(),
+                        };
+                        if prefix == "allow" && locked.contains(kind) {
+                            merged.rejected.push(origin);
+                        } else {
+                            merged.insert(origin);
+                        }
+                    }
+                }
+            }
+            if layer.options.allow_all {
+                let origin = PermissionsEntryOrigin {
+                    field: "allow_all".to_string(),
+                    entry: None,
+                    layer: layer.name.clone(),
+                };
+                if !locked.is_empty() {
+                    merged.rejected.push(origin);
+                } else if !merged.options.allow_all {
+                    merged.options.allow_all = true;
+                    merged.origins.push(origin);
+                }
+            }
+            merged.options.prompt |= layer.options.prompt;
+            merged.options.specifier_policy = merged
+                .options
+                .specifier_policy
+                .strictest(&layer.options.specifier_policy);
+            merged.options.quotas = merged.options.quotas.strictest(&layer.options.quotas);
+            merged.options.prompt_grant_ttl = match (
+                merged.options.prompt_grant_ttl,
+                layer.options.prompt_grant_ttl,
+            ) {
+                (Some(merged), Some(layer)) => Some(merged.min(layer)),
+                (merged, layer) => merged.or(layer),
+            };
+            locked.extend(layer.locked.iter().map(String::as_str));
+        }
+        merged
+    }
+}
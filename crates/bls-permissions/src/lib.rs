@@ -5,31 +5,57 @@ use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 use path_utils::url_to_file_path;
 use serde::de;
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Deserializer;
 use serde::Serialize;
+use serde::Serializer;
 use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::collections::HashSet;
 use std::ffi::OsStr;
 use std::fmt;
 use std::fmt::Debug;
 use std::hash::Hash;
+use std::hash::Hasher;
 use std::net::IpAddr;
+use std::net::Ipv4Addr;
 use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::ops::RangeInclusive;
 use std::path::Component;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::Duration;
 pub use url::Url;
 #[cfg(not(target_family = "wasm"))]
 use which::which;
 
+mod clock;
+mod custom;
 mod error;
+mod glob;
+mod integrity;
+mod layering;
+mod lint;
+mod package;
 mod path_utils;
+mod policy;
+mod quota;
+mod resolver;
+mod run_args;
+mod shared;
+mod snapshot;
+mod specifier_policy;
+mod sys_kinds;
 mod terminal;
+mod unix_socket;
 use error::custom_error;
+pub use error::get_custom_error_class;
 pub use error::is_yield_error_class;
 use error::type_error;
 use error::uri_error;
@@ -38,8 +64,24 @@ use error::yield_error;
 use terminal::colors;
 
 mod prompter;
+pub use clock::*;
+pub use custom::*;
+pub use integrity::*;
+pub use layering::*;
+pub use lint::*;
+pub use package::*;
+pub use policy::*;
 use prompter::bls_permission_prompt as permission_prompt;
 pub use prompter::*;
+pub use quota::*;
+pub use resolver::*;
+use run_args::format_command_line;
+pub use run_args::*;
+pub use shared::*;
+pub use snapshot::*;
+pub use specifier_policy::*;
+pub use sys_kinds::*;
+pub use unix_socket::*;
 
 pub type AnyError = anyhow::Error;
 
@@ -56,7 +98,7 @@ const API: &'static str = "bls-runtime";
 const UAPI: &'static str = "Bls-runtime";
 
 /// Quadri-state value for storing permission state
-#[derive(Eq, PartialEq, Default, Debug, Clone, Copy, Deserialize, PartialOrd)]
+#[derive(Eq, PartialEq, Default, Debug, Clone, Copy, Serialize, Deserialize, PartialOrd)]
 pub enum PermissionState {
     Granted = 0,
     GrantedPartial = 1,
@@ -103,6 +145,10 @@ pub trait PermissionDescriptorParser: Debug + Send + Sync {
 
     fn parse_ffi_descriptor(&self, text: &str) -> Result<FfiDescriptor, AnyError>;
 
+    fn parse_unix_socket_descriptor(&self, text: &str) -> Result<UnixSocketDescriptor, AnyError> {
+        UnixSocketDescriptor::parse(text, |path| Ok(self.parse_path_query(path)?.resolved))
+    }
+
     // queries
 
     fn parse_path_query(&self, path: &str) -> Result<PathQueryDescriptor, AnyError>;
@@ -217,6 +263,11 @@ impl PermissionState {
                         Self::log_perm_access(name, info);
                         (Ok(()), true, true)
                     }
+                    PromptResponse::AllowOnce => {
+                        // allows this access only, so there is nothing to record
+                        Self::log_perm_access(name, info);
+                        (Ok(()), false, false)
+                    }
                     PromptResponse::Deny => (Err(Self::error(name, info)), true, false),
                     #[cfg(target_family = "wasm")]
                     PromptResponse::Yield => (Err(yield_error("yield.")), false, false),
@@ -230,7 +281,8 @@ impl PermissionState {
 
 /// A normalized environment variable name. On Windows this will
 /// be uppercase and on other platforms it will stay as-is.
-#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
 struct EnvVarName {
     inner: String,
 }
@@ -307,8 +359,8 @@ pub fn resolve_from_cwd(path: &Path) -> Result<PathBuf, AnyError> {
 }
 
 pub trait QueryDescriptor: Debug {
-    type AllowDesc: Debug + Eq + Clone + Hash;
-    type DenyDesc: Debug + Eq + Clone + Hash;
+    type AllowDesc: Debug + Eq + Clone + Hash + Serialize + DeserializeOwned;
+    type DenyDesc: Debug + Eq + Clone + Hash + Serialize + DeserializeOwned;
 
     fn flag_name() -> &'static str;
     fn display_name(&self) -> Cow<str>;
@@ -332,9 +384,22 @@ pub trait QueryDescriptor: Debug {
     fn revokes(&self, other: &Self::AllowDesc) -> bool;
     fn stronger_than_deny(&self, other: &Self::DenyDesc) -> bool;
     fn overlaps_deny(&self, other: &Self::DenyDesc) -> bool;
+
+    /// Same as `overlaps_deny`, for a check of the whole query descriptor,
+    /// which is denied on an overlap.
+    fn overlaps_deny_for_check(&self, other: &Self::DenyDesc) -> bool {
+        self.overlaps_deny(other)
+    }
+
+    /// Gets if only a part of this query descriptor is covered by the
+    /// provided allow descriptor, e.g. a port range that is partly allowed.
+    fn overlaps_allow(&self, _other: &Self::AllowDesc) -> bool {
+        false
+    }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
 pub struct UnaryPermission<TQuery: QueryDescriptor + ?Sized> {
     pub granted_global: bool,
     pub granted_list: HashSet<TQuery::AllowDesc>,
@@ -343,6 +408,43 @@ pub struct UnaryPermission<TQuery: QueryDescriptor + ?Sized> {
     pub prompt_denied_global: bool,
     pub prompt_denied_list: HashSet<TQuery::DenyDesc>,
     pub prompt: bool,
+    /// When the global grant expires, as a time since the unix epoch. It never
+    /// expires if unset.
+    #[serde(default)]
+    pub granted_global_expiry: Option<Duration>,
+    /// When the entries of `granted_list` granted with a TTL expire.
+    #[serde(
+        default,
+        serialize_with = "serialize_desc_map",
+        deserialize_with = "deserialize_desc_map"
+    )]
+    pub granted_expiry: HashMap<TQuery::AllowDesc, Duration>,
+    /// How many more times the global grant can be used, unlimited if unset.
+    /// Children granted the kind share the count.
+    #[serde(default)]
+    pub granted_global_uses: Option<Shared<u32>>,
+    /// How many more times the entries of `granted_list` granted with a count
+    /// can be used, shared with the children they were granted to.
+    #[serde(
+        default,
+        serialize_with = "serialize_desc_map",
+        deserialize_with = "deserialize_desc_map"
+    )]
+    pub granted_uses: HashMap<TQuery::AllowDesc, Shared<u32>>,
+    /// The quotas of allow entries, which limit every query the entry covers,
+    /// with what they used so far. Children share them.
+    #[serde(
+        default,
+        serialize_with = "serialize_desc_map",
+        deserialize_with = "deserialize_desc_map"
+    )]
+    pub quotas: HashMap<TQuery::AllowDesc, Shared<QuotaState>>,
+    /// How long the grants answered at a prompt last, forever if unset.
+    #[serde(default)]
+    pub prompt_grant_ttl: Option<Duration>,
+    /// The source of the current time for TTLs and quotas.
+    #[serde(skip)]
+    pub clock: SharedClock,
 }
 
 impl<TQuery: QueryDescriptor> Default for UnaryPermission<TQuery> {
@@ -355,6 +457,13 @@ impl<TQuery: QueryDescriptor> Default for UnaryPermission<TQuery> {
             prompt_denied_global: Default::default(),
             prompt_denied_list: Default::default(),
             prompt: Default::default(),
+            granted_global_expiry: Default::default(),
+            granted_expiry: Default::default(),
+            granted_global_uses: Default::default(),
+            granted_uses: Default::default(),
+            quotas: Default::default(),
+            prompt_grant_ttl: Default::default(),
+            clock: Default::default(),
         }
     }
 }
@@ -377,6 +486,22 @@ impl<TQuery: QueryDescriptor> Clone for UnaryPermission<TQuery> {
             prompt_denied_global: self.prompt_denied_global,
             prompt_denied_list: self.prompt_denied_list.clone(),
             prompt: self.prompt,
+            granted_global_expiry: self.granted_global_expiry,
+            granted_expiry: self.granted_expiry.clone(),
+            // a clone doesn't spend the budgets of this permission
+            granted_global_uses: self.granted_global_uses.as_ref().map(Shared::detach),
+            granted_uses: self
+                .granted_uses
+                .iter()
+                .map(|(desc, uses)| (desc.clone(), uses.detach()))
+                .collect(),
+            quotas: self
+                .quotas
+                .iter()
+                .map(|(desc, state)| (desc.clone(), state.detach()))
+                .collect(),
+            prompt_grant_ttl: self.prompt_grant_ttl,
+            clock: self.clock.clone(),
         }
     }
 }
@@ -391,6 +516,9 @@ impl<TQuery: QueryDescriptor> UnaryPermission<TQuery> {
 
     pub fn is_allow_all(&self) -> bool {
         self.granted_global
+            && self.granted_global_expiry.is_none()
+            && self.granted_global_uses.is_none()
+            && self.quotas.is_empty()
             && self.flag_denied_list.is_empty()
             && self.prompt_denied_list.is_empty()
     }
@@ -406,6 +534,7 @@ impl<TQuery: QueryDescriptor> UnaryPermission<TQuery> {
         assert_non_partial: bool,
         api_name: Option<&str>,
     ) -> Result<(), AnyError> {
+        self.prune_expired();
         let (result, prompted, is_allow_all) = self
             .query_desc(desc, AllowPartial::from(!assert_non_partial))
             .check2(
@@ -425,6 +554,18 @@ impl<TQuery: QueryDescriptor> UnaryPermission<TQuery> {
                 self.insert_prompt_denied(desc.map(|d| d.as_deny()));
             }
         }
+        if result.is_ok() {
+            // quotas also limit accesses granted at a prompt
+            if let Some(desc) = desc {
+                self.charge_quota(desc, None)?;
+            }
+            // a parent or child sharing the grant may have just used it up
+            if !prompted && !self.consume_use(desc) {
+                return Err(PermissionState::error(TQuery::flag_name(), || {
+                    desc.map(|d| format_display_name(d.display_name()))
+                }));
+            }
+        }
         result
     }
 
@@ -435,14 +576,14 @@ impl<TQuery: QueryDescriptor> UnaryPermission<TQuery> {
             match allow_partial {
                 AllowPartial::TreatAsGranted => PermissionState::Granted,
                 AllowPartial::TreatAsDenied => {
-                    if self.is_partial_flag_denied(desc) {
+                    if self.is_partial_flag_denied(desc, true) {
                         PermissionState::Denied
                     } else {
                         PermissionState::Granted
                     }
                 }
                 AllowPartial::TreatAsPartialGranted => {
-                    if self.is_partial_flag_denied(desc) {
+                    if self.is_partial_flag_denied(desc, false) {
                         PermissionState::GrantedPartial
                     } else {
                         PermissionState::Granted
@@ -450,21 +591,28 @@ impl<TQuery: QueryDescriptor> UnaryPermission<TQuery> {
                 }
             }
         } else if matches!(allow_partial, AllowPartial::TreatAsDenied)
-            && self.is_partial_flag_denied(desc)
+            && self.is_partial_flag_denied(desc, true)
         {
             PermissionState::Denied
+        } else if matches!(allow_partial, AllowPartial::TreatAsPartialGranted)
+            && self.is_partial_granted(desc)
+        {
+            PermissionState::GrantedPartial
         } else {
             PermissionState::Prompt
         }
     }
 
     fn request_desc(&mut self, desc: Option<&TQuery>) -> PermissionState {
+        self.prune_expired();
         let state = self.query_desc(desc, AllowPartial::TreatAsPartialGranted);
         if state == PermissionState::Granted {
             self.insert_granted(desc);
             return state;
         }
-        if state != PermissionState::Prompt {
+        // a query only partly covered by the granted list is still prompted for
+        let partly_granted = state == PermissionState::GrantedPartial && !self.is_granted(desc);
+        if state != PermissionState::Prompt && !partly_granted {
             return state;
         }
         if !self.prompt {
@@ -493,6 +641,16 @@ impl<TQuery: QueryDescriptor> UnaryPermission<TQuery> {
                 self.insert_granted(None);
                 PermissionState::Granted
             }
+            PromptResponse::AllowOnce => {
+                // the next access uses the grant up. A query that can't be
+                // granted on its own is left to prompt again on that access.
+                match desc.map(|d| d.as_allow()) {
+                    Some(Some(allow)) => self.grant_uses(Some(allow), 1),
+                    Some(None) => return PermissionState::Prompt,
+                    None => self.grant_uses(None, 1),
+                }
+                PermissionState::Granted
+            }
             #[cfg(target_family = "wasm")]
             PromptResponse::Yield => PermissionState::Yield,
         }
@@ -502,9 +660,15 @@ impl<TQuery: QueryDescriptor> UnaryPermission<TQuery> {
         match desc {
             Some(desc) => {
                 self.granted_list.retain(|v| !desc.revokes(v));
+                self.granted_expiry.retain(|v, _| !desc.revokes(v));
+                self.granted_uses.retain(|v, _| !desc.revokes(v));
             }
             None => {
                 self.granted_global = false;
+                self.granted_global_expiry = None;
+                self.granted_expiry.clear();
+                self.granted_global_uses = None;
+                self.granted_uses.clear();
                 // Revoke global is a special case where the entire granted list is
                 // cleared. It's inconsistent with the granular case where only
                 // descriptors stronger than the revoked one are purged.
@@ -517,9 +681,44 @@ impl<TQuery: QueryDescriptor> UnaryPermission<TQuery> {
     fn is_granted(&self, query: Option<&TQuery>) -> bool {
         match query {
             Some(query) => {
-                self.granted_global || self.granted_list.iter().any(|v| query.matches_allow(v))
+                self.is_granted_global()
+                    || self
+                        .granted_list
+                        .iter()
+                        .any(|v| query.matches_allow(v) && self.is_live(v))
             }
-            None => self.granted_global,
+            None => self.is_granted_global(),
+        }
+    }
+
+    fn is_granted_global(&self) -> bool {
+        self.granted_global
+            && self
+                .granted_global_uses
+                .as_ref()
+                .is_none_or(|uses| uses.get() > 0)
+            && self
+                .granted_global_expiry
+                .is_none_or(|expiry| expiry > self.clock.now())
+    }
+
+    fn is_live(&self, allow: &TQuery::AllowDesc) -> bool {
+        self.granted_uses
+            .get(allow)
+            .is_none_or(|uses| uses.get() > 0)
+            && self
+                .granted_expiry
+                .get(allow)
+                .is_none_or(|expiry| *expiry > self.clock.now())
+    }
+
+    fn is_partial_granted(&self, query: Option<&TQuery>) -> bool {
+        match query {
+            Some(query) => self
+                .granted_list
+                .iter()
+                .any(|v| query.overlaps_allow(v) && self.is_live(v)),
+            None => false,
         }
     }
 
@@ -543,10 +742,13 @@ impl<TQuery: QueryDescriptor> UnaryPermission<TQuery> {
         }
     }
 
-    fn is_partial_flag_denied(&self, query: Option<&TQuery>) -> bool {
+    fn is_partial_flag_denied(&self, query: Option<&TQuery>, for_check: bool) -> bool {
         match query {
             None => !self.flag_denied_list.is_empty(),
-            Some(query) => self.flag_denied_list.iter().any(|v| query.overlaps_deny(v)),
+            Some(query) => self.flag_denied_list.iter().any(|v| match for_check {
+                true => query.overlaps_deny_for_check(v),
+                false => query.overlaps_deny(v),
+            }),
         }
     }
 
@@ -561,10 +763,304 @@ impl<TQuery: QueryDescriptor> UnaryPermission<TQuery> {
             }
             None => None,
         };
-        Self::list_insert(desc, &mut self.granted_global, &mut self.granted_list);
+        let expiry = self.prompt_grant_ttl.map(|ttl| self.clock.now() + ttl);
+        self.insert_granted_until(desc, expiry);
+        true
+    }
+
+    /// Grants `desc`, or everything if `None`, for `ttl`. A grant that never
+    /// expires isn't shortened.
+    pub fn grant_for(&mut self, desc: Option<TQuery::AllowDesc>, ttl: Duration) {
+        self.insert_granted_until(desc, Some(self.clock.now() + ttl));
+    }
+
+    fn insert_granted_until(&mut self, desc: Option<TQuery::AllowDesc>, expiry: Option<Duration>) {
+        // keep the latest expiry, `None` being the latest of all
+        let latest = |current: Option<Duration>| match (current, expiry) {
+            (Some(current), Some(expiry)) => Some(current.max(expiry)),
+            _ => None,
+        };
+        // a count-limited grant is replaced by the new one
+        match desc {
+            Some(desc) => {
+                let limited = self.granted_uses.remove(&desc).is_some();
+                let current = match self.granted_list.contains(&desc) && !limited {
+                    true => self.granted_expiry.get(&desc).copied(),
+                    false => expiry,
+                };
+                match latest(current) {
+                    Some(expiry) => self.granted_expiry.insert(desc.clone(), expiry),
+                    None => self.granted_expiry.remove(&desc),
+                };
+                self.granted_list.insert(desc);
+            }
+            None => {
+                let limited = self.granted_global_uses.take().is_some();
+                let current = match self.granted_global && !limited {
+                    true => self.granted_global_expiry,
+                    false => expiry,
+                };
+                self.granted_global_expiry = latest(current);
+                self.granted_global = true;
+            }
+        }
+    }
+
+    /// Drops the grants whose TTL has elapsed, and the ones used up.
+    fn prune_expired(&mut self) {
+        self.drop_used_up();
+        if self.granted_expiry.is_empty() && self.granted_global_expiry.is_none() {
+            return;
+        }
+        let now = self.clock.now();
+        if self
+            .granted_global_expiry
+            .is_some_and(|expiry| expiry <= now)
+        {
+            self.granted_global = false;
+            self.granted_global_expiry = None;
+            self.granted_global_uses = None;
+        }
+        let granted_list = &mut self.granted_list;
+        let granted_uses = &mut self.granted_uses;
+        self.granted_expiry.retain(|desc, expiry| {
+            let live = *expiry > now;
+            if !live {
+                granted_list.remove(desc);
+                granted_uses.remove(desc);
+            }
+            live
+        });
+    }
+
+    /// Grants `desc`, or everything if `None`, for `uses` checks. A grant
+    /// without a count isn't limited, and the counts of a count-limited one
+    /// add up.
+    pub fn grant_uses(&mut self, desc: Option<TQuery::AllowDesc>, uses: u32) {
+        self.prune_expired();
+        if uses == 0 {
+            return;
+        }
+        match desc {
+            Some(desc) => {
+                if self.granted_list.contains(&desc) && !self.granted_uses.contains_key(&desc) {
+                    return;
+                }
+                self.granted_uses
+                    .entry(desc.clone())
+                    .or_insert_with(|| Shared::new(0))
+                    .update(|current| *current = current.saturating_add(uses));
+                self.granted_list.insert(desc);
+            }
+            None => {
+                if self.granted_global && self.granted_global_uses.is_none() {
+                    return;
+                }
+                self.granted_global_uses
+                    .get_or_insert_with(|| Shared::new(0))
+                    .update(|current| *current = current.saturating_add(uses));
+                self.granted_global = true;
+            }
+        }
+    }
+
+    /// How many more times `query` can be checked, if it is only granted by
+    /// count-limited grants. `None` if a grant without a count allows it, or
+    /// if it isn't granted at all.
+    pub fn remaining_uses(&self, query: Option<&TQuery>) -> Option<u32> {
+        if !self.is_granted(query) {
+            return None;
+        }
+        let mut remaining = 0u32;
+        if self.is_granted_global() {
+            remaining = self.granted_global_uses.as_ref()?.get();
+        }
+        if let Some(query) = query {
+            for allow in self.granted_list.iter() {
+                if query.matches_allow(allow) && self.is_live(allow) {
+                    remaining = remaining.saturating_add(self.granted_uses.get(allow)?.get());
+                }
+            }
+        }
+        Some(remaining)
+    }
+
+    /// Runs a check without using up count-limited grants, e.g. to make sure
+    /// a child isn't granted more than its parent. Grants made at a prompt
+    /// meanwhile are kept.
+    fn without_using_grants<R>(&mut self, check: impl FnOnce(&mut Self) -> R) -> R {
+        let global_uses = self
+            .granted_global_uses
+            .clone()
+            .map(|uses| (uses.get(), uses));
+        let uses = self
+            .granted_uses
+            .iter()
+            .map(|(desc, uses)| (desc.clone(), uses.get(), uses.clone()))
+            .collect::<Vec<_>>();
+        let quotas = self
+            .quotas
+            .values()
+            .map(|state| (state.get(), state.clone()))
+            .collect::<Vec<_>>();
+        let result = check(self);
+        for (used, state) in quotas {
+            state.set(used);
+        }
+        if let Some((left, global_uses)) = global_uses {
+            if !self.granted_global || self.granted_global_uses.is_some() {
+                global_uses.set(left);
+                self.granted_global = true;
+                self.granted_global_uses = Some(global_uses);
+            }
+        }
+        for (desc, left, uses) in uses {
+            if !self.granted_list.contains(&desc) || self.granted_uses.contains_key(&desc) {
+                uses.set(left);
+                self.granted_list.insert(desc.clone());
+                self.granted_uses.insert(desc, uses);
+            }
+        }
+        result
+    }
+
+    /// Attaches a quota to `allow`. It limits every query `allow` covers,
+    /// whichever grant allows the query, and outlives the grants. Returns
+    /// `false` if `allow` isn't granted.
+    pub fn set_quota(&mut self, allow: TQuery::AllowDesc, quota: Quota) -> bool {
+        if !self.is_granted(Some(&TQuery::from_allow(&allow))) {
+            return false;
+        }
+        self.quotas
+            .insert(allow, Shared::new(QuotaState::new(quota)));
         true
     }
 
+    /// The quotas limiting `query`.
+    pub fn quotas_for(&self, query: &TQuery) -> Vec<QuotaState> {
+        self.limiting_quotas(query)
+            .iter()
+            .map(|allow| self.quotas[allow].get())
+            .collect()
+    }
+
+    /// Counts `bytes` reported by the host for an operation on `query` against
+    /// the quotas limiting it.
+    pub fn report_bytes(&mut self, query: &TQuery, bytes: u64) -> Result<(), AnyError> {
+        self.charge_quota(query, Some(bytes))
+    }
+
+    fn limiting_quotas(&self, query: &TQuery) -> Vec<TQuery::AllowDesc> {
+        self.quotas
+            .keys()
+            .filter(|allow| query.matches_allow(allow))
+            .cloned()
+            .collect()
+    }
+
+    /// Charges a check, or `bytes` if set, to the quotas limiting `query`.
+    fn charge_quota(&mut self, query: &TQuery, bytes: Option<u64>) -> Result<(), AnyError> {
+        let allows = self.limiting_quotas(query);
+        if allows.is_empty() {
+            return Ok(());
+        }
+        let now = self.clock.now();
+        for allow in &allows {
+            let state = self.quotas[allow].get();
+            let exceeded = match bytes {
+                Some(bytes) => state.bytes_exceeded(bytes),
+                None => state.check_exceeded(now),
+            };
+            if let Some(reason) = exceeded {
+                return Err(quota_exceeded_error(
+                    TQuery::flag_name(),
+                    &format_display_name(query.display_name()),
+                    &reason,
+                ));
+            }
+        }
+        for allow in &allows {
+            self.quotas[allow].update(|state| match bytes {
+                Some(bytes) => state.record_bytes(bytes),
+                None => state.record_check(now),
+            });
+        }
+        Ok(())
+    }
+
+    /// The count-limited grant a check of `query` uses up, if no grant
+    /// without a count allows it.
+    fn limited_uses(&self, query: Option<&TQuery>) -> Option<Shared<u32>> {
+        self.remaining_uses(query)?;
+        let limited = query.and_then(|query| {
+            self.granted_list
+                .iter()
+                .find(|allow| query.matches_allow(allow) && self.is_live(allow))
+        });
+        match limited {
+            Some(allow) => self.granted_uses.get(allow).cloned(),
+            None => self.granted_global_uses.clone(),
+        }
+    }
+
+    /// Uses up one use of the count-limited grant allowing `query`, unless a
+    /// grant without a count allows it as well. Returns `false` if the grant
+    /// had no uses left.
+    fn consume_use(&mut self, query: Option<&TQuery>) -> bool {
+        if self.granted_uses.is_empty() && self.granted_global_uses.is_none() {
+            return true;
+        }
+        let Some(uses) = self.limited_uses(query) else {
+            return true;
+        };
+        let used = uses.update(|uses| match uses.checked_sub(1) {
+            Some(left) => {
+                *uses = left;
+                true
+            }
+            None => false,
+        });
+        self.drop_used_up();
+        used
+    }
+
+    /// Drops the count-limited grants with no uses left, which a parent or
+    /// child sharing them may have used up.
+    pub(crate) fn drop_used_up(&mut self) {
+        if self
+            .granted_global_uses
+            .as_ref()
+            .is_some_and(|uses| uses.get() == 0)
+        {
+            self.granted_global = false;
+            self.granted_global_uses = None;
+            self.granted_global_expiry = None;
+        }
+        let used_up = self
+            .granted_uses
+            .iter()
+            .filter(|(_, uses)| uses.get() == 0)
+            .map(|(desc, _)| desc.clone())
+            .collect::<Vec<_>>();
+        for desc in used_up {
+            self.granted_uses.remove(&desc);
+            self.granted_expiry.remove(&desc);
+            self.granted_list.remove(&desc);
+        }
+    }
+
+    /// The time until which `query` stays granted, `None` if it never expires.
+    fn granted_until(&self, query: &TQuery) -> Option<Duration> {
+        let mut until = Duration::ZERO;
+        if self.granted_global {
+            until = self.granted_global_expiry?;
+        }
+        for allow in self.granted_list.iter().filter(|v| query.matches_allow(v)) {
+            until = until.max(*self.granted_expiry.get(allow)?);
+        }
+        Some(until)
+    }
+
     fn insert_prompt_denied(&mut self, desc: Option<TQuery::DenyDesc>) {
         Self::list_insert(
             desc,
@@ -587,17 +1083,34 @@ impl<TQuery: QueryDescriptor> UnaryPermission<TQuery> {
         flag: ChildUnaryPermissionArg,
         parse: impl Fn(&str) -> Result<Option<TQuery::AllowDesc>, AnyError>,
     ) -> Result<UnaryPermission<TQuery>, AnyError> {
-        let mut perms = Self::default();
+        let mut perms = Self {
+            clock: self.clock.clone(),
+            ..Default::default()
+        };
 
         match flag {
             ChildUnaryPermissionArg::Inherit => {
                 perms.clone_from(self);
+                // the child spends the parent's count-limited grants and quotas
+                perms
+                    .granted_global_uses
+                    .clone_from(&self.granted_global_uses);
+                perms.granted_uses.clone_from(&self.granted_uses);
+                perms.quotas.clone_from(&self.quotas);
             }
             ChildUnaryPermissionArg::Granted => {
-                if self.check_all_api(None).is_err() {
+                if self
+                    .without_using_grants(|perm| perm.check_all_api(None))
+                    .is_err()
+                {
                     return Err(escalation_error());
                 }
                 perms.granted_global = true;
+                perms.granted_global_expiry = self.granted_global_expiry;
+                perms
+                    .granted_global_uses
+                    .clone_from(&self.granted_global_uses);
+                perms.quotas.clone_from(&self.quotas);
             }
             ChildUnaryPermissionArg::NotGranted => {}
             ChildUnaryPermissionArg::GrantedList(granted_list) => {
@@ -606,17 +1119,39 @@ impl<TQuery: QueryDescriptor> UnaryPermission<TQuery> {
                     .filter_map(|i| parse(i).transpose())
                     .collect::<Result<_, _>>()?;
                 if !perms.granted_list.iter().all(|desc| {
-                    TQuery::from_allow(desc)
-                        .check_in_permission(self, None)
-                        .is_ok()
+                    self.without_using_grants(|perm| {
+                        TQuery::from_allow(desc).check_in_permission(perm, None)
+                    })
+                    .is_ok()
                 }) {
                     return Err(escalation_error());
                 }
+                // the child's grants expire with the parent's
+                perms.granted_expiry = perms
+                    .granted_list
+                    .iter()
+                    .filter_map(|desc| {
+                        let until = self.granted_until(&TQuery::from_allow(desc))?;
+                        Some((desc.clone(), until))
+                    })
+                    .collect();
+                // and spend the parent's count-limited grants
+                perms.granted_uses = perms
+                    .granted_list
+                    .iter()
+                    .filter_map(|desc| {
+                        let uses = self.limited_uses(Some(&TQuery::from_allow(desc)))?;
+                        Some((desc.clone(), uses))
+                    })
+                    .collect();
+                // and spend the parent's quotas
+                perms.quotas.clone_from(&self.quotas);
             }
         }
         perms.flag_denied_global = self.flag_denied_global;
         perms.prompt_denied_global = self.prompt_denied_global;
         perms.prompt = self.prompt;
+        perms.prompt_grant_ttl = self.prompt_grant_ttl;
         perms.flag_denied_list.clone_from(&self.flag_denied_list);
         perms
             .prompt_denied_list
@@ -626,7 +1161,7 @@ impl<TQuery: QueryDescriptor> UnaryPermission<TQuery> {
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum ChildUnaryPermissionArg {
     Inherit,
     Granted,
@@ -751,11 +1286,11 @@ impl QueryDescriptor for ReadQueryDescriptor {
     }
 
     fn matches_allow(&self, other: &Self::AllowDesc) -> bool {
-        self.0.resolved.starts_with(&other.0)
+        glob::path_matches(&other.0, &self.0.resolved)
     }
 
     fn matches_deny(&self, other: &Self::DenyDesc) -> bool {
-        self.0.resolved.starts_with(&other.0)
+        glob::path_matches(&other.0, &self.0.resolved)
     }
 
     fn revokes(&self, other: &Self::AllowDesc) -> bool {
@@ -763,15 +1298,19 @@ impl QueryDescriptor for ReadQueryDescriptor {
     }
 
     fn stronger_than_deny(&self, other: &Self::DenyDesc) -> bool {
-        other.0.starts_with(&self.0.resolved)
+        glob::path_contains_match(&self.0.resolved, &other.0)
     }
 
     fn overlaps_deny(&self, other: &Self::DenyDesc) -> bool {
         self.stronger_than_deny(other)
     }
+
+    fn overlaps_deny_for_check(&self, other: &Self::DenyDesc) -> bool {
+        glob::path_contains_fixed_match(&self.0.resolved, &other.0)
+    }
 }
 
-#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
 pub struct ReadDescriptor(pub PathBuf);
 
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
@@ -814,11 +1353,11 @@ impl QueryDescriptor for WriteQueryDescriptor {
     }
 
     fn matches_allow(&self, other: &Self::AllowDesc) -> bool {
-        self.0.resolved.starts_with(&other.0)
+        glob::path_matches(&other.0, &self.0.resolved)
     }
 
     fn matches_deny(&self, other: &Self::DenyDesc) -> bool {
-        self.0.resolved.starts_with(&other.0)
+        glob::path_matches(&other.0, &self.0.resolved)
     }
 
     fn revokes(&self, other: &Self::AllowDesc) -> bool {
@@ -826,26 +1365,69 @@ impl QueryDescriptor for WriteQueryDescriptor {
     }
 
     fn stronger_than_deny(&self, other: &Self::DenyDesc) -> bool {
-        other.0.starts_with(&self.0.resolved)
+        glob::path_contains_match(&self.0.resolved, &other.0)
     }
 
     fn overlaps_deny(&self, other: &Self::DenyDesc) -> bool {
         self.stronger_than_deny(other)
     }
+
+    fn overlaps_deny_for_check(&self, other: &Self::DenyDesc) -> bool {
+        glob::path_contains_fixed_match(&self.0.resolved, &other.0)
+    }
 }
 
-#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
 pub struct WriteDescriptor(pub PathBuf);
 
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
 pub enum Host {
     Fqdn(FQDN),
     Ip(IpAddr),
+    /// `*.example.com`, matches every subdomain of the domain but not the
+    /// domain itself.
+    Wildcard(FQDN),
+    /// `10.0.0.0/8`, the network address and the prefix length. IPv4-mapped
+    /// IPv6 networks are stored in their IPv4 form.
+    Cidr(IpAddr, u8),
 }
 
 impl Host {
     // TODO(bartlomieju): rewrite to not use `AnyError` but a specific error implementations
     pub fn parse(s: &str) -> Result<Self, AnyError> {
+        if let Some(domain) = s.strip_prefix("*.") {
+            return match Host::parse(domain) {
+                Ok(Host::Fqdn(fqdn)) => Ok(Host::Wildcard(fqdn)),
+                _ => Err(uri_error(format!("invalid wildcard host: '{s}'"))),
+            };
+        }
+        if s.contains('*') {
+            return Err(uri_error(format!(
+                "invalid wildcard host: '{s}', only a leading '*.' is supported"
+            )));
+        }
+        if let Some((ip, prefix_len)) = s.split_once('/') {
+            let ip = ip
+                .strip_prefix('[')
+                .and_then(|ip| ip.strip_suffix(']'))
+                .unwrap_or(ip);
+            let ip = ip
+                .parse::<IpAddr>()
+                .map_err(|_| uri_error(format!("invalid CIDR address: '{s}'")))?;
+            let max_len = if ip.is_ipv4() { 32 } else { 128 };
+            let prefix_len = prefix_len
+                .parse::<u8>()
+                .ok()
+                .filter(|len| *len <= max_len)
+                .ok_or_else(|| uri_error(format!("invalid CIDR prefix length: '{s}'")))?;
+            return Ok(match ip {
+                IpAddr::V6(v6) if prefix_len >= 96 && v6.to_ipv4_mapped().is_some() => {
+                    let v4 = IpAddr::V4(v6.to_ipv4_mapped().unwrap());
+                    Host::Cidr(mask_ip(v4, prefix_len - 96), prefix_len - 96)
+                }
+                ip => Host::Cidr(mask_ip(ip, prefix_len), prefix_len),
+            });
+        }
         if s.starts_with('[') && s.ends_with(']') {
             let ip = s[1..s.len() - 1]
                 .parse::<Ipv6Addr>()
@@ -880,10 +1462,86 @@ impl Host {
     pub fn must_parse(s: &str) -> Self {
         Self::parse(s).unwrap()
     }
+
+    /// Gets if every host matched by `other` is also matched by this one.
+    pub fn covers(&self, other: &Host) -> bool {
+        match (self, other) {
+            (Host::Wildcard(domain), Host::Fqdn(name)) => is_subdomain(name, domain),
+            (Host::Wildcard(domain), Host::Wildcard(other)) => {
+                domain == other || is_subdomain(other, domain)
+            }
+            (Host::Ip(ip), Host::Ip(other)) => canonical_ip(*ip) == canonical_ip(*other),
+            (Host::Cidr(net, len), Host::Ip(ip)) => cidr_contains(*net, *len, *ip),
+            (Host::Ip(ip), Host::Cidr(net, len)) => {
+                *len == if net.is_ipv4() { 32 } else { 128 } && canonical_ip(*ip) == *net
+            }
+            (Host::Cidr(net, len), Host::Cidr(other, other_len)) => {
+                other_len >= len && cidr_contains(*net, *len, *other)
+            }
+            _ => self == other,
+        }
+    }
+
+    /// Gets if `other` matches some of the hosts below this one, e.g.
+    /// `api.example.com` or `*.example.com` for `example.com`, or
+    /// `10.0.0.1` for `10.0.0.0/8`.
+    fn contains_part_of(&self, other: &Host) -> bool {
+        match (self, other) {
+            (Host::Fqdn(domain) | Host::Wildcard(domain), Host::Fqdn(name)) => {
+                is_subdomain(name, domain)
+            }
+            (Host::Fqdn(domain) | Host::Wildcard(domain), Host::Wildcard(other)) => {
+                other == domain || is_subdomain(other, domain)
+            }
+            (Host::Cidr(..), Host::Ip(_) | Host::Cidr(..)) => {
+                self.covers(other) && !other.covers(self)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// IPv4-mapped IPv6 addresses are compared in their IPv4 form, so that
+/// `[::ffff:10.0.0.1]` can't be used to get around a deny for `10.0.0.1`.
+fn canonical_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        ip => ip,
+    }
+}
+
+fn mask_ip(ip: IpAddr, prefix_len: u8) -> IpAddr {
+    match ip {
+        IpAddr::V4(v4) => {
+            let mask = u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0);
+            IpAddr::V4(Ipv4Addr::from(u32::from(v4) & mask))
+        }
+        IpAddr::V6(v6) => {
+            let mask = u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0);
+            IpAddr::V6(Ipv6Addr::from(u128::from(v6) & mask))
+        }
+    }
 }
 
+fn cidr_contains(net: IpAddr, prefix_len: u8, ip: IpAddr) -> bool {
+    let ip = canonical_ip(ip);
+    net.is_ipv4() == ip.is_ipv4() && mask_ip(ip, prefix_len) == net
+}
+
+/// Gets if `name` is a strict subdomain of `domain`.
+fn is_subdomain(name: &FQDN, domain: &FQDN) -> bool {
+    let name = name.to_string();
+    let domain = domain.to_string();
+    let name = name.strip_suffix('.').unwrap_or(&name);
+    let domain = domain.strip_suffix('.').unwrap_or(&domain);
+    name.strip_suffix(domain)
+        .is_some_and(|prefix| prefix.len() > 1 && prefix.ends_with('.'))
+}
+
+/// A host, its ports and the scheme used to reach it, e.g.
+/// `https://deno.land:443`. A missing port or scheme stands for any of them.
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
-pub struct NetDescriptor(pub Host, pub Option<u16>);
+pub struct NetDescriptor(pub Host, pub Option<NetPort>, pub Option<String>);
 
 impl QueryDescriptor for NetDescriptor {
     type AllowDesc = NetDescriptor;
@@ -919,43 +1577,237 @@ impl QueryDescriptor for NetDescriptor {
     }
 
     fn matches_allow(&self, other: &Self::AllowDesc) -> bool {
-        self.0 == other.0 && (other.1.is_none() || self.1 == other.1)
+        other.covers(self)
     }
 
     fn matches_deny(&self, other: &Self::DenyDesc) -> bool {
-        self.0 == other.0 && (other.1.is_none() || self.1 == other.1)
+        // a connection without a scheme could use the denied one, so a deny
+        // scoped to a scheme applies to it too
+        other.0.covers(&self.0)
+            && NetPort::option_covers(&other.1, &self.1)
+            && scheme_overlaps(&other.2, &self.2)
     }
 
     fn revokes(&self, other: &Self::AllowDesc) -> bool {
-        self.matches_allow(other)
+        // also drop grants for some of the revoked ports, e.g. `:8050` when
+        // revoking `:8000-8100`
+        other.0.covers(&self.0)
+            && NetPort::option_overlaps(&self.1, &other.1)
+            && scheme_overlaps(&self.2, &other.2)
     }
 
     fn stronger_than_deny(&self, other: &Self::DenyDesc) -> bool {
         self.matches_deny(other)
     }
 
-    fn overlaps_deny(&self, _other: &Self::DenyDesc) -> bool {
-        false
+    fn overlaps_deny(&self, other: &Self::DenyDesc) -> bool {
+        if self.matches_deny(other) {
+            return false;
+        }
+        // either a subdomain is denied, or only some ports of the host are
+        NetPort::option_overlaps(&self.1, &other.1)
+            && scheme_overlaps(&self.2, &other.2)
+            && (self.0.contains_part_of(&other.0)
+                || (self.0.covers(&other.0) && !NetPort::option_covers(&other.1, &self.1)))
+    }
+
+    fn overlaps_allow(&self, other: &Self::AllowDesc) -> bool {
+        // a query without a port asks for every port, so it stays a prompt
+        // unless all of them are allowed
+        match (&self.1, &other.1) {
+            (Some(ports), Some(allowed)) => {
+                other.0.covers(&self.0)
+                    && scheme_covers(&other.2, &self.2)
+                    && ports.overlaps(allowed)
+                    && !allowed.covers(ports)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The ports of a `NetDescriptor`: a single port (`80`), a range
+/// (`8000-8100`) or a list of both (`80,443,8000-8100`).
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct NetPort(Vec<RangeInclusive<u16>>);
+
+impl NetPort {
+    pub fn new(ranges: impl IntoIterator<Item = RangeInclusive<u16>>) -> Self {
+        let mut ranges = ranges
+            .into_iter()
+            .filter(|range| !range.is_empty())
+            .collect::<Vec<_>>();
+        ranges.sort_by_key(|range| *range.start());
+        // merge overlapping and adjacent ranges, so that equal sets of ports
+        // compare and hash equal
+        let mut merged: Vec<RangeInclusive<u16>> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if *range.start() as u32 <= *last.end() as u32 + 1 => {
+                    *last = *last.start()..=*last.end().max(range.end());
+                }
+                _ => merged.push(range),
+            }
+        }
+        Self(merged)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, AnyError> {
+        let parse_port = |port: &str| {
+            port.parse::<u16>()
+                .map_err(|_| uri_error(format!("invalid port: '{port}'")))
+        };
+        let mut ranges = Vec::new();
+        for part in text.split(',') {
+            let range = match part.split_once('-') {
+                Some((start, end)) => {
+                    let (start, end) = (parse_port(start)?, parse_port(end)?);
+                    if start > end {
+                        return Err(uri_error(format!("invalid port range: '{part}'")));
+                    }
+                    start..=end
+                }
+                None => {
+                    let port = parse_port(part)?;
+                    port..=port
+                }
+            };
+            ranges.push(range);
+        }
+        Ok(Self::new(ranges))
+    }
+
+    pub fn ranges(&self) -> &[RangeInclusive<u16>] {
+        &self.0
+    }
+
+    pub fn contains(&self, port: u16) -> bool {
+        self.0.iter().any(|range| range.contains(&port))
+    }
+
+    /// Gets if every port of `other` is one of these ports.
+    pub fn covers(&self, other: &NetPort) -> bool {
+        other.0.iter().all(|other| {
+            self.0
+                .iter()
+                .any(|range| range.start() <= other.start() && other.end() <= range.end())
+        })
+    }
+
+    /// Gets if `other` has at least one of these ports.
+    pub fn overlaps(&self, other: &NetPort) -> bool {
+        self.0.iter().any(|range| {
+            other
+                .0
+                .iter()
+                .any(|other| range.start() <= other.end() && other.start() <= range.end())
+        })
+    }
+
+    /// Same as `covers`, where `None` stands for every port.
+    fn option_covers(ports: &Option<NetPort>, other: &Option<NetPort>) -> bool {
+        match (ports, other) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(ports), Some(other)) => ports.covers(other),
+        }
+    }
+
+    /// Same as `overlaps`, where `None` stands for every port.
+    fn option_overlaps(ports: &Option<NetPort>, other: &Option<NetPort>) -> bool {
+        match (ports, other) {
+            (Some(ports), Some(other)) => ports.overlaps(other),
+            _ => true,
+        }
+    }
+}
+
+impl From<u16> for NetPort {
+    fn from(port: u16) -> Self {
+        Self(vec![port..=port])
+    }
+}
+
+impl fmt::Display for NetPort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, range) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            if range.start() == range.end() {
+                write!(f, "{}", range.start())?;
+            } else {
+                write!(f, "{}-{}", range.start(), range.end())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn scheme_covers(outer: &Option<String>, inner: &Option<String>) -> bool {
+    match outer {
+        Some(outer) => inner.as_ref() == Some(outer),
+        None => true,
+    }
+}
+
+fn scheme_overlaps(a: &Option<String>, b: &Option<String>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a == b,
+        _ => true,
     }
 }
 
 // TODO(bartlomieju): rewrite to not use `AnyError` but a specific error implementations
 impl NetDescriptor {
-    pub fn parse(hostname: &str) -> Result<Self, AnyError> {
-        if hostname.starts_with("http://") || hostname.starts_with("https://") {
-            return Err(uri_error(format!(
-                "invalid value '{hostname}': URLs are not supported, only domains and ips"
-            )));
+    /// Parses `host[:ports]`, optionally prefixed by a scheme, e.g.
+    /// `https://deno.land` or `wss://[::1]:8000`. URLs with a path aren't
+    /// supported.
+    pub fn parse(text: &str) -> Result<Self, AnyError> {
+        let Some((scheme, rest)) = text.split_once("://") else {
+            return Self::parse_host_and_port(text);
+        };
+        let valid_scheme = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+        if !valid_scheme {
+            return Err(uri_error(format!("invalid scheme in '{text}': '{scheme}'")));
+        }
+        let rest = rest.strip_suffix('/').unwrap_or(rest);
+        let NetDescriptor(host, port, _) = Self::parse_host_and_port(rest).map_err(|err| {
+            if rest.contains('/') {
+                uri_error(format!(
+                    "invalid value '{text}': paths are not supported, only schemes, domains and ips"
+                ))
+            } else {
+                err
+            }
+        })?;
+        Ok(NetDescriptor(host, port, Some(scheme.to_ascii_lowercase())))
+    }
+
+    fn parse_host_and_port(hostname: &str) -> Result<Self, AnyError> {
+        // A bare IPv6 network can't have a port, those need square brackets.
+        if hostname.contains('/') && !hostname.starts_with('[') && hostname.contains("::") {
+            return Ok(NetDescriptor(Host::parse(hostname)?, None, None));
         }
 
         // If this is a IPv6 address enclosed in square brackets, parse it as such.
         if hostname.starts_with('[') {
             if let Some((ip, after)) = hostname.split_once(']') {
-                let ip = ip[1..].parse::<Ipv6Addr>().map_err(|_| {
-                    uri_error(format!("invalid IPv6 address in '{hostname}': '{ip}'"))
-                })?;
+                let host = if ip.contains('/') {
+                    match Host::parse(&ip[1..])? {
+                        host @ Host::Cidr(IpAddr::V6(_), _) => host,
+                        _ => return Err(uri_error(format!("invalid IPv6 network: '{hostname}'"))),
+                    }
+                } else {
+                    Host::Ip(IpAddr::V6(ip[1..].parse::<Ipv6Addr>().map_err(|_| {
+                        uri_error(format!("invalid IPv6 address in '{hostname}': '{ip}'"))
+                    })?))
+                };
                 let port = if let Some(port) = after.strip_prefix(':') {
-                    let port = port.parse::<u16>().map_err(|_| {
+                    let port = NetPort::parse(port).map_err(|_| {
                         uri_error(format!("invalid port in '{hostname}': '{port}'"))
                     })?;
                     Some(port)
@@ -964,7 +1816,7 @@ impl NetDescriptor {
                 } else {
                     return Err(uri_error(format!("invalid host: '{hostname}'")));
                 };
-                return Ok(NetDescriptor(Host::Ip(IpAddr::V6(ip)), port));
+                return Ok(NetDescriptor(host, port, None));
             } else {
                 return Err(uri_error(format!("invalid host: '{hostname}'")));
             }
@@ -983,7 +1835,7 @@ impl NetDescriptor {
         let port = if port.is_empty() {
             None
         } else {
-            let port = port.parse::<u16>().map_err(|_| {
+            let port = NetPort::parse(port).map_err(|_| {
                 // If the user forgot to enclose an IPv6 address in square brackets, we
                 // should give them a hint. There are always at least two colons in an
                 // IPv6 address, so this heuristic finds likely a bare IPv6 address.
@@ -998,7 +1850,7 @@ impl NetDescriptor {
             Some(port)
         };
 
-        Ok(NetDescriptor(host, port))
+        Ok(NetDescriptor(host, port, None))
     }
 
     pub fn from_url(url: &Url) -> Result<Self, AnyError> {
@@ -1006,27 +1858,68 @@ impl NetDescriptor {
             .host_str()
             .ok_or_else(|| type_error(format!("Missing host in url: '{}'", url)))?;
         let host = Host::parse(host)?;
-        let port = url.port_or_known_default();
-        Ok(NetDescriptor(host, port))
+        let port = url.port_or_known_default().map(NetPort::from);
+        Ok(NetDescriptor(host, port, Some(url.scheme().to_string())))
+    }
+
+    fn covers(&self, other: &NetDescriptor) -> bool {
+        self.0.covers(&other.0)
+            && NetPort::option_covers(&self.1, &other.1)
+            && scheme_covers(&self.2, &other.2)
     }
 }
 
 impl fmt::Display for NetDescriptor {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(scheme) = &self.2 {
+            write!(f, "{scheme}://")?;
+        }
         match &self.0 {
             Host::Fqdn(fqdn) => write!(f, "{fqdn}"),
+            Host::Wildcard(fqdn) => write!(f, "*.{fqdn}"),
             Host::Ip(IpAddr::V4(ip)) => write!(f, "{ip}"),
             Host::Ip(IpAddr::V6(ip)) => write!(f, "[{ip}]"),
+            Host::Cidr(IpAddr::V4(ip), prefix_len) => write!(f, "{ip}/{prefix_len}"),
+            Host::Cidr(IpAddr::V6(ip), prefix_len) => write!(f, "[{ip}/{prefix_len}]"),
         }?;
-        if let Some(port) = self.1 {
+        if let Some(port) = &self.1 {
             write!(f, ":{}", port)?;
         }
         Ok(())
     }
 }
 
+// Net descriptors are stored in their textual form, the same one accepted by
+// `--allow-net`, so that serialized permissions stay readable.
+impl Serialize for NetDescriptor {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for NetDescriptor {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let text = String::deserialize(deserializer)?;
+        NetDescriptor::parse(&text).map_err(de::Error::custom)
+    }
+}
+
+/// What may be imported: either a module host, optionally scoped to a URL
+/// path prefix, e.g. `https://jsr.io/@std/`, or a `npm:`/`jsr:` package.
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
-pub struct ImportDescriptor(NetDescriptor);
+pub enum ImportDescriptor {
+    /// Queries made for a module carry its full path. Allowed modules may be
+    /// pinned to the digest of their content, e.g.
+    /// `https://deno.land/x/mod.ts@sha256:<hex>`.
+    Url(NetDescriptor, Option<String>, Option<Sha256Digest>),
+    Package(PackageDescriptor),
+}
 
 impl QueryDescriptor for ImportDescriptor {
     type AllowDesc = ImportDescriptor;
@@ -1037,19 +1930,40 @@ impl QueryDescriptor for ImportDescriptor {
     }
 
     fn display_name(&self) -> Cow<str> {
-        self.0.display_name()
+        match self {
+            // show the module url the way it was likely written, without the
+            // default port
+            ImportDescriptor::Url(net, Some(path), _) => {
+                match Url::parse(&format!("{net}{path}")) {
+                    Ok(url) => Cow::from(url.to_string()),
+                    Err(_) => Cow::from(format!("{net}{path}")),
+                }
+            }
+            ImportDescriptor::Url(net, None, _) => net.display_name(),
+            ImportDescriptor::Package(package) => Cow::from(package.to_string()),
+        }
     }
 
     fn from_allow(allow: &Self::AllowDesc) -> Self {
-        Self(NetDescriptor::from_allow(&allow.0))
+        match allow {
+            ImportDescriptor::Url(net, path, _) => {
+                ImportDescriptor::Url(NetDescriptor::from_allow(net), path.clone(), None)
+            }
+            ImportDescriptor::Package(package) => ImportDescriptor::Package(package.clone()),
+        }
     }
 
     fn as_allow(&self) -> Option<Self::AllowDesc> {
-        self.0.as_allow().map(ImportDescriptor)
+        match self {
+            ImportDescriptor::Url(net, path, _) => net
+                .as_allow()
+                .map(|net| ImportDescriptor::Url(net, path.clone(), None)),
+            ImportDescriptor::Package(package) => Some(ImportDescriptor::Package(package.clone())),
+        }
     }
 
     fn as_deny(&self) -> Self::DenyDesc {
-        Self(self.0.as_deny())
+        Self::from_allow(self)
     }
 
     fn check_in_permission(
@@ -1062,43 +1976,287 @@ impl QueryDescriptor for ImportDescriptor {
     }
 
     fn matches_allow(&self, other: &Self::AllowDesc) -> bool {
-        self.0.matches_allow(&other.0)
+        match (self, other) {
+            (
+                ImportDescriptor::Url(net, path, _),
+                ImportDescriptor::Url(other_net, other_path, _),
+            ) => net.matches_allow(other_net) && url_path_covers(other_path, path),
+            _ => self.package_covered_by(other),
+        }
     }
 
     fn matches_deny(&self, other: &Self::DenyDesc) -> bool {
-        self.0.matches_deny(&other.0)
+        match (self, other) {
+            (
+                ImportDescriptor::Url(net, path, _),
+                ImportDescriptor::Url(other_net, other_path, _),
+            ) => net.matches_deny(other_net) && url_path_covers(other_path, path),
+            _ => self.package_covered_by(other),
+        }
     }
 
     fn revokes(&self, other: &Self::AllowDesc) -> bool {
-        self.0.revokes(&other.0)
+        match (self, other) {
+            (
+                ImportDescriptor::Url(net, path, _),
+                ImportDescriptor::Url(other_net, other_path, _),
+            ) => net.revokes(other_net) && url_paths_overlap(path, other_path),
+            _ => self.package_covered_by(other) || other.package_covered_by(self),
+        }
     }
 
     fn stronger_than_deny(&self, other: &Self::DenyDesc) -> bool {
-        self.0.stronger_than_deny(&other.0)
+        match (self, other) {
+            (
+                ImportDescriptor::Url(net, path, _),
+                ImportDescriptor::Url(other_net, other_path, _),
+            ) => net.stronger_than_deny(other_net) && url_path_covers(other_path, path),
+            _ => self.package_covered_by(other),
+        }
     }
 
     fn overlaps_deny(&self, other: &Self::DenyDesc) -> bool {
-        self.0.overlaps_deny(&other.0)
+        if self.matches_deny(other) {
+            return false;
+        }
+        match (self, other) {
+            (
+                ImportDescriptor::Url(net, path, _),
+                ImportDescriptor::Url(other_net, other_path, _),
+            ) => {
+                // either the host is partly denied, or only some paths of it are
+                url_paths_overlap(path, other_path)
+                    && (net.matches_deny(other_net) || net.overlaps_deny(other_net))
+            }
+            // some packages of a queried scope are denied
+            _ => other.package_covered_by(self),
+        }
+    }
+
+    fn overlaps_allow(&self, other: &Self::AllowDesc) -> bool {
+        match (self, other) {
+            (
+                ImportDescriptor::Url(net, path, _),
+                ImportDescriptor::Url(other_net, other_path, _),
+            ) => net.overlaps_allow(other_net) && url_path_covers(other_path, path),
+            _ => false,
+        }
+    }
+}
+
+/// Gets if the path prefix `outer` covers `inner`. Prefixes match whole
+/// segments, so `/@std` covers `/@std/path` but not `/@stdlib`.
+fn url_path_covers(outer: &Option<String>, inner: &Option<String>) -> bool {
+    match (outer, inner) {
+        (None, _) => true,
+        (Some(_), None) => false,
+        (Some(outer), Some(inner)) => match inner.strip_prefix(outer.as_str()) {
+            Some(rest) => rest.is_empty() || outer.ends_with('/') || rest.starts_with('/'),
+            None => false,
+        },
+    }
+}
+
+fn url_paths_overlap(a: &Option<String>, b: &Option<String>) -> bool {
+    url_path_covers(a, b) || url_path_covers(b, a)
+}
+
+/// Percent-decodes a url path, so that entries and urls compare the same
+/// however they are encoded. An encoded `/` or `%` stays encoded, as it
+/// doesn't separate path segments.
+fn normalize_url_path(path: &str) -> String {
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = match tail {
+            [hi, lo, ..] if byte == b'%' => std::str::from_utf8(&[*hi, *lo])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match decoded {
+            Some(b'/' | b'%') | None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+        }
     }
+    String::from_utf8_lossy(&bytes).into_owned()
 }
 
 impl ImportDescriptor {
+    /// Parses a `npm:`/`jsr:` package, or a net descriptor optionally
+    /// followed by a path prefix when there's a scheme, e.g.
+    /// `https://jsr.io/@std/`. A `/` after a scheme is read as a network
+    /// first, so `https://10.0.0.0/8` is a CIDR range.
     pub fn parse(specifier: &str) -> Result<Self, AnyError> {
-        Ok(ImportDescriptor(NetDescriptor::parse(specifier)?))
+        if PackageDescriptor::is_package_specifier(specifier) {
+            return Ok(ImportDescriptor::Package(PackageDescriptor::parse(
+                specifier,
+            )?));
+        }
+        let (specifier, digest) = Sha256Digest::split_descriptor(specifier)?;
+        let (net, path) = Self::parse_url_prefix(specifier)?;
+        Ok(ImportDescriptor::Url(net, path, digest))
+    }
+
+    fn parse_url_prefix(specifier: &str) -> Result<(NetDescriptor, Option<String>), AnyError> {
+        let path_start = specifier
+            .split_once("://")
+            .and_then(|(scheme, rest)| rest.find('/').map(|i| scheme.len() + 3 + i));
+        match path_start {
+            Some(start) if NetDescriptor::parse(specifier).is_err() => {
+                let net = NetDescriptor::parse(&specifier[..start])?;
+                let path = normalize_url_path(&specifier[start..]);
+                Ok((net, (path != "/").then_some(path)))
+            }
+            _ => Ok((NetDescriptor::parse(specifier)?, None)),
+        }
     }
 
     pub fn from_url(url: &Url) -> Result<Self, AnyError> {
-        Ok(ImportDescriptor(NetDescriptor::from_url(url)?))
+        if PackageRegistry::from_scheme(url.scheme()).is_some() {
+            return Ok(ImportDescriptor::Package(PackageDescriptor::from_url(url)?));
+        }
+        Ok(ImportDescriptor::Url(
+            NetDescriptor::from_url(url)?,
+            Some(normalize_url_path(url.path())),
+            None,
+        ))
+    }
+
+    pub fn digest(&self) -> Option<&Sha256Digest> {
+        match self {
+            ImportDescriptor::Url(_, _, digest) => digest.as_ref(),
+            ImportDescriptor::Package(_) => None,
+        }
+    }
+
+    fn package_covered_by(&self, other: &ImportDescriptor) -> bool {
+        match (self, other) {
+            (ImportDescriptor::Package(package), ImportDescriptor::Package(other)) => {
+                other.covers(package)
+            }
+            _ => false,
+        }
     }
 }
 
-#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+impl fmt::Display for ImportDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportDescriptor::Url(net, path, digest) => {
+                write!(f, "{net}")?;
+                if let Some(path) = path {
+                    write!(f, "{path}")?;
+                }
+                if let Some(digest) = digest {
+                    write!(f, "@{digest}")?;
+                }
+                Ok(())
+            }
+            ImportDescriptor::Package(package) => write!(f, "{package}"),
+        }
+    }
+}
+
+impl Serialize for ImportDescriptor {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ImportDescriptor {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let text = String::deserialize(deserializer)?;
+        ImportDescriptor::parse(&text).map_err(de::Error::custom)
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
 pub struct EnvDescriptor(EnvVarName);
 
 impl EnvDescriptor {
     pub fn new(env: impl AsRef<str>) -> Self {
         Self(EnvVarName::new(env))
     }
+
+    /// Parses an env descriptor, which is either a variable name or a
+    /// pattern with a single leading or trailing `*`, e.g. `AWS_*` or
+    /// `*_SECRET`.
+    pub fn parse(text: &str) -> Result<Self, AnyError> {
+        if text.is_empty() {
+            return Err(type_error("Empty env not allowed"));
+        }
+        let inner = text.strip_prefix('*').or_else(|| text.strip_suffix('*'));
+        if inner.unwrap_or(text).contains('*') {
+            return Err(type_error(format!(
+                "Invalid env pattern '{text}', only a leading or trailing '*' is supported"
+            )));
+        }
+        Ok(Self::new(text))
+    }
+
+    fn pattern(&self) -> EnvPattern<'_> {
+        let name = self.0.as_ref();
+        if let Some(prefix) = name.strip_suffix('*') {
+            EnvPattern::Prefix(prefix)
+        } else if let Some(suffix) = name.strip_prefix('*') {
+            EnvPattern::Suffix(suffix)
+        } else {
+            EnvPattern::Name(name)
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum EnvPattern<'a> {
+    Name(&'a str),
+    Prefix(&'a str),
+    Suffix(&'a str),
+}
+
+impl EnvPattern<'_> {
+    /// Gets if every variable matched by `other` is also matched by this one.
+    fn covers(self, other: EnvPattern) -> bool {
+        match (self, other) {
+            (EnvPattern::Name(name), EnvPattern::Name(other)) => name == other,
+            (EnvPattern::Name(_), _) => false,
+            (EnvPattern::Prefix(prefix), EnvPattern::Name(other) | EnvPattern::Prefix(other)) => {
+                other.starts_with(prefix)
+            }
+            (EnvPattern::Suffix(suffix), EnvPattern::Name(other) | EnvPattern::Suffix(other)) => {
+                other.ends_with(suffix)
+            }
+            // `*` on its own matches every variable
+            (EnvPattern::Prefix(s), EnvPattern::Suffix(_))
+            | (EnvPattern::Suffix(s), EnvPattern::Prefix(_)) => s.is_empty(),
+        }
+    }
+
+    /// Gets if some variable is matched by both patterns.
+    fn overlaps(self, other: EnvPattern) -> bool {
+        match (self, other) {
+            (EnvPattern::Prefix(a), EnvPattern::Prefix(b)) => a.starts_with(b) || b.starts_with(a),
+            (EnvPattern::Suffix(a), EnvPattern::Suffix(b)) => a.ends_with(b) || b.ends_with(a),
+            // `{prefix}{suffix}` is matched by both
+            (EnvPattern::Prefix(_), EnvPattern::Suffix(_))
+            | (EnvPattern::Suffix(_), EnvPattern::Prefix(_)) => true,
+            (EnvPattern::Name(_), _) | (_, EnvPattern::Name(_)) => {
+                self.covers(other) || other.covers(self)
+            }
+        }
+    }
 }
 
 impl QueryDescriptor for EnvDescriptor {
@@ -1135,23 +2293,23 @@ impl QueryDescriptor for EnvDescriptor {
     }
 
     fn matches_allow(&self, other: &Self::AllowDesc) -> bool {
-        self == other
+        other.pattern().covers(self.pattern())
     }
 
     fn matches_deny(&self, other: &Self::DenyDesc) -> bool {
-        self == other
+        other.pattern().covers(self.pattern())
     }
 
     fn revokes(&self, other: &Self::AllowDesc) -> bool {
-        self == other
+        other.pattern().covers(self.pattern()) || self.pattern().covers(other.pattern())
     }
 
     fn stronger_than_deny(&self, other: &Self::DenyDesc) -> bool {
-        self == other
+        other.pattern().covers(self.pattern()) || self.pattern().covers(other.pattern())
     }
 
-    fn overlaps_deny(&self, _other: &Self::DenyDesc) -> bool {
-        false
+    fn overlaps_deny(&self, other: &Self::DenyDesc) -> bool {
+        !self.matches_deny(other) && self.pattern().overlaps(other.pattern())
     }
 }
 
@@ -1166,6 +2324,10 @@ pub enum RunQueryDescriptor {
     Path {
         requested: String,
         resolved: PathBuf,
+        /// The arguments the command will be run with, or `None` when asking
+        /// about the command itself.
+        #[serde(default)]
+        args: Option<Vec<String>>,
     },
     /// This variant won't actually grant permissions because the path of
     /// the executable is unresolved. It's mostly used so that prompts and
@@ -1189,6 +2351,7 @@ impl RunQueryDescriptor {
             Ok(RunQueryDescriptor::Path {
                 requested: requested.to_string(),
                 resolved,
+                args: None,
             })
         } else {
             #[cfg(not(target_family = "wasm"))]
@@ -1196,6 +2359,7 @@ impl RunQueryDescriptor {
                 Ok(resolved) => Ok(RunQueryDescriptor::Path {
                     requested: requested.to_string(),
                     resolved,
+                    args: None,
                 }),
                 Err(_) => Ok(RunQueryDescriptor::Name(requested.to_string())),
             }
@@ -1203,6 +2367,31 @@ impl RunQueryDescriptor {
             Ok(RunQueryDescriptor::Name(requested.to_string()))
         }
     }
+
+    /// Sets the arguments the command will be run with, so that it's
+    /// checked against the arguments patterns of the allowed commands.
+    /// Unresolved commands are never granted, so they don't keep them.
+    pub fn with_args(self, new_args: Vec<String>) -> Self {
+        match self {
+            RunQueryDescriptor::Path {
+                requested,
+                resolved,
+                ..
+            } => RunQueryDescriptor::Path {
+                requested,
+                resolved,
+                args: Some(new_args),
+            },
+            RunQueryDescriptor::Name(name) => RunQueryDescriptor::Name(name),
+        }
+    }
+
+    pub fn args(&self) -> Option<&[String]> {
+        match self {
+            RunQueryDescriptor::Path { args, .. } => args.as_deref(),
+            RunQueryDescriptor::Name(_) => None,
+        }
+    }
 }
 
 impl QueryDescriptor for RunQueryDescriptor {
@@ -1215,6 +2404,11 @@ impl QueryDescriptor for RunQueryDescriptor {
 
     fn display_name(&self) -> Cow<str> {
         match self {
+            RunQueryDescriptor::Path {
+                requested,
+                args: Some(args),
+                ..
+            } => Cow::Owned(format_command_line(requested, args)),
             RunQueryDescriptor::Path { requested, .. } => Cow::Borrowed(requested),
             RunQueryDescriptor::Name(name) => Cow::Borrowed(name),
         }
@@ -1224,21 +2418,30 @@ impl QueryDescriptor for RunQueryDescriptor {
         RunQueryDescriptor::Path {
             requested: allow.0.to_string_lossy().into_owned(),
             resolved: allow.0.clone(),
+            args: None,
         }
     }
 
     fn as_allow(&self) -> Option<Self::AllowDesc> {
         match self {
-            RunQueryDescriptor::Path { resolved, .. } => Some(AllowRunDescriptor(resolved.clone())),
+            // a prompt grants the command line that was shown, not the command
+            RunQueryDescriptor::Path { resolved, args, .. } => Some(AllowRunDescriptor(
+                resolved.clone(),
+                args.clone().map(RunArgsPattern::Args),
+                None,
+            )),
             RunQueryDescriptor::Name(_) => None,
         }
     }
 
+    // deny descriptors don't have arguments, so denying a command line at a
+    // prompt denies the whole command
     fn as_deny(&self) -> Self::DenyDesc {
         match self {
             RunQueryDescriptor::Path {
                 resolved,
                 requested,
+                ..
             } => {
                 if requested.contains('/') || (cfg!(windows) && requested.contains("\\")) {
                     DenyRunDescriptor::Path(resolved.clone())
@@ -1261,7 +2464,14 @@ impl QueryDescriptor for RunQueryDescriptor {
 
     fn matches_allow(&self, other: &Self::AllowDesc) -> bool {
         match self {
-            RunQueryDescriptor::Path { resolved, .. } => *resolved == other.0,
+            RunQueryDescriptor::Path { resolved, args, .. } => {
+                *resolved == other.0
+                    && match (&other.1, args) {
+                        (None, _) => true,
+                        (Some(pattern), Some(args)) => pattern.matches(args),
+                        (Some(_), None) => false,
+                    }
+            }
             RunQueryDescriptor::Name(_) => false,
         }
     }
@@ -1284,7 +2494,14 @@ impl QueryDescriptor for RunQueryDescriptor {
             RunQueryDescriptor::Path {
                 resolved,
                 requested,
+                args,
             } => {
+                // revoking a command line only drops the grants allowing it
+                if let (Some(args), Some(pattern)) = (args, &other.1) {
+                    if !pattern.matches(args) {
+                        return false;
+                    }
+                }
                 if *resolved == other.0 {
                     return true;
                 }
@@ -1305,6 +2522,17 @@ impl QueryDescriptor for RunQueryDescriptor {
     fn overlaps_deny(&self, _other: &Self::DenyDesc) -> bool {
         false
     }
+
+    fn overlaps_allow(&self, other: &Self::AllowDesc) -> bool {
+        match self {
+            RunQueryDescriptor::Path {
+                resolved,
+                args: None,
+                ..
+            } => *resolved == other.0 && other.1.is_some(),
+            _ => false,
+        }
+    }
 }
 
 pub enum RunDescriptorArg {
@@ -1332,12 +2560,20 @@ fn resolve_from_known_cwd(path: &Path, cwd: &Path) -> PathBuf {
     }
 }
 
-#[derive(Debug, Clone, Hash, Eq, PartialEq)]
-pub struct AllowRunDescriptor(pub PathBuf);
+/// An allowed command, optionally restricted to some arguments and pinned
+/// to the digest of the executable.
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AllowRunDescriptor(
+    pub PathBuf,
+    #[serde(default)] pub Option<RunArgsPattern>,
+    #[serde(default)] pub Option<Sha256Digest>,
+);
 
 impl AllowRunDescriptor {
     #[cfg(not(target_family = "wasm"))]
-    pub fn parse(text: &str, cwd: &Path) -> Result<AllowRunDescriptorParseResult, which::Error> {
+    pub fn parse(text: &str, cwd: &Path) -> Result<AllowRunDescriptorParseResult, AnyError> {
+        let (text, args) = RunArgsPattern::split_descriptor(text)?;
+        let (text, digest) = Sha256Digest::split_descriptor(text)?;
         let is_path = is_path(text);
         // todo(dsherret): canonicalize in #25458
         let path = if is_path {
@@ -1347,7 +2583,7 @@ impl AllowRunDescriptor {
                 Ok(path) => path,
                 Err(err) => match err {
                     which::Error::BadAbsolutePath | which::Error::BadRelativePath => {
-                        return Err(err);
+                        return Err(err.into());
                     }
                     which::Error::CannotFindBinaryPath
                     | which::Error::CannotGetCurrentDir
@@ -1358,12 +2594,14 @@ impl AllowRunDescriptor {
             }
         };
         Ok(AllowRunDescriptorParseResult::Descriptor(
-            AllowRunDescriptor(path),
+            AllowRunDescriptor(path, args, digest),
         ))
     }
 
     #[cfg(target_family = "wasm")]
     pub fn parse(text: &str, cwd: &Path) -> Result<AllowRunDescriptorParseResult, AnyError> {
+        let (text, args) = RunArgsPattern::split_descriptor(text)?;
+        let (text, digest) = Sha256Digest::split_descriptor(text)?;
         let is_path = is_path(text);
         // todo(dsherret): canonicalize in #25458
         let path = if is_path {
@@ -1372,12 +2610,12 @@ impl AllowRunDescriptor {
             resolve_from_known_cwd(Path::new(&format!("/{text}")), cwd)
         };
         Ok(AllowRunDescriptorParseResult::Descriptor(
-            AllowRunDescriptor(path),
+            AllowRunDescriptor(path, args, digest),
         ))
     }
 }
 
-#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
 pub enum DenyRunDescriptor {
     /// Warning: You may want to construct with `RunDescriptor::from()` for case
     /// handling.
@@ -1423,21 +2661,82 @@ pub fn denies_run_name(name: &str, cmd_path: &Path) -> bool {
     // be broad and consider anything like `deno.something` as matching deny perms
     suffix.is_empty() || suffix.starts_with('.')
 }
-#[derive(Clone, Eq, PartialEq, Hash, Debug)]
-pub struct SysDescriptor(String);
+/// A system info kind, or a group of them like `memory:*`. Only the syntax
+/// is checked when parsing, `SysKinds::resolve` checks that the kind is
+/// registered. Descriptors compare by kind, the description is only shown
+/// in prompts.
+#[derive(Clone, Debug)]
+pub struct SysDescriptor {
+    kind: String,
+    description: Option<String>,
+}
 
 impl SysDescriptor {
     pub fn parse(kind: String) -> Result<Self, AnyError> {
-        match kind.as_str() {
-            "hostname" | "osRelease" | "osUptime" | "loadavg" | "networkInterfaces"
-            | "systemMemoryInfo" | "uid" | "gid" | "cpus" | "homedir" | "getegid" | "username"
-            | "statfs" | "getPriority" | "setPriority" => Ok(Self(kind)),
-            _ => Err(type_error(format!("unknown system info kind \"{kind}\""))),
+        if sys_kinds::is_valid_name(&kind) {
+            Ok(Self {
+                kind,
+                description: None,
+            })
+        } else {
+            Err(type_error(format!("Invalid system info kind \"{kind}\"")))
         }
     }
 
+    pub fn as_str(&self) -> &str {
+        &self.kind
+    }
+
     pub fn into_string(self) -> String {
-        self.0
+        self.kind
+    }
+
+    pub(crate) fn with_description(self, description: Option<String>) -> Self {
+        Self {
+            description,
+            ..self
+        }
+    }
+
+    /// Gets if every kind of `other` is one of this descriptor's kinds.
+    fn covers(&self, other: &SysDescriptor) -> bool {
+        match self.kind.strip_suffix('*') {
+            Some(group) => other.kind.starts_with(group),
+            None => self == other,
+        }
+    }
+}
+
+impl PartialEq for SysDescriptor {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl Eq for SysDescriptor {}
+
+impl Hash for SysDescriptor {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.kind.hash(state);
+    }
+}
+
+impl Serialize for SysDescriptor {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.kind)
+    }
+}
+
+impl<'de> Deserialize<'de> for SysDescriptor {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let kind = String::deserialize(deserializer)?;
+        SysDescriptor::parse(kind).map_err(de::Error::custom)
     }
 }
 
@@ -1450,7 +2749,10 @@ impl QueryDescriptor for SysDescriptor {
     }
 
     fn display_name(&self) -> Cow<str> {
-        Cow::from(self.0.to_string())
+        match &self.description {
+            Some(description) => Cow::from(format!("{} ({description})", self.kind)),
+            None => Cow::from(self.kind.as_str()),
+        }
     }
 
     fn from_allow(allow: &Self::AllowDesc) -> Self {
@@ -1475,23 +2777,26 @@ impl QueryDescriptor for SysDescriptor {
     }
 
     fn matches_allow(&self, other: &Self::AllowDesc) -> bool {
-        self == other
+        other.covers(self)
     }
 
     fn matches_deny(&self, other: &Self::DenyDesc) -> bool {
-        self == other
+        other.covers(self)
     }
 
     fn revokes(&self, other: &Self::AllowDesc) -> bool {
-        self == other
+        self.covers(other) || other.covers(self)
     }
 
     fn stronger_than_deny(&self, other: &Self::DenyDesc) -> bool {
-        self == other
+        // a group answered at a prompt denies its kinds, and a group
+        // containing a kind denied at a prompt is denied too
+        self.covers(other) || other.covers(self)
     }
 
-    fn overlaps_deny(&self, _other: &Self::DenyDesc) -> bool {
-        false
+    fn overlaps_deny(&self, other: &Self::DenyDesc) -> bool {
+        // some kinds of a queried group are denied
+        self.covers(other) && !other.covers(self)
     }
 }
 
@@ -1519,11 +2824,11 @@ impl QueryDescriptor for FfiQueryDescriptor {
     }
 
     fn as_allow(&self) -> Option<Self::AllowDesc> {
-        Some(FfiDescriptor(self.0.resolved.clone()))
+        Some(FfiDescriptor(self.0.resolved.clone(), None, None))
     }
 
     fn as_deny(&self) -> Self::DenyDesc {
-        FfiDescriptor(self.0.resolved.clone())
+        FfiDescriptor(self.0.resolved.clone(), None, None)
     }
 
     fn check_in_permission(
@@ -1536,11 +2841,11 @@ impl QueryDescriptor for FfiQueryDescriptor {
     }
 
     fn matches_allow(&self, other: &Self::AllowDesc) -> bool {
-        self.0.resolved.starts_with(&other.0)
+        glob::path_matches(&other.0, &self.0.resolved)
     }
 
     fn matches_deny(&self, other: &Self::DenyDesc) -> bool {
-        self.0.resolved.starts_with(&other.0)
+        glob::path_matches(&other.0, &self.0.resolved)
     }
 
     fn revokes(&self, other: &Self::AllowDesc) -> bool {
@@ -1548,16 +2853,56 @@ impl QueryDescriptor for FfiQueryDescriptor {
     }
 
     fn stronger_than_deny(&self, other: &Self::DenyDesc) -> bool {
-        other.0.starts_with(&self.0.resolved)
+        glob::path_contains_match(&self.0.resolved, &other.0)
     }
 
     fn overlaps_deny(&self, other: &Self::DenyDesc) -> bool {
         self.stronger_than_deny(other)
     }
+
+    fn overlaps_deny_for_check(&self, other: &Self::DenyDesc) -> bool {
+        glob::path_contains_fixed_match(&self.0.resolved, &other.0)
+    }
 }
 
-#[derive(Clone, Eq, PartialEq, Hash, Debug)]
-pub struct FfiDescriptor(pub PathBuf);
+/// A library path, optionally pinned to the digest of the library and
+/// restricted to some symbols, e.g.
+/// `/lib/libfoo.so@sha256:<hex>[symbols=add,sub]`. Deny entries can't be
+/// pinned.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub struct FfiDescriptor(
+    pub PathBuf,
+    #[serde(default)] pub Option<Sha256Digest>,
+    #[serde(default)] pub Option<Vec<String>>,
+);
+
+impl FfiDescriptor {
+    /// Splits `/lib/libfoo.so[symbols=add,sub]` into the rest of the
+    /// descriptor and the symbols that may be bound, if restricted. The rest
+    /// may still carry a digest, see `Sha256Digest::split_descriptor`.
+    pub fn split_symbols(text: &str) -> Result<(&str, Option<Vec<String>>), AnyError> {
+        let Some(start) = text.find("[symbols=") else {
+            return Ok((text, None));
+        };
+        let Some(list) = text[start + "[symbols=".len()..].strip_suffix(']') else {
+            return Err(type_error(format!(
+                "Invalid ffi descriptor '{text}', missing closing ']'"
+            )));
+        };
+        let symbols = list
+            .split(',')
+            .map(|symbol| symbol.trim().to_string())
+            .collect::<Vec<_>>();
+        if symbols.iter().any(|symbol| symbol.is_empty()) {
+            return Err(type_error(format!("Invalid ffi symbol list '{list}'")));
+        }
+        Ok((&text[..start], Some(symbols)))
+    }
+
+    fn is_pinned(&self) -> bool {
+        self.1.is_some() || self.2.is_some()
+    }
+}
 
 impl UnaryPermission<ReadQueryDescriptor> {
     pub fn query(&self, desc: Option<&ReadQueryDescriptor>) -> PermissionState {
@@ -1681,6 +3026,31 @@ impl UnaryPermission<ImportDescriptor> {
         self.check_desc(Some(host), false, api_name)
     }
 
+    /// Checks the digest of a fetched module against the digests pinned by
+    /// the grants allowing it.
+    pub fn check_digest(
+        &self,
+        module: &ImportDescriptor,
+        digest: &Sha256Digest,
+    ) -> Result<(), AnyError> {
+        let pins = self
+            .granted_list
+            .iter()
+            .filter(|allow| module.matches_allow(allow))
+            .map(|allow| allow.digest());
+        if self.granted_global || integrity::pinned_digests_include(pins, digest) {
+            Ok(())
+        } else {
+            Err(custom_error(
+                "PermissionDenied",
+                format!(
+                    "Requires import access to {}, but its content ({digest}) does not match the pinned sha256 digest",
+                    format_display_name(module.display_name()),
+                ),
+            ))
+        }
+    }
+
     pub fn check_all(&mut self) -> Result<(), AnyError> {
         skip_check_if_is_permission_fully_granted!(self);
         self.check_desc(None, false, None)
@@ -1756,9 +3126,34 @@ impl UnaryPermission<RunQueryDescriptor> {
         cmd: &RunQueryDescriptor,
         api_name: Option<&str>,
     ) -> Result<(), AnyError> {
+        // verify the content first, so that a mismatch doesn't use up a
+        // count-limited grant or quota
+        self.check_pinned_digest(cmd)?;
         self.check_desc(Some(cmd), false, api_name)
     }
 
+    /// Verifies the executable against the digests of the grants allowing
+    /// it, unless one of them isn't pinned.
+    fn check_pinned_digest(&self, cmd: &RunQueryDescriptor) -> Result<(), AnyError> {
+        let RunQueryDescriptor::Path { resolved, .. } = cmd else {
+            return Ok(());
+        };
+        let pins = self
+            .granted_list
+            .iter()
+            .filter(|allow| cmd.matches_allow(allow))
+            .map(|allow| allow.2.as_ref());
+        if self.granted_global || integrity::pinned_digests_match(pins, resolved) {
+            Ok(())
+        } else {
+            Err(digest_mismatch_error(
+                RunQueryDescriptor::flag_name(),
+                &cmd.display_name(),
+                resolved,
+            ))
+        }
+    }
+
     pub fn check_all(&mut self, api_name: Option<&str>) -> Result<(), AnyError> {
         self.check_desc(None, false, api_name)
     }
@@ -1792,18 +3187,73 @@ impl UnaryPermission<FfiQueryDescriptor> {
         self.revoke_desc(path)
     }
 
-    pub fn check(
+    pub fn check(
+        &mut self,
+        path: &FfiQueryDescriptor,
+        api_name: Option<&str>,
+    ) -> Result<(), AnyError> {
+        skip_check_if_is_permission_fully_granted!(self);
+        self.check_pinned_digest(path)?;
+        self.check_desc(Some(path), true, api_name)
+    }
+
+    pub fn check_partial(&mut self, path: Option<&FfiQueryDescriptor>) -> Result<(), AnyError> {
+        skip_check_if_is_permission_fully_granted!(self);
+        if let Some(path) = path {
+            self.check_pinned_digest(path)?;
+        }
+        self.check_desc(path, false, None)
+    }
+
+    /// Checks that `symbol` of the library may be bound, on top of the
+    /// library itself being allowed.
+    pub fn check_symbol(
         &mut self,
         path: &FfiQueryDescriptor,
+        symbol: &str,
         api_name: Option<&str>,
     ) -> Result<(), AnyError> {
         skip_check_if_is_permission_fully_granted!(self);
-        self.check_desc(Some(path), true, api_name)
+        self.check(path, api_name)?;
+        if self.granted_global {
+            return Ok(());
+        }
+        let allowed = self
+            .granted_list
+            .iter()
+            .filter(|allow| path.matches_allow(allow))
+            .any(|allow| match &allow.2 {
+                Some(symbols) => symbols.iter().any(|s| s == symbol),
+                None => true,
+            });
+        if allowed {
+            Ok(())
+        } else {
+            Err(custom_error(
+                "PermissionDenied",
+                format!(
+                    "Requires ffi access to symbol \"{symbol}\" of {}, but it is not in the allowed symbols",
+                    format_display_name(path.display_name())
+                ),
+            ))
+        }
     }
 
-    pub fn check_partial(&mut self, path: Option<&FfiQueryDescriptor>) -> Result<(), AnyError> {
-        skip_check_if_is_permission_fully_granted!(self);
-        self.check_desc(path, false, None)
+    fn check_pinned_digest(&self, path: &FfiQueryDescriptor) -> Result<(), AnyError> {
+        let pins = self
+            .granted_list
+            .iter()
+            .filter(|allow| path.matches_allow(allow))
+            .map(|allow| allow.1.as_ref());
+        if self.granted_global || integrity::pinned_digests_match(pins, &path.0.resolved) {
+            Ok(())
+        } else {
+            Err(digest_mismatch_error(
+                FfiQueryDescriptor::flag_name(),
+                &path.display_name(),
+                &path.0.resolved,
+            ))
+        }
     }
 
     pub fn check_all(&mut self) -> Result<(), AnyError> {
@@ -1822,7 +3272,15 @@ pub struct Permissions {
     pub run: UnaryPermission<RunQueryDescriptor>,
     pub ffi: UnaryPermission<FfiQueryDescriptor>,
     pub import: UnaryPermission<ImportDescriptor>,
+    pub unix_socket: UnaryPermission<UnixSocketDescriptor>,
     pub all: UnitPermission,
+    pub specifier_policy: SpecifierPolicy,
+    /// The system info kinds `sys` descriptors are resolved against.
+    pub sys_kinds: SysKinds,
+    /// The custom permission kinds registered by the runtime.
+    pub custom_kinds: CustomKinds,
+    /// The permissions of the kinds in `custom_kinds`.
+    pub custom: CustomPermissions,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Default, Serialize, Deserialize)]
@@ -1843,7 +3301,102 @@ pub struct PermissionsOptions {
     pub allow_write: Option<Vec<String>>,
     pub deny_write: Option<Vec<String>>,
     pub allow_import: Option<Vec<String>>,
+    #[serde(default)]
+    pub deny_import: Option<Vec<String>>,
+    #[serde(default)]
+    pub allow_unix_socket: Option<Vec<String>>,
+    #[serde(default)]
+    pub deny_unix_socket: Option<Vec<String>>,
+    #[serde(default)]
+    pub specifier_policy: SpecifierPolicy,
+    /// The options of runtime-registered kinds, keyed by kind name.
+    #[serde(default)]
+    pub custom: BTreeMap<String, CustomPermissionOptions>,
     pub prompt: bool,
+    /// How many seconds the grants answered at a prompt last. They never
+    /// expire if unset.
+    #[serde(default)]
+    pub prompt_grant_ttl: Option<u64>,
+    /// The quotas of `allow_net`, `allow_write` and `allow_run` entries.
+    #[serde(default)]
+    pub quotas: QuotaOptions,
+    /// The system info kinds `allow_sys` and `deny_sys` entries must name,
+    /// the built-in ones unless the runtime registers more. It isn't part of
+    /// a policy file.
+    #[serde(skip)]
+    pub sys_kinds: SysKinds,
+    /// The custom permission kinds `custom` may hold options of. It isn't
+    /// part of a policy file.
+    #[serde(skip)]
+    pub custom_kinds: CustomKinds,
+}
+
+impl PermissionsOptions {
+    /// The allow and deny lists of every permission kind, keyed by the kind's
+    /// name as used in the option fields (`allow_<name>`, `deny_<name>`).
+    /// Custom kinds follow the built-in ones.
+    #[allow(clippy::type_complexity)]
+    pub fn lists(&self) -> Vec<(&str, Option<&Vec<String>>, Option<&Vec<String>>)> {
+        let mut lists = vec![
+            ("env", self.allow_env.as_ref(), self.deny_env.as_ref()),
+            ("net", self.allow_net.as_ref(), self.deny_net.as_ref()),
+            ("ffi", self.allow_ffi.as_ref(), self.deny_ffi.as_ref()),
+            ("read", self.allow_read.as_ref(), self.deny_read.as_ref()),
+            ("run", self.allow_run.as_ref(), self.deny_run.as_ref()),
+            ("sys", self.allow_sys.as_ref(), self.deny_sys.as_ref()),
+            ("write", self.allow_write.as_ref(), self.deny_write.as_ref()),
+            (
+                "import",
+                self.allow_import.as_ref(),
+                self.deny_import.as_ref(),
+            ),
+            (
+                "unix_socket",
+                self.allow_unix_socket.as_ref(),
+                self.deny_unix_socket.as_ref(),
+            ),
+        ];
+        lists.extend(
+            self.custom
+                .iter()
+                .map(|(name, opts)| (name.as_str(), opts.allow.as_ref(), opts.deny.as_ref())),
+        );
+        lists
+    }
+
+    /// The list stored in the option field named `field`, e.g. `allow_net`,
+    /// or `allow_kv` for the custom kind `kv`.
+    pub(crate) fn list_mut(&mut self, field: &str) -> Option<&mut Option<Vec<String>>> {
+        Some(match field {
+            "allow_env" => &mut self.allow_env,
+            "deny_env" => &mut self.deny_env,
+            "allow_net" => &mut self.allow_net,
+            "deny_net" => &mut self.deny_net,
+            "allow_ffi" => &mut self.allow_ffi,
+            "deny_ffi" => &mut self.deny_ffi,
+            "allow_read" => &mut self.allow_read,
+            "deny_read" => &mut self.deny_read,
+            "allow_run" => &mut self.allow_run,
+            "deny_run" => &mut self.deny_run,
+            "allow_sys" => &mut self.allow_sys,
+            "deny_sys" => &mut self.deny_sys,
+            "allow_write" => &mut self.allow_write,
+            "deny_write" => &mut self.deny_write,
+            "allow_import" => &mut self.allow_import,
+            "deny_import" => &mut self.deny_import,
+            "allow_unix_socket" => &mut self.allow_unix_socket,
+            "deny_unix_socket" => &mut self.deny_unix_socket,
+            _ => {
+                let (prefix, kind) = field.split_once('_')?;
+                let opts = self.custom.entry(kind.to_string()).or_default();
+                match prefix {
+                    "allow" => &mut opts.allow,
+                    "deny" => &mut opts.deny,
+                    _ => return None,
+                }
+            }
+        })
+    }
 }
 
 impl Permissions {
@@ -1879,6 +3432,8 @@ impl Permissions {
         parser: &dyn PermissionDescriptorParser,
         opts: &PermissionsOptions,
     ) -> Result<Self, AnyError> {
+        // run descriptors hash by their regex source, not the regex's cache
+        #[allow(clippy::mutable_key_type)]
         fn resolve_allow_run(
             parser: &dyn PermissionDescriptorParser,
             allow_run: &[String],
@@ -1951,7 +3506,7 @@ impl Permissions {
             }
         }
 
-        Ok(Self {
+        let mut perms = Self {
             read: Permissions::new_unary(
                 parse_maybe_vec(opts.allow_read.as_deref(), |item| {
                     parser.parse_read_descriptor(item)
@@ -1988,10 +3543,10 @@ impl Permissions {
             )?,
             sys: Permissions::new_unary(
                 parse_maybe_vec(opts.allow_sys.as_deref(), |text| {
-                    parser.parse_sys_descriptor(text)
+                    opts.sys_kinds.resolve(parser.parse_sys_descriptor(text)?)
                 })?,
                 parse_maybe_vec(opts.deny_sys.as_deref(), |text| {
-                    parser.parse_sys_descriptor(text)
+                    opts.sys_kinds.resolve(parser.parse_sys_descriptor(text)?)
                 })?,
                 opts.prompt,
             )?,
@@ -2007,7 +3562,11 @@ impl Permissions {
                     parser.parse_ffi_descriptor(text)
                 })?,
                 parse_maybe_vec(opts.deny_ffi.as_deref(), |text| {
-                    parser.parse_ffi_descriptor(text)
+                    let desc = parser.parse_ffi_descriptor(text)?;
+                    if desc.is_pinned() {
+                        bail!("deny_ffi entries can't have a digest or symbols: '{text}'");
+                    }
+                    Ok(desc)
                 })?,
                 opts.prompt,
             )?,
@@ -2015,11 +3574,93 @@ impl Permissions {
                 parse_maybe_vec(opts.allow_import.as_deref(), |item| {
                     parser.parse_import_descriptor(item)
                 })?,
-                None,
+                parse_maybe_vec(opts.deny_import.as_deref(), |item| {
+                    let desc = parser.parse_import_descriptor(item)?;
+                    if desc.digest().is_some() {
+                        bail!("deny_import entries can't have a digest: '{item}'");
+                    }
+                    Ok(desc)
+                })?,
+                opts.prompt,
+            )?,
+            unix_socket: Permissions::new_unary(
+                parse_maybe_vec(opts.allow_unix_socket.as_deref(), |text| {
+                    parser.parse_unix_socket_descriptor(text)
+                })?,
+                parse_maybe_vec(opts.deny_unix_socket.as_deref(), |text| {
+                    parser.parse_unix_socket_descriptor(text)
+                })?,
                 opts.prompt,
             )?,
             all: Permissions::new_all(opts.allow_all),
-        })
+            specifier_policy: opts.specifier_policy,
+            sys_kinds: opts.sys_kinds.clone(),
+            custom_kinds: opts.custom_kinds.clone(),
+            custom: CustomPermissions::from_options(&opts.custom_kinds, &opts.custom, opts.prompt)?,
+        };
+        perms.set_prompt_grant_ttl(opts.prompt_grant_ttl.map(Duration::from_secs));
+
+        let missing_entry = |kind: &str, entry: &str| {
+            type_error(format!(
+                "The quota for '{entry}' doesn't match any allow_{kind} entry"
+            ))
+        };
+        for (entry, quota) in &opts.quotas.net {
+            if !perms
+                .net
+                .set_quota(parser.parse_net_descriptor(entry)?, *quota)
+            {
+                return Err(missing_entry("net", entry));
+            }
+        }
+        for (entry, quota) in &opts.quotas.write {
+            if !perms
+                .write
+                .set_quota(parser.parse_write_descriptor(entry)?, *quota)
+            {
+                return Err(missing_entry("write", entry));
+            }
+        }
+        for (entry, quota) in &opts.quotas.run {
+            // commands that couldn't be resolved aren't allowed at all
+            for desc in resolve_allow_run(parser, std::slice::from_ref(entry))? {
+                if !perms.run.set_quota(desc, *quota) {
+                    return Err(missing_entry("run", entry));
+                }
+            }
+        }
+        Ok(perms)
+    }
+
+    /// Makes the grants answered at a prompt expire after `ttl`, for every
+    /// kind. Grants made before aren't affected.
+    pub fn set_prompt_grant_ttl(&mut self, ttl: Option<Duration>) {
+        self.read.prompt_grant_ttl = ttl;
+        self.write.prompt_grant_ttl = ttl;
+        self.net.prompt_grant_ttl = ttl;
+        self.env.prompt_grant_ttl = ttl;
+        self.sys.prompt_grant_ttl = ttl;
+        self.run.prompt_grant_ttl = ttl;
+        self.ffi.prompt_grant_ttl = ttl;
+        self.import.prompt_grant_ttl = ttl;
+        self.unix_socket.prompt_grant_ttl = ttl;
+        self.custom.set_prompt_grant_ttl(ttl);
+    }
+
+    /// Sets the clock grants with a TTL and quotas are timed with, the
+    /// system clock by default. Child permissions inherit it.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        let clock = SharedClock::new(clock);
+        self.read.clock = clock.clone();
+        self.write.clock = clock.clone();
+        self.net.clock = clock.clone();
+        self.env.clock = clock.clone();
+        self.sys.clock = clock.clone();
+        self.run.clock = clock.clone();
+        self.ffi.clock = clock.clone();
+        self.import.clock = clock.clone();
+        self.unix_socket.clock = clock.clone();
+        self.custom.set_clock(clock);
     }
 
     /// Create a set of permissions that explicitly allow everything.
@@ -2033,7 +3674,12 @@ impl Permissions {
             run: UnaryPermission::allow_all(),
             ffi: UnaryPermission::allow_all(),
             import: UnaryPermission::allow_all(),
+            unix_socket: UnaryPermission::allow_all(),
             all: Permissions::new_all(true),
+            specifier_policy: SpecifierPolicy::default(),
+            sys_kinds: SysKinds::default(),
+            custom_kinds: CustomKinds::default(),
+            custom: CustomPermissions::default(),
         }
     }
 
@@ -2057,7 +3703,12 @@ impl Permissions {
             run: Permissions::new_unary(None, None, prompt).unwrap(),
             ffi: Permissions::new_unary(None, None, prompt).unwrap(),
             import: Permissions::new_unary(None, None, prompt).unwrap(),
+            unix_socket: Permissions::new_unary(None, None, prompt).unwrap(),
             all: Permissions::new_all(false),
+            specifier_policy: SpecifierPolicy::default(),
+            sys_kinds: SysKinds::default(),
+            custom_kinds: CustomKinds::default(),
+            custom: CustomPermissions::default(),
         }
     }
 }
@@ -2239,6 +3890,27 @@ impl<'de> Deserialize<'de> for ChildUnitPermissionArg {
     }
 }
 
+/// Maps keyed by descriptors, which aren't always strings, are written as a
+/// list of pairs.
+fn serialize_desc_map<K, V, S>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    K: Serialize,
+    V: Serialize,
+    S: Serializer,
+{
+    serializer.collect_seq(map.iter())
+}
+
+fn deserialize_desc_map<'de, K, V, D>(deserializer: D) -> Result<HashMap<K, V>, D::Error>
+where
+    K: DeserializeOwned + Hash + Eq,
+    V: DeserializeOwned,
+    D: Deserializer<'de>,
+{
+    let pairs = Vec::<(K, V)>::deserialize(deserializer)?;
+    Ok(pairs.into_iter().collect())
+}
+
 fn global_from_option<T>(flag: Option<&HashSet<T>>) -> bool {
     matches!(flag, Some(v) if v.is_empty())
 }
@@ -2264,6 +3936,17 @@ const fn unit_permission_from_flag_bools(
     }
 }
 
+fn digest_mismatch_error(name: &str, display_name: &str, path: &Path) -> AnyError {
+    custom_error(
+        "PermissionDenied",
+        format!(
+            "Requires {name} access to {}, but {} does not match its pinned sha256 digest",
+            format_display_name(Cow::Borrowed(display_name)),
+            path.display()
+        ),
+    )
+}
+
 fn escalation_error() -> AnyError {
     custom_error(
         "PermissionDenied",
@@ -2305,6 +3988,7 @@ impl From<bool> for AllowPartial {
 #[derive(Clone, Debug)]
 pub struct BlsPermissionsContainer {
     descriptor_parser: Arc<dyn PermissionDescriptorParser>,
+    net_resolver: Arc<dyn NetResolver>,
     pub inner: Arc<Mutex<Permissions>>,
 }
 
@@ -2312,10 +3996,24 @@ impl BlsPermissionsContainer {
     pub fn new(descriptor_parser: Arc<dyn PermissionDescriptorParser>, perms: Permissions) -> Self {
         Self {
             descriptor_parser,
+            net_resolver: Arc::new(SystemResolver),
             inner: Arc::new(Mutex::new(perms)),
         }
     }
 
+    /// Sets the resolver used by `resolve_net`, the system one by default.
+    /// Child containers inherit it.
+    pub fn with_net_resolver(mut self, net_resolver: Arc<dyn NetResolver>) -> Self {
+        self.net_resolver = net_resolver;
+        self
+    }
+
+    /// Sets the clock of the permissions, see `Permissions::set_clock`.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        self.inner.lock().set_clock(clock);
+        self
+    }
+
     pub fn allow_all(descriptor_parser: Arc<dyn PermissionDescriptorParser>) -> Self {
         Self::new(descriptor_parser, Permissions::allow_all())
     }
@@ -2341,6 +4039,9 @@ impl BlsPermissionsContainer {
         let mut worker_perms = Permissions::none_without_prompt();
 
         let mut inner = self.inner.lock();
+        worker_perms.specifier_policy = inner.specifier_policy;
+        worker_perms.sys_kinds = inner.sys_kinds.clone();
+        worker_perms.custom_kinds = inner.custom_kinds.clone();
         worker_perms.all = inner
             .all
             .create_child_permissions(ChildUnitPermissionArg::Inherit)?;
@@ -2356,8 +4057,17 @@ impl BlsPermissionsContainer {
                 &child_permissions_arg.sys,
                 &child_permissions_arg.run,
                 &child_permissions_arg.ffi,
+                &child_permissions_arg.unix_socket,
             ];
-            let allow_all = unary_perms.into_iter().all(is_granted_unary);
+            let allow_all = unary_perms.into_iter().all(is_granted_unary)
+                && inner.custom.kinds().all(|kind| {
+                    is_granted_unary(
+                        child_permissions_arg
+                            .custom
+                            .get(kind)
+                            .unwrap_or(&child_permissions_arg.custom_default),
+                    )
+                });
             if !allow_all {
                 worker_perms.all.revoke();
             }
@@ -2390,11 +4100,13 @@ impl BlsPermissionsContainer {
             .create_child_permissions(child_permissions_arg.env, |text| {
                 Ok(Some(self.descriptor_parser.parse_env_descriptor(text)?))
             })?;
-        worker_perms.sys = inner
-            .sys
-            .create_child_permissions(child_permissions_arg.sys, |text| {
-                Ok(Some(self.descriptor_parser.parse_sys_descriptor(text)?))
-            })?;
+        worker_perms.sys =
+            inner
+                .sys
+                .create_child_permissions(child_permissions_arg.sys, |text| {
+                    let desc = self.descriptor_parser.parse_sys_descriptor(text)?;
+                    Ok(Some(worker_perms.sys_kinds.resolve(desc)?))
+                })?;
         worker_perms.run =
             inner
                 .run
@@ -2409,11 +4121,23 @@ impl BlsPermissionsContainer {
             .create_child_permissions(child_permissions_arg.ffi, |text| {
                 Ok(Some(self.descriptor_parser.parse_ffi_descriptor(text)?))
             })?;
-
-        Ok(BlsPermissionsContainer::new(
-            self.descriptor_parser.clone(),
-            worker_perms,
-        ))
+        worker_perms.unix_socket = inner.unix_socket.create_child_permissions(
+            child_permissions_arg.unix_socket,
+            |text| {
+                Ok(Some(
+                    self.descriptor_parser.parse_unix_socket_descriptor(text)?,
+                ))
+            },
+        )?;
+        worker_perms.custom = inner.custom.create_child_permissions(
+            child_permissions_arg.custom,
+            &child_permissions_arg.custom_default,
+        )?;
+
+        Ok(
+            BlsPermissionsContainer::new(self.descriptor_parser.clone(), worker_perms)
+                .with_net_resolver(self.net_resolver.clone()),
+        )
     }
 
     #[inline(always)]
@@ -2421,11 +4145,44 @@ impl BlsPermissionsContainer {
         &self,
         specifier: &ModuleSpecifier,
         kind: CheckSpecifierKind,
+    ) -> Result<(), AnyError> {
+        self.check_specifier_inner(specifier, kind, None)
+    }
+
+    /// Checks a fetched module like `check_specifier`, and then its content
+    /// digest against the digests pinned by the grants allowing it.
+    pub fn check_specifier_with_digest(
+        &self,
+        specifier: &ModuleSpecifier,
+        kind: CheckSpecifierKind,
+        digest: &Sha256Digest,
+    ) -> Result<(), AnyError> {
+        self.check_specifier_inner(specifier, kind, Some(digest))
+    }
+
+    fn check_specifier_inner(
+        &self,
+        specifier: &ModuleSpecifier,
+        kind: CheckSpecifierKind,
+        digest: Option<&Sha256Digest>,
     ) -> Result<(), AnyError> {
         let mut inner = self.inner.lock();
+        match inner.specifier_policy.action(specifier.scheme(), kind) {
+            SpecifierAction::Allow => return Ok(()),
+            SpecifierAction::Deny => {
+                return Err(custom_error(
+                    "PermissionDenied",
+                    format!(
+                        "Importing {}: modules is denied by the specifier policy.\n  Specifier: {specifier}",
+                        specifier.scheme()
+                    ),
+                ));
+            }
+            SpecifierAction::Check => {}
+        }
         match specifier.scheme() {
             "file" => {
-                if inner.read.is_allow_all() || kind == CheckSpecifierKind::Static {
+                if inner.read.is_allow_all() {
                     return Ok(());
                 }
 
@@ -2443,8 +4200,7 @@ impl BlsPermissionsContainer {
                     ))),
                 }
             }
-            "data" => Ok(()),
-            "blob" => Ok(()),
+            "data" | "blob" => inner.import.check_all(),
             _ => {
                 if inner.import.is_allow_all() {
                     return Ok(()); // avoid allocation below
@@ -2453,6 +4209,11 @@ impl BlsPermissionsContainer {
                 let desc = self
                     .descriptor_parser
                     .parse_import_descriptor_from_url(specifier)?;
+                // verify the content first, so that a mismatch doesn't use up
+                // a count-limited grant
+                if let Some(digest) = digest {
+                    inner.import.check_digest(&desc, digest)?;
+                }
                 inner.import.check(&desc, Some("import()"))?;
                 Ok(())
             }
@@ -2640,10 +4401,51 @@ impl BlsPermissionsContainer {
 
     #[inline(always)]
     pub fn check_sys(&self, kind: &str, api_name: &str) -> Result<(), AnyError> {
-        self.inner.lock().sys.check(
-            &self.descriptor_parser.parse_sys_descriptor(kind)?,
-            Some(api_name),
-        )
+        let mut inner = self.inner.lock();
+        let desc = self.parse_sys_kind(&inner, kind)?;
+        inner.sys.check(&desc, Some(api_name))
+    }
+
+    /// Parses a system info kind and resolves it against the kinds
+    /// registered on `perms`.
+    fn parse_sys_kind(&self, perms: &Permissions, kind: &str) -> Result<SysDescriptor, AnyError> {
+        perms
+            .sys_kinds
+            .resolve(self.descriptor_parser.parse_sys_descriptor(kind)?)
+    }
+
+    /// Registers a system info kind exposed by the runtime, see
+    /// `SysKinds::register`. Child containers inherit it.
+    pub fn register_sys_kind(&self, name: &str, description: &str) -> Result<(), AnyError> {
+        self.inner.lock().sys_kinds.register(name, description)
+    }
+
+    /// Every known system info kind, built-in ones included.
+    pub fn sys_kinds(&self) -> Vec<SysKind> {
+        self.inner.lock().sys_kinds.list()
+    }
+
+    /// Registers a custom permission kind, see
+    /// `Permissions::register_permission_kind`. Child containers inherit it.
+    pub fn register_permission_kind<D: CustomPermissionDescriptor>(&self) -> Result<(), AnyError> {
+        self.inner.lock().register_permission_kind::<D>()
+    }
+
+    /// The names of every registered custom permission kind.
+    pub fn permission_kinds(&self) -> Vec<&'static str> {
+        self.inner.lock().custom_kinds.names()
+    }
+
+    /// Checks a runtime-registered kind, `value` is parsed with the kind's
+    /// descriptor and `None` checks the whole kind.
+    #[inline(always)]
+    pub fn check_custom(
+        &self,
+        kind: &str,
+        value: Option<&str>,
+        api_name: &str,
+    ) -> Result<(), AnyError> {
+        self.inner.lock().custom.check(kind, value, Some(api_name))
     }
 
     #[inline(always)]
@@ -2790,10 +4592,75 @@ impl BlsPermissionsContainer {
         let inner = &mut inner.net;
         skip_check_if_is_permission_fully_granted!(inner);
         let hostname = Host::parse(host.0.as_ref())?;
-        let descriptor = NetDescriptor(hostname, host.1);
+        let descriptor = NetDescriptor(hostname, host.1.map(NetPort::from), None);
         inner.check(&descriptor, Some(api_name))
     }
 
+    /// Checks the addresses an approved `host` resolved to against the IP and
+    /// CIDR entries of the deny lists, so that an allowed hostname can't be
+    /// pointed at a denied address. Call it after `check_net`.
+    pub fn check_net_resolved<T: AsRef<str>>(
+        &self,
+        host: &(T, Option<u16>),
+        addrs: &[SocketAddr],
+    ) -> Result<(), AnyError> {
+        let inner = self.inner.lock();
+        if inner.net.is_allow_all() {
+            return Ok(());
+        }
+        let hostname = Host::parse(host.0.as_ref())?;
+        let descriptor = NetDescriptor(hostname, host.1.map(NetPort::from), None);
+        inner.net.check_resolved(&descriptor, addrs)
+    }
+
+    /// Same as `check_net_resolved`, for a host approved with `check_net_url`.
+    pub fn check_net_url_resolved(&self, url: &Url, addrs: &[SocketAddr]) -> Result<(), AnyError> {
+        let inner = self.inner.lock();
+        if inner.net.is_allow_all() {
+            return Ok(());
+        }
+        let desc = self.descriptor_parser.parse_net_descriptor_from_url(url)?;
+        inner.net.check_resolved(&desc, addrs)
+    }
+
+    /// Checks `host`, resolves it with the container's resolver and checks the
+    /// addresses it resolved to. The port is required, as deny entries with
+    /// a port couldn't be matched without it. The host check is done once,
+    /// so a one-time allow from the prompt covers the resolved addresses.
+    #[must_use = "the resolved return value to mitigate time-of-check to time-of-use issues"]
+    pub fn resolve_net<T: AsRef<str>>(
+        &self,
+        host: &(T, Option<u16>),
+        api_name: &str,
+    ) -> Result<Vec<SocketAddr>, AnyError> {
+        let Some(port) = host.1 else {
+            return Err(type_error(format!(
+                "Resolving '{}' requires a port",
+                host.0.as_ref()
+            )));
+        };
+        self.check_net(host, api_name)?;
+        let addrs = self.net_resolver.resolve(host.0.as_ref(), port)?;
+        let inner = self.inner.lock();
+        if !inner.net.is_allow_all() {
+            let hostname = Host::parse(host.0.as_ref())?;
+            let descriptor = NetDescriptor(hostname, Some(NetPort::from(port)), None);
+            inner.net.check_resolved_addrs(&descriptor, &addrs)?;
+        }
+        Ok(addrs)
+    }
+
+    /// Checks a connection to, or a bind of, the Unix socket at `path`, or the
+    /// abstract socket `@name`.
+    #[inline(always)]
+    pub fn check_unix_socket(&self, path: &str, api_name: &str) -> Result<(), AnyError> {
+        let mut inner = self.inner.lock();
+        let inner = &mut inner.unix_socket;
+        skip_check_if_is_permission_fully_granted!(inner);
+        let desc = self.descriptor_parser.parse_unix_socket_descriptor(path)?;
+        inner.check(&desc, Some(api_name))
+    }
+
     #[inline(always)]
     pub fn check_ffi(&self, path: &str) -> Result<PathBuf, AnyError> {
         let mut inner = self.inner.lock();
@@ -2807,6 +4674,21 @@ impl BlsPermissionsContainer {
         }
     }
 
+    /// Checks that `symbol` may be bound from the library at `path`.
+    #[must_use = "the resolved return value to mitigate time-of-check to time-of-use issues"]
+    #[inline(always)]
+    pub fn check_ffi_symbol(&self, path: &str, symbol: &str) -> Result<PathBuf, AnyError> {
+        let mut inner = self.inner.lock();
+        let inner = &mut inner.ffi;
+        if inner.is_allow_all() {
+            Ok(PathBuf::from(path))
+        } else {
+            let desc = self.descriptor_parser.parse_path_query(path)?.into_ffi();
+            inner.check_symbol(&desc, symbol, None)?;
+            Ok(desc.0.resolved)
+        }
+    }
+
     #[must_use = "the resolved return value to mitigate time-of-check to time-of-use issues"]
     #[inline(always)]
     pub fn check_ffi_partial_no_path(&self) -> Result<(), AnyError> {
@@ -2905,7 +4787,7 @@ impl BlsPermissionsContainer {
             return Ok(PermissionState::Granted);
         }
         Ok(permission.query(
-            kind.map(|kind| self.descriptor_parser.parse_sys_descriptor(kind))
+            kind.map(|kind| self.parse_sys_kind(&inner, kind))
                 .transpose()?
                 .as_ref(),
         ))
@@ -2941,6 +4823,225 @@ impl BlsPermissionsContainer {
         ))
     }
 
+    #[inline(always)]
+    pub fn query_unix_socket(&self, path: Option<&str>) -> Result<PermissionState, AnyError> {
+        let inner = self.inner.lock();
+        let permission = &inner.unix_socket;
+        if permission.is_allow_all() {
+            return Ok(PermissionState::Granted);
+        }
+        Ok(permission.query(
+            path.map(|path| self.descriptor_parser.parse_unix_socket_descriptor(path))
+                .transpose()?
+                .as_ref(),
+        ))
+    }
+
+    #[inline(always)]
+    pub fn query_import(&self, host: Option<&str>) -> Result<PermissionState, AnyError> {
+        let inner = self.inner.lock();
+        let permission = &inner.import;
+        if permission.is_allow_all() {
+            return Ok(PermissionState::Granted);
+        }
+        Ok(permission.query(
+            match host {
+                None => None,
+                Some(h) => Some(self.descriptor_parser.parse_import_descriptor(h)?),
+            }
+            .as_ref(),
+        ))
+    }
+
+    /// How many more times a kind, or a value of it, can be checked if it is
+    /// only granted by count-limited grants, see `UnaryPermission::remaining_uses`.
+    pub fn query_uses(&self, kind: &str, value: Option<&str>) -> Result<Option<u32>, AnyError> {
+        let inner = self.inner.lock();
+        let parser = &self.descriptor_parser;
+        let path = |value: &str| parser.parse_path_query(value);
+        Ok(match kind {
+            "read" => inner.read.remaining_uses(
+                value
+                    .map(|value| Result::<_, AnyError>::Ok(path(value)?.into_read()))
+                    .transpose()?
+                    .as_ref(),
+            ),
+            "write" => inner.write.remaining_uses(
+                value
+                    .map(|value| Result::<_, AnyError>::Ok(path(value)?.into_write()))
+                    .transpose()?
+                    .as_ref(),
+            ),
+            "ffi" => inner.ffi.remaining_uses(
+                value
+                    .map(|value| Result::<_, AnyError>::Ok(path(value)?.into_ffi()))
+                    .transpose()?
+                    .as_ref(),
+            ),
+            "net" => inner.net.remaining_uses(
+                value
+                    .map(|value| parser.parse_net_descriptor(value))
+                    .transpose()?
+                    .as_ref(),
+            ),
+            "import" => inner.import.remaining_uses(
+                value
+                    .map(|value| parser.parse_import_descriptor(value))
+                    .transpose()?
+                    .as_ref(),
+            ),
+            "env" => inner
+                .env
+                .remaining_uses(value.map(EnvDescriptor::new).as_ref()),
+            "sys" => inner.sys.remaining_uses(
+                value
+                    .map(|value| self.parse_sys_kind(&inner, value))
+                    .transpose()?
+                    .as_ref(),
+            ),
+            "run" => inner.run.remaining_uses(
+                value
+                    .map(|value| parser.parse_run_query(value))
+                    .transpose()?
+                    .as_ref(),
+            ),
+            "unix_socket" => inner.unix_socket.remaining_uses(
+                value
+                    .map(|value| parser.parse_unix_socket_descriptor(value))
+                    .transpose()?
+                    .as_ref(),
+            ),
+            _ => inner.custom.remaining_uses(kind, value)?,
+        })
+    }
+
+    /// The quotas limiting a value of a kind, with what they used so far.
+    /// Only net, write and run entries carry quotas.
+    pub fn query_quota(&self, kind: &str, value: &str) -> Result<Vec<QuotaState>, AnyError> {
+        let inner = self.inner.lock();
+        let parser = &self.descriptor_parser;
+        Ok(match kind {
+            "net" => inner.net.quotas_for(&parser.parse_net_descriptor(value)?),
+            "write" => inner
+                .write
+                .quotas_for(&parser.parse_path_query(value)?.into_write()),
+            "run" => inner.run.quotas_for(&parser.parse_run_query(value)?),
+            "read" | "ffi" | "import" | "env" | "sys" | "unix_socket" => Vec::new(),
+            _ => {
+                inner.custom.ensure_kind(kind)?;
+                Vec::new()
+            }
+        })
+    }
+
+    /// Counts `bytes` transferred by an operation on a value of a kind, e.g.
+    /// the size of a response, against its quotas.
+    pub fn report_bytes(&self, kind: &str, value: &str, bytes: u64) -> Result<(), AnyError> {
+        let mut inner = self.inner.lock();
+        let parser = &self.descriptor_parser;
+        match kind {
+            "net" => inner
+                .net
+                .report_bytes(&parser.parse_net_descriptor(value)?, bytes),
+            "write" => inner
+                .write
+                .report_bytes(&parser.parse_path_query(value)?.into_write(), bytes),
+            "run" => inner
+                .run
+                .report_bytes(&parser.parse_run_query(value)?, bytes),
+            "read" | "ffi" | "import" | "env" | "sys" | "unix_socket" => Ok(()),
+            _ => inner.custom.ensure_kind(kind),
+        }
+    }
+
+    /// Grants a value of a kind, or the whole kind if `value` is `None`,
+    /// for `ttl`. A grant that lasts longer isn't shortened.
+    pub fn grant_for(
+        &self,
+        kind: &str,
+        value: Option<&str>,
+        ttl: Duration,
+    ) -> Result<(), AnyError> {
+        fn grant<TQuery: QueryDescriptor>(
+            perm: &mut UnaryPermission<TQuery>,
+            desc: Option<TQuery>,
+            ttl: Duration,
+        ) -> Result<(), AnyError> {
+            let allow = match desc {
+                Some(desc) => Some(desc.as_allow().ok_or_else(|| {
+                    type_error(format!(
+                        "Can't grant {} access to {}",
+                        TQuery::flag_name(),
+                        format_display_name(desc.display_name())
+                    ))
+                })?),
+                None => None,
+            };
+            perm.grant_for(allow, ttl);
+            Ok(())
+        }
+
+        let mut inner = self.inner.lock();
+        let parser = &self.descriptor_parser;
+        let path = |value: &str| parser.parse_path_query(value);
+        match kind {
+            "read" => grant(
+                &mut inner.read,
+                value.map(|v| path(v).map(|p| p.into_read())).transpose()?,
+                ttl,
+            ),
+            "write" => grant(
+                &mut inner.write,
+                value.map(|v| path(v).map(|p| p.into_write())).transpose()?,
+                ttl,
+            ),
+            "net" => grant(
+                &mut inner.net,
+                value.map(|v| parser.parse_net_descriptor(v)).transpose()?,
+                ttl,
+            ),
+            "env" => grant(&mut inner.env, value.map(EnvDescriptor::new), ttl),
+            "sys" => {
+                let desc = value.map(|v| self.parse_sys_kind(&inner, v)).transpose()?;
+                grant(&mut inner.sys, desc, ttl)
+            }
+            "run" => grant(
+                &mut inner.run,
+                value.map(|v| parser.parse_run_query(v)).transpose()?,
+                ttl,
+            ),
+            "ffi" => grant(
+                &mut inner.ffi,
+                value.map(|v| path(v).map(|p| p.into_ffi())).transpose()?,
+                ttl,
+            ),
+            "import" => grant(
+                &mut inner.import,
+                value
+                    .map(|v| parser.parse_import_descriptor(v))
+                    .transpose()?,
+                ttl,
+            ),
+            "unix_socket" => grant(
+                &mut inner.unix_socket,
+                value
+                    .map(|v| parser.parse_unix_socket_descriptor(v))
+                    .transpose()?,
+                ttl,
+            ),
+            _ => inner.custom.grant_for(kind, value, ttl),
+        }
+    }
+
+    #[inline(always)]
+    pub fn query_custom(
+        &self,
+        kind: &str,
+        value: Option<&str>,
+    ) -> Result<PermissionState, AnyError> {
+        self.inner.lock().custom.query(kind, value)
+    }
+
     // revoke
 
     #[inline(always)]
@@ -2987,11 +5088,11 @@ impl BlsPermissionsContainer {
 
     #[inline(always)]
     pub fn revoke_sys(&self, kind: Option<&str>) -> Result<PermissionState, AnyError> {
-        Ok(self.inner.lock().sys.revoke(
-            kind.map(|kind| self.descriptor_parser.parse_sys_descriptor(kind))
-                .transpose()?
-                .as_ref(),
-        ))
+        let mut inner = self.inner.lock();
+        let desc = kind
+            .map(|kind| self.parse_sys_kind(&inner, kind))
+            .transpose()?;
+        Ok(inner.sys.revoke(desc.as_ref()))
     }
 
     #[inline(always)]
@@ -3014,6 +5115,35 @@ impl BlsPermissionsContainer {
         ))
     }
 
+    #[inline(always)]
+    pub fn revoke_unix_socket(&self, path: Option<&str>) -> Result<PermissionState, AnyError> {
+        Ok(self.inner.lock().unix_socket.revoke(
+            path.map(|path| self.descriptor_parser.parse_unix_socket_descriptor(path))
+                .transpose()?
+                .as_ref(),
+        ))
+    }
+
+    #[inline(always)]
+    pub fn revoke_import(&self, host: Option<&str>) -> Result<PermissionState, AnyError> {
+        Ok(self.inner.lock().import.revoke(
+            match host {
+                None => None,
+                Some(h) => Some(self.descriptor_parser.parse_import_descriptor(h)?),
+            }
+            .as_ref(),
+        ))
+    }
+
+    #[inline(always)]
+    pub fn revoke_custom(
+        &self,
+        kind: &str,
+        value: Option<&str>,
+    ) -> Result<PermissionState, AnyError> {
+        self.inner.lock().custom.revoke(kind, value)
+    }
+
     // request
 
     #[inline(always)]
@@ -3060,11 +5190,11 @@ impl BlsPermissionsContainer {
 
     #[inline(always)]
     pub fn request_sys(&self, kind: Option<&str>) -> Result<PermissionState, AnyError> {
-        Ok(self.inner.lock().sys.request(
-            kind.map(|kind| self.descriptor_parser.parse_sys_descriptor(kind))
-                .transpose()?
-                .as_ref(),
-        ))
+        let mut inner = self.inner.lock();
+        let desc = kind
+            .map(|kind| self.parse_sys_kind(&inner, kind))
+            .transpose()?;
+        Ok(inner.sys.request(desc.as_ref()))
     }
 
     #[inline(always)]
@@ -3086,6 +5216,35 @@ impl BlsPermissionsContainer {
             .as_ref(),
         ))
     }
+
+    #[inline(always)]
+    pub fn request_unix_socket(&self, path: Option<&str>) -> Result<PermissionState, AnyError> {
+        Ok(self.inner.lock().unix_socket.request(
+            path.map(|path| self.descriptor_parser.parse_unix_socket_descriptor(path))
+                .transpose()?
+                .as_ref(),
+        ))
+    }
+
+    #[inline(always)]
+    pub fn request_import(&self, host: Option<&str>) -> Result<PermissionState, AnyError> {
+        Ok(self.inner.lock().import.request(
+            match host {
+                None => None,
+                Some(h) => Some(self.descriptor_parser.parse_import_descriptor(h)?),
+            }
+            .as_ref(),
+        ))
+    }
+
+    #[inline(always)]
+    pub fn request_custom(
+        &self,
+        kind: &str,
+        value: Option<&str>,
+    ) -> Result<PermissionState, AnyError> {
+        self.inner.lock().custom.request(kind, value)
+    }
 }
 
 /// Directly deserializable from JS worker and test permission options.
@@ -3099,6 +5258,11 @@ pub struct ChildPermissionsArg {
     pub run: ChildUnaryPermissionArg,
     pub sys: ChildUnaryPermissionArg,
     pub write: ChildUnaryPermissionArg,
+    pub unix_socket: ChildUnaryPermissionArg,
+    /// The args of runtime-registered kinds.
+    pub custom: BTreeMap<String, ChildUnaryPermissionArg>,
+    /// The arg of the runtime-registered kinds missing from `custom`.
+    pub custom_default: ChildUnaryPermissionArg,
 }
 
 impl ChildPermissionsArg {
@@ -3112,6 +5276,9 @@ impl ChildPermissionsArg {
             run: ChildUnaryPermissionArg::Inherit,
             sys: ChildUnaryPermissionArg::Inherit,
             write: ChildUnaryPermissionArg::Inherit,
+            unix_socket: ChildUnaryPermissionArg::Inherit,
+            custom: BTreeMap::new(),
+            custom_default: ChildUnaryPermissionArg::Inherit,
         }
     }
 
@@ -3125,6 +5292,9 @@ impl ChildPermissionsArg {
             run: ChildUnaryPermissionArg::NotGranted,
             sys: ChildUnaryPermissionArg::NotGranted,
             write: ChildUnaryPermissionArg::NotGranted,
+            unix_socket: ChildUnaryPermissionArg::NotGranted,
+            custom: BTreeMap::new(),
+            custom_default: ChildUnaryPermissionArg::NotGranted,
         }
     }
 }
@@ -3208,8 +5378,18 @@ impl<'de> Deserialize<'de> for ChildPermissionsArg {
                         child_permissions_arg.write = arg.map_err(|e| {
                             de::Error::custom(format!("({API}.permissions.write) {e}"))
                         })?;
+                    } else if key == "unix_socket" {
+                        let arg = serde_json::from_value::<ChildUnaryPermissionArg>(value);
+                        child_permissions_arg.unix_socket = arg.map_err(|e| {
+                            de::Error::custom(format!("({API}.permissions.unix_socket) {e}"))
+                        })?;
                     } else {
-                        return Err(de::Error::custom("unknown permission name"));
+                        // custom kinds, checked when creating the child
+                        let arg = serde_json::from_value::<ChildUnaryPermissionArg>(value);
+                        let arg = arg.map_err(|e| {
+                            de::Error::custom(format!("({API}.permissions.{key}) {e}"))
+                        })?;
+                        child_permissions_arg.custom.insert(key, arg);
                     }
                 }
                 Ok(child_permissions_arg)
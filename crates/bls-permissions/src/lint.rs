@@ -0,0 +1,444 @@
+use std::fmt;
+
+use crate::AllowRunDescriptor;
+use crate::AllowRunDescriptorParseResult;
+use crate::AnyError;
+use crate::DenyRunDescriptor;
+use crate::EnvDescriptor;
+use crate::FfiDescriptor;
+use crate::FfiQueryDescriptor;
+use crate::ImportDescriptor;
+use crate::NetDescriptor;
+use crate::PermissionDescriptorParser;
+use crate::Permissions;
+use crate::PermissionsOptions;
+use crate::QueryDescriptor;
+use crate::ReadDescriptor;
+use crate::ReadQueryDescriptor;
+use crate::RunQueryDescriptor;
+use crate::SysDescriptor;
+use crate::UnaryPermission;
+use crate::UnixSocketDescriptor;
+use crate::WriteDescriptor;
+use crate::WriteQueryDescriptor;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum LintSeverity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for LintSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LintSeverity::Info => f.pad("info"),
+            LintSeverity::Warning => f.pad("warning"),
+            LintSeverity::Error => f.pad("error"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum LintKind {
+    /// The entry is listed more than once.
+    Duplicate,
+    /// The allow entry is already covered by another allow entry.
+    Redundant,
+    /// The allow entry is fully covered by a deny entry and never takes effect.
+    Shadowed,
+    /// Part of what the allow entry covers is denied.
+    PartiallyShadowed,
+    /// The `allow_run` entry couldn't be resolved and is ignored.
+    Unresolved,
+    /// The entry couldn't be parsed.
+    Invalid,
+}
+
+/// A problem found in a set of permissions by `PermissionsOptions::lint` or
+/// `Permissions::lint`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PermissionLint {
+    pub severity: LintSeverity,
+    pub kind: LintKind,
+    /// The flag name of the permission kind, e.g. `read`.
+    pub flag_name: &'static str,
+    pub entry: String,
+    /// The entry that causes this one to be redundant or shadowed.
+    pub related: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for PermissionLint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.flag_name, self.message)
+    }
+}
+
+impl PermissionsOptions {
+    /// Finds duplicate, redundant, shadowed and invalid entries. Entries are
+    /// compared with the same rules used when checking permissions.
+    pub fn lint(&self, parser: &dyn PermissionDescriptorParser) -> Vec<PermissionLint> {
+        let mut lints = Vec::new();
+        lint_options::<ReadQueryDescriptor>(
+            &mut lints,
+            self.allow_read.as_ref(),
+            self.deny_read.as_ref(),
+            |text| parser.parse_read_descriptor(text).map(Some),
+            |text| parser.parse_read_descriptor(text),
+        );
+        lint_options::<WriteQueryDescriptor>(
+            &mut lints,
+            self.allow_write.as_ref(),
+            self.deny_write.as_ref(),
+            |text| parser.parse_write_descriptor(text).map(Some),
+            |text| parser.parse_write_descriptor(text),
+        );
+        lint_options::<NetDescriptor>(
+            &mut lints,
+            self.allow_net.as_ref(),
+            self.deny_net.as_ref(),
+            |text| parser.parse_net_descriptor(text).map(Some),
+            |text| parser.parse_net_descriptor(text),
+        );
+        lint_options::<EnvDescriptor>(
+            &mut lints,
+            self.allow_env.as_ref(),
+            self.deny_env.as_ref(),
+            |text| parser.parse_env_descriptor(text).map(Some),
+            |text| parser.parse_env_descriptor(text),
+        );
+        lint_options::<SysDescriptor>(
+            &mut lints,
+            self.allow_sys.as_ref(),
+            self.deny_sys.as_ref(),
+            |text| {
+                let desc = parser.parse_sys_descriptor(text)?;
+                self.sys_kinds.resolve(desc).map(Some)
+            },
+            |text| self.sys_kinds.resolve(parser.parse_sys_descriptor(text)?),
+        );
+        lint_options::<RunQueryDescriptor>(
+            &mut lints,
+            self.allow_run.as_ref(),
+            self.deny_run.as_ref(),
+            |text| {
+                if text.is_empty() {
+                    anyhow::bail!("Empty command name not allowed");
+                }
+                match parser.parse_allow_run_descriptor(text)? {
+                    AllowRunDescriptorParseResult::Descriptor(desc) => Ok(Some(desc)),
+                    AllowRunDescriptorParseResult::Unresolved(_) => Ok(None),
+                }
+            },
+            |text| parser.parse_deny_run_descriptor(text),
+        );
+        lint_options::<FfiQueryDescriptor>(
+            &mut lints,
+            self.allow_ffi.as_ref(),
+            self.deny_ffi.as_ref(),
+            |text| parser.parse_ffi_descriptor(text).map(Some),
+            |text| parser.parse_ffi_descriptor(text),
+        );
+        lint_options::<ImportDescriptor>(
+            &mut lints,
+            self.allow_import.as_ref(),
+            self.deny_import.as_ref(),
+            |text| parser.parse_import_descriptor(text).map(Some),
+            |text| parser.parse_import_descriptor(text),
+        );
+        lint_options::<UnixSocketDescriptor>(
+            &mut lints,
+            self.allow_unix_socket.as_ref(),
+            self.deny_unix_socket.as_ref(),
+            |text| parser.parse_unix_socket_descriptor(text).map(Some),
+            |text| parser.parse_unix_socket_descriptor(text),
+        );
+        self.custom_kinds.lint(&self.custom, &mut lints);
+        lints
+    }
+}
+
+impl Permissions {
+    /// Same as `PermissionsOptions::lint`, but over the live state, so
+    /// entries granted or denied at a prompt are included.
+    pub fn lint(&self) -> Vec<PermissionLint> {
+        let mut lints = Vec::new();
+        lint_unary(&self.read, &mut lints);
+        lint_unary(&self.write, &mut lints);
+        lint_unary(&self.net, &mut lints);
+        lint_unary(&self.env, &mut lints);
+        lint_unary(&self.sys, &mut lints);
+        lint_unary(&self.run, &mut lints);
+        lint_unary(&self.ffi, &mut lints);
+        lint_unary(&self.import, &mut lints);
+        lint_unary(&self.unix_socket, &mut lints);
+        lints
+    }
+}
+
+/// A human readable form of a descriptor, as it would be written in a flag.
+trait LintLabel {
+    fn lint_label(&self) -> String;
+}
+
+impl LintLabel for ReadDescriptor {
+    fn lint_label(&self) -> String {
+        self.0.display().to_string()
+    }
+}
+
+impl LintLabel for WriteDescriptor {
+    fn lint_label(&self) -> String {
+        self.0.display().to_string()
+    }
+}
+
+impl LintLabel for NetDescriptor {
+    fn lint_label(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl LintLabel for UnixSocketDescriptor {
+    fn lint_label(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl LintLabel for ImportDescriptor {
+    fn lint_label(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl LintLabel for EnvDescriptor {
+    fn lint_label(&self) -> String {
+        self.as_ref().to_string()
+    }
+}
+
+impl LintLabel for SysDescriptor {
+    fn lint_label(&self) -> String {
+        self.as_str().to_string()
+    }
+}
+
+impl LintLabel for AllowRunDescriptor {
+    fn lint_label(&self) -> String {
+        let mut label = self.0.display().to_string();
+        if let Some(digest) = &self.2 {
+            label.push_str(&format!("@{digest}"));
+        }
+        if let Some(args) = &self.1 {
+            label.push_str(&format!("[{args}]"));
+        }
+        label
+    }
+}
+
+impl LintLabel for DenyRunDescriptor {
+    fn lint_label(&self) -> String {
+        match self {
+            DenyRunDescriptor::Name(name) => name.clone(),
+            DenyRunDescriptor::Path(path) => path.display().to_string(),
+        }
+    }
+}
+
+impl LintLabel for FfiDescriptor {
+    fn lint_label(&self) -> String {
+        let mut label = self.0.display().to_string();
+        if let Some(digest) = &self.1 {
+            label.push_str(&format!("@{digest}"));
+        }
+        if let Some(symbols) = &self.2 {
+            label.push_str(&format!("[symbols={}]", symbols.join(",")));
+        }
+        label
+    }
+}
+
+fn lint_unary<TQuery: QueryDescriptor>(
+    perm: &UnaryPermission<TQuery>,
+    lints: &mut Vec<PermissionLint>,
+) where
+    TQuery::AllowDesc: LintLabel,
+    TQuery::DenyDesc: LintLabel,
+{
+    let mut allow = perm
+        .granted_list
+        .iter()
+        .map(|desc| (desc.lint_label(), desc.clone()))
+        .collect::<Vec<_>>();
+    let mut deny = perm
+        .flag_denied_list
+        .iter()
+        .chain(perm.prompt_denied_list.iter())
+        .map(|desc| (desc.lint_label(), desc.clone()))
+        .collect::<Vec<_>>();
+    // the lists are hash sets, sort them to get a stable output
+    allow.sort_by(|a, b| a.0.cmp(&b.0));
+    deny.sort_by(|a, b| a.0.cmp(&b.0));
+    lint_entries::<TQuery>(
+        lints,
+        perm.granted_global,
+        &allow,
+        perm.flag_denied_global || perm.prompt_denied_global,
+        &deny,
+    );
+}
+
+pub(crate) fn lint_options<TQuery: QueryDescriptor>(
+    lints: &mut Vec<PermissionLint>,
+    allow: Option<&Vec<String>>,
+    deny: Option<&Vec<String>>,
+    parse_allow: impl Fn(&str) -> Result<Option<TQuery::AllowDesc>, AnyError>,
+    parse_deny: impl Fn(&str) -> Result<TQuery::DenyDesc, AnyError>,
+) {
+    let name = TQuery::flag_name();
+    let invalid = |item: &String, err: AnyError| PermissionLint {
+        severity: LintSeverity::Error,
+        kind: LintKind::Invalid,
+        flag_name: name,
+        entry: item.clone(),
+        related: None,
+        message: format!("'{item}' is not a valid {name} entry: {err}"),
+    };
+    let mut allow_entries = Vec::new();
+    for item in allow.into_iter().flatten() {
+        match parse_allow(item) {
+            Ok(Some(desc)) => allow_entries.push((item.clone(), desc)),
+            Ok(None) => lints.push(PermissionLint {
+                severity: LintSeverity::Warning,
+                kind: LintKind::Unresolved,
+                flag_name: name,
+                entry: item.clone(),
+                related: None,
+                message: format!("'{item}' could not be resolved and will be ignored"),
+            }),
+            Err(err) => lints.push(invalid(item, err)),
+        }
+    }
+    let mut deny_entries = Vec::new();
+    for item in deny.into_iter().flatten() {
+        match parse_deny(item) {
+            Ok(desc) => deny_entries.push((item.clone(), desc)),
+            Err(err) => lints.push(invalid(item, err)),
+        }
+    }
+    lint_entries::<TQuery>(
+        lints,
+        allow.is_some_and(|items| items.is_empty()),
+        &allow_entries,
+        deny.is_some_and(|items| items.is_empty()),
+        &deny_entries,
+    );
+}
+
+fn lint_entries<TQuery: QueryDescriptor>(
+    lints: &mut Vec<PermissionLint>,
+    allow_global: bool,
+    allow: &[(String, TQuery::AllowDesc)],
+    deny_global: bool,
+    deny: &[(String, TQuery::DenyDesc)],
+) {
+    let name = TQuery::flag_name();
+    let mut push = |severity, kind, entry: &str, related: Option<&str>, message| {
+        lints.push(PermissionLint {
+            severity,
+            kind,
+            flag_name: name,
+            entry: entry.to_string(),
+            related: related.map(|related| related.to_string()),
+            message,
+        })
+    };
+
+    let mut unique_allow: Vec<&(String, TQuery::AllowDesc)> = Vec::new();
+    for entry in allow {
+        match unique_allow.iter().find(|other| other.1 == entry.1) {
+            Some(first) => push(
+                LintSeverity::Info,
+                LintKind::Duplicate,
+                &entry.0,
+                Some(&first.0),
+                format!("'{}' is allowed more than once", entry.0),
+            ),
+            None => unique_allow.push(entry),
+        }
+    }
+    let mut unique_deny: Vec<&(String, TQuery::DenyDesc)> = Vec::new();
+    for entry in deny {
+        match unique_deny.iter().find(|other| other.1 == entry.1) {
+            Some(first) => push(
+                LintSeverity::Info,
+                LintKind::Duplicate,
+                &entry.0,
+                Some(&first.0),
+                format!("'{}' is denied more than once", entry.0),
+            ),
+            None => unique_deny.push(entry),
+        }
+    }
+
+    for (label, desc) in unique_allow.iter().copied() {
+        if deny_global {
+            push(
+                LintSeverity::Warning,
+                LintKind::Shadowed,
+                label,
+                None,
+                format!("'{label}' is allowed, but all {name} access is denied"),
+            );
+            continue;
+        }
+        if allow_global {
+            push(
+                LintSeverity::Info,
+                LintKind::Redundant,
+                label,
+                None,
+                format!("'{label}' is already covered by allowing all {name} access"),
+            );
+        }
+        let query = TQuery::from_allow(desc);
+        if let Some((other, _)) = unique_allow
+            .iter()
+            .find(|(_, other)| other != desc && query.matches_allow(other))
+        {
+            push(
+                LintSeverity::Info,
+                LintKind::Redundant,
+                label,
+                Some(other),
+                format!("'{label}' is already covered by '{other}'"),
+            );
+        }
+        if let Some((other, _)) = unique_deny
+            .iter()
+            .find(|(_, deny)| query.matches_deny(deny))
+        {
+            push(
+                LintSeverity::Warning,
+                LintKind::Shadowed,
+                label,
+                Some(other),
+                format!("'{label}' is allowed, but denied by '{other}'"),
+            );
+            continue;
+        }
+        for (other, _) in unique_deny
+            .iter()
+            .filter(|(_, deny)| query.stronger_than_deny(deny) || query.overlaps_deny(deny))
+        {
+            push(
+                LintSeverity::Info,
+                LintKind::PartiallyShadowed,
+                label,
+                Some(other),
+                format!("'{label}' is allowed, but '{other}' within it is denied"),
+            );
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::fmt;
+
+use crate::error::type_error;
+use crate::AnyError;
+use crate::Url;
+
+/// The package registries with their own specifier scheme.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum PackageRegistry {
+    Npm,
+    Jsr,
+}
+
+impl PackageRegistry {
+    pub fn from_scheme(scheme: &str) -> Option<Self> {
+        match scheme {
+            "npm" => Some(Self::Npm),
+            "jsr" => Some(Self::Jsr),
+            _ => None,
+        }
+    }
+
+    pub fn scheme(&self) -> &'static str {
+        match self {
+            Self::Npm => "npm",
+            Self::Jsr => "jsr",
+        }
+    }
+}
+
+/// A package of a registry, or a set of them: `npm:lodash`, every package of
+/// a scope with `jsr:@std/*`, or every package of the registry with `npm:*`.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct PackageDescriptor(pub PackageRegistry, pub String);
+
+impl PackageDescriptor {
+    /// Gets if `text` is written with a package scheme, e.g. `npm:lodash`.
+    pub fn is_package_specifier(text: &str) -> bool {
+        text.split_once(':')
+            .is_some_and(|(scheme, _)| PackageRegistry::from_scheme(scheme).is_some())
+    }
+
+    /// Parses a package pattern like `npm:lodash`, `jsr:@std/*` or `npm:*`.
+    /// Versions aren't supported, every version of a package is covered.
+    pub fn parse(text: &str) -> Result<Self, AnyError> {
+        let invalid = |reason: &str| type_error(format!("Invalid package '{text}': {reason}"));
+        let Some((registry, name)) = text
+            .split_once(':')
+            .and_then(|(scheme, name)| Some((PackageRegistry::from_scheme(scheme)?, name)))
+        else {
+            return Err(invalid("expected a npm: or jsr: package"));
+        };
+        if name == "*" {
+            return Ok(Self(registry, name.to_string()));
+        }
+        let (scope, package) = match name.strip_prefix('@') {
+            Some(scoped) => match scoped.split_once('/') {
+                Some((scope, package)) => (Some(scope), package),
+                None => return Err(invalid("scoped packages are written as @scope/name")),
+            },
+            None => (None, name),
+        };
+        if registry == PackageRegistry::Jsr && scope.is_none() {
+            return Err(invalid("jsr packages are always scoped"));
+        }
+        let valid_part = |part: &str| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '~'))
+        };
+        let valid_package = valid_part(package) || (scope.is_some() && package == "*");
+        if scope.is_some_and(|scope| !valid_part(scope)) || !valid_package {
+            // versions and paths end up here as well
+            return Err(invalid("expected a package name without a version or path"));
+        }
+        Ok(Self(registry, name.to_string()))
+    }
+
+    /// The package a specifier like `npm:@types/node@^20/fs` imports.
+    pub fn from_url(url: &Url) -> Result<Self, AnyError> {
+        let registry = PackageRegistry::from_scheme(url.scheme())
+            .ok_or_else(|| type_error(format!("Not a package specifier: '{url}'")))?;
+        let path = url.path().trim_start_matches('/');
+        let name_len = match path.strip_prefix('@') {
+            Some(scoped) => scoped.find('/').map(|slash| {
+                let rest = &scoped[slash + 1..];
+                slash + 2 + rest.find(['@', '/']).unwrap_or(rest.len())
+            }),
+            None => Some(path.find(['@', '/']).unwrap_or(path.len())),
+        };
+        match name_len {
+            Some(len) if len > 0 => Self::parse(&format!("{}:{}", registry.scheme(), &path[..len])),
+            _ => Err(type_error(format!("Missing package name in '{url}'"))),
+        }
+    }
+
+    /// Gets if every package matched by `other` is matched by this one.
+    pub fn covers(&self, other: &PackageDescriptor) -> bool {
+        if self.0 != other.0 {
+            return false;
+        }
+        match self.1.strip_suffix('*') {
+            Some("") => true,
+            Some(scope) => other.1.starts_with(scope),
+            None => self.1 == other.1,
+        }
+    }
+}
+
+impl fmt::Display for PackageDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.0.scheme(), self.1)
+    }
+}
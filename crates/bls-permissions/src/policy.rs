@@ -0,0 +1,357 @@
+use std::fmt;
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::AllowRunDescriptorParseResult;
+use crate::AnyError;
+use crate::PermissionDescriptorParser;
+use crate::Permissions;
+use crate::PermissionsOptions;
+
+/// The on-disk format of a permissions policy file.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PolicyFormat {
+    Toml,
+    Json,
+}
+
+impl PolicyFormat {
+    /// Picks the format from the extension of the policy file.
+    pub fn from_path(path: &Path) -> Result<Self, AnyError> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => Ok(Self::Toml),
+            Some(ext) if ext.eq_ignore_ascii_case("json") => Ok(Self::Json),
+            _ => Err(PolicyError::new(
+                path.display().to_string(),
+                1,
+                1,
+                "unsupported policy file extension, expected .toml or .json",
+            )
+            .into()),
+        }
+    }
+}
+
+impl fmt::Display for PolicyFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyFormat::Toml => f.pad("toml"),
+            PolicyFormat::Json => f.pad("json"),
+        }
+    }
+}
+
+/// An error found while loading a policy file, positioned at the offending
+/// entry. Line and column are 1-based.
+#[derive(Debug, Error)]
+#[error("{file}:{line}:{column}: {message}")]
+pub struct PolicyError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    /// The `PermissionsOptions` field the error belongs to, if any.
+    pub field: Option<String>,
+    /// The descriptor that failed to parse, if any.
+    pub descriptor: Option<String>,
+    pub message: String,
+}
+
+impl PolicyError {
+    fn new(file: String, line: usize, column: usize, message: impl Into<String>) -> Self {
+        Self {
+            file,
+            line,
+            column,
+            field: None,
+            descriptor: None,
+            message: message.into(),
+        }
+    }
+}
+
+impl PermissionsOptions {
+    /// Loads the options from a `.toml` or `.json` policy file. Every
+    /// descriptor is validated with `parser`, so a successfully loaded policy
+    /// can be passed to `Permissions::from_options`.
+    pub fn from_policy_file(
+        parser: &dyn PermissionDescriptorParser,
+        path: &Path,
+    ) -> Result<Self, AnyError> {
+        Self::default().with_policy_file(parser, path)
+    }
+
+    /// Same as `from_policy_file`, for an in-memory policy. `origin` is used
+    /// as the file name in errors.
+    pub fn from_policy_str(
+        parser: &dyn PermissionDescriptorParser,
+        text: &str,
+        format: PolicyFormat,
+        origin: &str,
+    ) -> Result<Self, AnyError> {
+        Self::default().with_policy_str(parser, text, format, origin)
+    }
+
+    /// Same as `from_policy_file`, but the policy may also use the system
+    /// info kinds and custom permission kinds registered on these options,
+    /// which the loaded options keep.
+    pub fn with_policy_file(
+        self,
+        parser: &dyn PermissionDescriptorParser,
+        path: &Path,
+    ) -> Result<Self, AnyError> {
+        let format = PolicyFormat::from_path(path)?;
+        let text = std::fs::read_to_string(path).map_err(|err| {
+            PolicyError::new(
+                path.display().to_string(),
+                1,
+                1,
+                format!("failed to read policy file: {err}"),
+            )
+        })?;
+        self.with_policy_str(parser, &text, format, &path.display().to_string())
+    }
+
+    /// Same as `with_policy_file`, for an in-memory policy.
+    pub fn with_policy_str(
+        self,
+        parser: &dyn PermissionDescriptorParser,
+        text: &str,
+        format: PolicyFormat,
+        origin: &str,
+    ) -> Result<Self, AnyError> {
+        let source = PolicySource {
+            text,
+            format,
+            origin,
+        };
+        let value = source.parse_value()?;
+        let serde_json::Value::Object(map) = &value else {
+            return Err(source
+                .error_at(0, "the policy must be a table of options")
+                .into());
+        };
+        let defaults = default_fields();
+        for key in map.keys() {
+            if !defaults.contains_key(key) {
+                let mut err = source.error_at(
+                    source.find_key(key).unwrap_or(0),
+                    format!("unknown policy field '{key}'"),
+                );
+                err.field = Some(key.clone());
+                return Err(err.into());
+            }
+        }
+        for (key, field) in map {
+            let mut fields = defaults.clone();
+            fields.insert(key.clone(), field.clone());
+            if let Err(err) =
+                serde_json::from_value::<PermissionsOptions>(serde_json::Value::Object(fields))
+            {
+                let mut err = source.error_at(
+                    source.find_key(key).unwrap_or(0),
+                    format!("invalid value for '{key}': {err}"),
+                );
+                err.field = Some(key.clone());
+                return Err(err.into());
+            }
+        }
+        let mut fields = defaults;
+        fields.extend(map.clone());
+        let mut opts: PermissionsOptions =
+            serde_json::from_value(serde_json::Value::Object(fields))
+                .map_err(|err| source.error_at(0, format!("invalid policy: {err}")))?;
+        opts.sys_kinds = self.sys_kinds;
+        opts.custom_kinds = self.custom_kinds;
+        source.validate(parser, &opts)?;
+        Ok(opts)
+    }
+}
+
+impl Permissions {
+    /// Builds permissions from a `.toml` or `.json` policy file.
+    pub fn from_policy_file(
+        parser: &dyn PermissionDescriptorParser,
+        path: &Path,
+    ) -> Result<Self, AnyError> {
+        Self::from_options(parser, &PermissionsOptions::from_policy_file(parser, path)?)
+    }
+}
+
+/// Every field accepted in a policy file, with the value it takes when the
+/// policy leaves it out. `PermissionsOptions` itself requires every field.
+fn default_fields() -> serde_json::Map<String, serde_json::Value> {
+    match serde_json::to_value(PermissionsOptions::default()) {
+        Ok(serde_json::Value::Object(map)) => map,
+        _ => serde_json::Map::new(),
+    }
+}
+
+struct PolicySource<'a> {
+    text: &'a str,
+    format: PolicyFormat,
+    origin: &'a str,
+}
+
+impl PolicySource<'_> {
+    fn parse_value(&self) -> Result<serde_json::Value, PolicyError> {
+        match self.format {
+            PolicyFormat::Toml => {
+                let value = toml::from_str::<toml::Value>(self.text).map_err(|err| {
+                    let offset = err.span().map(|span| span.start).unwrap_or(0);
+                    self.error_at(offset, err.message().to_string())
+                })?;
+                serde_json::to_value(value).map_err(|err| self.error_at(0, err.to_string()))
+            }
+            PolicyFormat::Json => serde_json::from_str(self.text).map_err(|err| {
+                PolicyError::new(
+                    self.origin.to_string(),
+                    err.line().max(1),
+                    err.column().max(1),
+                    err.to_string(),
+                )
+            }),
+        }
+    }
+
+    fn validate(
+        &self,
+        parser: &dyn PermissionDescriptorParser,
+        opts: &PermissionsOptions,
+    ) -> Result<(), PolicyError> {
+        for (name, allow, deny) in opts.lists() {
+            for (list, items) in [("allow", allow), ("deny", deny)] {
+                let Some(items) = items else {
+                    continue;
+                };
+                let field = format!("{list}_{name}");
+                for (index, item) in items.iter().enumerate() {
+                    if let Err(err) = parse_entry(parser, opts, name, list == "allow", item) {
+                        let offset = self
+                            .find_entry(&field, index)
+                            .or_else(|| self.find_key(&field))
+                            .or_else(|| self.find_key("custom"))
+                            .unwrap_or(0);
+                        let mut err =
+                            self.error_at(offset, format!("invalid {field} entry '{item}': {err}"));
+                        err.field = Some(field);
+                        err.descriptor = Some(item.clone());
+                        return Err(err);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn error_at(&self, offset: usize, message: impl Into<String>) -> PolicyError {
+        let (line, column) = line_column(self.text, offset);
+        PolicyError::new(self.origin.to_string(), line, column, message)
+    }
+
+    /// Finds the byte offset of a top level key.
+    fn find_key(&self, key: &str) -> Option<usize> {
+        let quoted = format!("\"{key}\"");
+        let mut offset = 0;
+        for line in self.text.split_inclusive('\n') {
+            let trimmed = line.trim_start();
+            let indent = line.len() - trimmed.len();
+            match self.format {
+                PolicyFormat::Toml => {
+                    let rest = trimmed
+                        .strip_prefix(key)
+                        .or_else(|| trimmed.strip_prefix(quoted.as_str()));
+                    if let Some(rest) = rest {
+                        if rest.trim_start().starts_with('=') {
+                            return Some(offset + indent);
+                        }
+                    }
+                }
+                PolicyFormat::Json => {
+                    if let Some(pos) = line.find(&quoted) {
+                        if line[pos + quoted.len()..].trim_start().starts_with(':') {
+                            return Some(offset + pos);
+                        }
+                    }
+                }
+            }
+            offset += line.len();
+        }
+        None
+    }
+
+    /// Finds the byte offset of the `index`th string of the list under `key`.
+    fn find_entry(&self, key: &str, index: usize) -> Option<usize> {
+        let start = self.find_key(key)?;
+        let bytes = self.text.as_bytes();
+        // skip past the key itself
+        let mut pos = start + self.text[start..].find(['=', ':']).map(|p| p + 1)?;
+        let mut seen = 0;
+        while pos < bytes.len() {
+            match bytes[pos] {
+                b']' | b'}' => return None,
+                b'#' if self.format == PolicyFormat::Toml => {
+                    pos += self.text[pos..].find('\n').unwrap_or(bytes.len() - pos);
+                }
+                quote @ (b'"' | b'\'') => {
+                    if seen == index {
+                        return Some(pos);
+                    }
+                    seen += 1;
+                    pos += 1;
+                    while pos < bytes.len() && bytes[pos] != quote {
+                        if quote == b'"' && bytes[pos] == b'\\' {
+                            pos += 1;
+                        }
+                        pos += 1;
+                    }
+                    pos += 1;
+                }
+                _ => pos += 1,
+            }
+        }
+        None
+    }
+}
+
+fn parse_entry(
+    parser: &dyn PermissionDescriptorParser,
+    opts: &PermissionsOptions,
+    name: &str,
+    is_allow: bool,
+    text: &str,
+) -> Result<(), AnyError> {
+    match name {
+        "read" => parser.parse_read_descriptor(text).map(|_| ()),
+        "write" => parser.parse_write_descriptor(text).map(|_| ()),
+        "net" => parser.parse_net_descriptor(text).map(|_| ()),
+        "env" => parser.parse_env_descriptor(text).map(|_| ()),
+        "sys" => opts
+            .sys_kinds
+            .resolve(parser.parse_sys_descriptor(text)?)
+            .map(|_| ()),
+        "ffi" => parser.parse_ffi_descriptor(text).map(|_| ()),
+        "import" => parser.parse_import_descriptor(text).map(|_| ()),
+        "unix_socket" => parser.parse_unix_socket_descriptor(text).map(|_| ()),
+        "run" if text.is_empty() => Err(anyhow::anyhow!("empty command name not allowed")),
+        "run" if is_allow => match parser.parse_allow_run_descriptor(text)? {
+            // unresolved commands are dropped by `from_options`, same as a flag
+            AllowRunDescriptorParseResult::Unresolved(_)
+            | AllowRunDescriptorParseResult::Descriptor(_) => Ok(()),
+        },
+        "run" => parser.parse_deny_run_descriptor(text).map(|_| ()),
+        _ => opts.custom_kinds.parse_entry(name, text),
+    }
+}
+
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let offset = offset.min(text.len());
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rfind('\n')
+        .map(|p| before[p + 1..].chars().count())
+        .unwrap_or_else(|| before.chars().count())
+        + 1;
+    (line, column)
+}
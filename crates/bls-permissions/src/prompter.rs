@@ -6,6 +6,8 @@ pub enum PromptResponse {
     Allow,
     Deny,
     AllowAll,
+    /// Allows the prompted access only.
+    AllowOnce,
     #[cfg(target_family = "wasm")]
     Yield,
 }
@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::error::custom_error;
+use crate::AnyError;
+
+const QUOTA_WINDOW: Duration = Duration::from_secs(1);
+
+/// Limits attached to an allow entry. Unset limits aren't enforced.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Quota {
+    /// How many checks are allowed within a second.
+    pub checks_per_second: Option<u32>,
+    /// How many checks are allowed in total.
+    pub total_checks: Option<u64>,
+    /// How many bytes the host can report with `report_bytes` in total.
+    pub total_bytes: Option<u64>,
+}
+
+impl Quota {
+    /// Combines two quotas, keeping the lowest of each limit.
+    pub fn strictest(&self, other: &Self) -> Self {
+        fn min<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            }
+        }
+        Self {
+            checks_per_second: min(self.checks_per_second, other.checks_per_second),
+            total_checks: min(self.total_checks, other.total_checks),
+            total_bytes: min(self.total_bytes, other.total_bytes),
+        }
+    }
+}
+
+/// A quota and what was used of it so far.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuotaState {
+    pub quota: Quota,
+    pub checks: u64,
+    pub bytes: u64,
+    /// When the current window of `checks_per_second` started, as a time since
+    /// the unix epoch.
+    pub window_start: Duration,
+    pub window_checks: u32,
+}
+
+impl QuotaState {
+    pub fn new(quota: Quota) -> Self {
+        Self {
+            quota,
+            ..Default::default()
+        }
+    }
+
+    pub fn remaining_checks(&self) -> Option<u64> {
+        self.quota
+            .total_checks
+            .map(|total| total.saturating_sub(self.checks))
+    }
+
+    pub fn remaining_bytes(&self) -> Option<u64> {
+        self.quota
+            .total_bytes
+            .map(|total| total.saturating_sub(self.bytes))
+    }
+
+    /// Gets why one more check at `now` would exceed the quota, if it would.
+    pub(crate) fn check_exceeded(&self, now: Duration) -> Option<String> {
+        if self.remaining_checks() == Some(0) {
+            return Some(format!(
+                "all {} checks were used",
+                self.quota.total_checks.unwrap_or_default()
+            ));
+        }
+        let per_second = self.quota.checks_per_second?;
+        let window_checks = match now.saturating_sub(self.window_start) < QUOTA_WINDOW {
+            true => self.window_checks,
+            false => 0,
+        };
+        (window_checks >= per_second).then(|| format!("more than {per_second} checks per second"))
+    }
+
+    pub(crate) fn record_check(&mut self, now: Duration) {
+        if now.saturating_sub(self.window_start) >= QUOTA_WINDOW {
+            self.window_start = now;
+            self.window_checks = 0;
+        }
+        self.window_checks += 1;
+        self.checks += 1;
+    }
+
+    /// Gets why reporting `bytes` more would exceed the quota, if it would.
+    pub(crate) fn bytes_exceeded(&self, bytes: u64) -> Option<String> {
+        let remaining = self.remaining_bytes()?;
+        (bytes > remaining).then(|| {
+            format!(
+                "{bytes} bytes reported with only {remaining} of {} left",
+                self.quota.total_bytes.unwrap_or_default()
+            )
+        })
+    }
+
+    pub(crate) fn record_bytes(&mut self, bytes: u64) {
+        self.bytes = self.bytes.saturating_add(bytes);
+    }
+}
+
+/// The quotas of allow entries, keyed by the entry as written in the
+/// `allow_<kind>` option.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuotaOptions {
+    pub net: BTreeMap<String, Quota>,
+    pub write: BTreeMap<String, Quota>,
+    pub run: BTreeMap<String, Quota>,
+}
+
+impl QuotaOptions {
+    pub fn is_empty(&self) -> bool {
+        self.net.is_empty() && self.write.is_empty() && self.run.is_empty()
+    }
+
+    /// Combines two sets of quotas, keeping the lowest of each limit of an
+    /// entry.
+    pub fn strictest(&self, other: &Self) -> Self {
+        fn merge(
+            a: &BTreeMap<String, Quota>,
+            b: &BTreeMap<String, Quota>,
+        ) -> BTreeMap<String, Quota> {
+            let mut merged = a.clone();
+            for (entry, quota) in b {
+                merged
+                    .entry(entry.clone())
+                    .and_modify(|merged| *merged = merged.strictest(quota))
+                    .or_insert(*quota);
+            }
+            merged
+        }
+        Self {
+            net: merge(&self.net, &other.net),
+            write: merge(&self.write, &other.write),
+            run: merge(&self.run, &other.run),
+        }
+    }
+}
+
+pub(crate) fn quota_exceeded_error(name: &str, display_name: &str, reason: &str) -> AnyError {
+    custom_error(
+        "QuotaExceeded",
+        format!("Quota exceeded for {name} access to {display_name}: {reason}"),
+    )
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::net::ToSocketAddrs;
+
+use crate::error::custom_error;
+use crate::format_display_name;
+use crate::AllowPartial;
+use crate::AnyError;
+use crate::Host;
+use crate::NetDescriptor;
+use crate::NetPort;
+use crate::PermissionState;
+use crate::QueryDescriptor;
+use crate::UnaryPermission;
+
+/// Resolves hostnames to the addresses a connection would be made to. It's
+/// installed on a container with `BlsPermissionsContainer::with_net_resolver`.
+pub trait NetResolver: Debug + Send + Sync {
+    fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>, AnyError>;
+}
+
+/// Resolves with the system resolver. It isn't available on wasm, where the
+/// runtime must install its own resolver.
+#[derive(Debug)]
+pub struct SystemResolver;
+
+impl NetResolver for SystemResolver {
+    fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>, AnyError> {
+        Ok((host, port).to_socket_addrs()?.collect())
+    }
+}
+
+/// Resolves hostnames from a fixed table, for tests.
+#[derive(Clone, Debug, Default)]
+pub struct FakeResolver {
+    hosts: HashMap<String, Vec<IpAddr>>,
+}
+
+impl FakeResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_host(mut self, host: &str, ips: impl IntoIterator<Item = IpAddr>) -> Self {
+        self.hosts
+            .insert(host.to_ascii_lowercase(), ips.into_iter().collect());
+        self
+    }
+}
+
+impl NetResolver for FakeResolver {
+    fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>, AnyError> {
+        let ips = self
+            .hosts
+            .get(&host.to_ascii_lowercase())
+            .ok_or_else(|| custom_error("NotFound", format!("failed to resolve '{host}'")))?;
+        Ok(ips.iter().map(|ip| SocketAddr::new(*ip, port)).collect())
+    }
+}
+
+impl UnaryPermission<NetDescriptor> {
+    /// The second phase of a net check: `desc` must already be granted, and
+    /// none of `addrs` it resolved to may be denied by an IP or CIDR entry.
+    /// It never prompts.
+    pub fn check_resolved(
+        &self,
+        desc: &NetDescriptor,
+        addrs: &[SocketAddr],
+    ) -> Result<(), AnyError> {
+        if self.query_desc(Some(desc), AllowPartial::TreatAsGranted) != PermissionState::Granted {
+            return Err(PermissionState::error(NetDescriptor::flag_name(), || {
+                Some(format_display_name(desc.display_name()))
+            }));
+        }
+        self.check_resolved_addrs(desc, addrs)
+    }
+
+    /// Same as `check_resolved`, for a `desc` the caller has just checked.
+    /// The grant isn't queried again, so a one-time allow or the last use of
+    /// a count-limited grant still covers the addresses, and no use or quota
+    /// is taken a second time.
+    pub(crate) fn check_resolved_addrs(
+        &self,
+        desc: &NetDescriptor,
+        addrs: &[SocketAddr],
+    ) -> Result<(), AnyError> {
+        for addr in addrs {
+            let resolved = NetDescriptor(
+                Host::Ip(addr.ip()),
+                Some(NetPort::from(addr.port())),
+                desc.2.clone(),
+            );
+            if self.is_flag_denied(Some(&resolved)) || self.is_prompt_denied(Some(&resolved)) {
+                return Err(custom_error(
+                    "PermissionDenied",
+                    format!(
+                        "Requires net access to {}, which resolved to the denied address {}",
+                        format_display_name(desc.display_name()),
+                        format_display_name(resolved.display_name()),
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+use std::fmt;
+use std::hash::Hash;
+use std::hash::Hasher;
+
+use regex::Regex;
+use serde::de;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+
+use crate::error::type_error;
+use crate::AnyError;
+
+/// Restricts the arguments an allowed command may be run with. It's written
+/// in square brackets after the command, e.g. `git[sub=status,log]`.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum RunArgsPattern {
+    /// `git[sub=status,log]`, the first argument is one of the subcommands.
+    Subcommands(Vec<String>),
+    /// `git[prefix=remote -v]`, the arguments start with these ones.
+    Prefix(Vec<String>),
+    /// `git[args=status --short]`, exactly these arguments.
+    Args(Vec<String>),
+    /// `git[regex=log( --oneline)?]`, the arguments joined by spaces match
+    /// the whole pattern. Arguments that are empty or contain whitespace or
+    /// `"` are quoted, as in prompts, so `"a b"` is never read as two
+    /// arguments.
+    Regex(RunArgsRegex),
+}
+
+impl RunArgsPattern {
+    const KEYS: [&'static str; 4] = ["sub", "prefix", "args", "regex"];
+
+    /// Splits a run descriptor like `git[sub=status]` into the command and
+    /// its arguments pattern, if any.
+    pub fn split_descriptor(text: &str) -> Result<(&str, Option<Self>), AnyError> {
+        let start = Self::KEYS
+            .iter()
+            .filter_map(|key| text.find(&format!("[{key}=")))
+            .min();
+        match start {
+            Some(start) if text.ends_with(']') => {
+                let pattern = Self::parse(&text[start + 1..text.len() - 1])?;
+                Ok((&text[..start], Some(pattern)))
+            }
+            Some(_) => Err(type_error(format!(
+                "Invalid run descriptor '{text}', missing closing ']'"
+            ))),
+            None => Ok((text, None)),
+        }
+    }
+
+    /// Parses the part between the square brackets, e.g. `sub=status,log`.
+    pub fn parse(text: &str) -> Result<Self, AnyError> {
+        let Some((key, value)) = text.split_once('=') else {
+            return Err(type_error(format!(
+                "Invalid run arguments pattern '{text}'"
+            )));
+        };
+        let words = || {
+            value
+                .split_whitespace()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+        let pattern = match key {
+            "sub" => {
+                let subcommands = value
+                    .split(',')
+                    .map(|sub| sub.trim().to_string())
+                    .collect::<Vec<_>>();
+                if subcommands.iter().any(|sub| sub.is_empty()) {
+                    return Err(type_error(format!("Invalid subcommand list '{value}'")));
+                }
+                RunArgsPattern::Subcommands(subcommands)
+            }
+            "prefix" if value.trim().is_empty() => {
+                return Err(type_error("Empty run arguments prefix not allowed"));
+            }
+            "prefix" => RunArgsPattern::Prefix(words()),
+            "args" => RunArgsPattern::Args(words()),
+            "regex" => RunArgsPattern::Regex(RunArgsRegex::new(value)?),
+            _ => {
+                return Err(type_error(format!(
+                    "Unknown run arguments pattern '{key}', expected one of: {}",
+                    Self::KEYS.join(", ")
+                )));
+            }
+        };
+        Ok(pattern)
+    }
+
+    pub fn matches(&self, args: &[String]) -> bool {
+        match self {
+            RunArgsPattern::Subcommands(subcommands) => args
+                .first()
+                .is_some_and(|first| subcommands.contains(first)),
+            RunArgsPattern::Prefix(prefix) => args.starts_with(prefix),
+            RunArgsPattern::Args(expected) => args == expected,
+            RunArgsPattern::Regex(regex) => regex.is_match(&format_args(args)),
+        }
+    }
+}
+
+impl fmt::Display for RunArgsPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunArgsPattern::Subcommands(subcommands) => write!(f, "sub={}", subcommands.join(",")),
+            RunArgsPattern::Prefix(prefix) => write!(f, "prefix={}", prefix.join(" ")),
+            RunArgsPattern::Args(args) => write!(f, "args={}", args.join(" ")),
+            RunArgsPattern::Regex(regex) => write!(f, "regex={}", regex.source),
+        }
+    }
+}
+
+impl Serialize for RunArgsPattern {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for RunArgsPattern {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let text = String::deserialize(deserializer)?;
+        RunArgsPattern::parse(&text).map_err(de::Error::custom)
+    }
+}
+
+/// A `regex=` pattern, compiled once. Patterns compare and hash by their
+/// source, as they are used as hash set keys.
+#[derive(Clone, Debug)]
+pub struct RunArgsRegex {
+    source: String,
+    regex: Regex,
+}
+
+impl RunArgsRegex {
+    pub fn new(source: &str) -> Result<Self, AnyError> {
+        // anchor the pattern so that `log` doesn't also allow `log; rm -rf`
+        let regex = Regex::new(&format!("^(?:{source})$"))
+            .map_err(|err| type_error(format!("Invalid run arguments regex '{source}': {err}")))?;
+        Ok(Self {
+            source: source.to_string(),
+            regex,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.regex.is_match(text)
+    }
+}
+
+impl PartialEq for RunArgsRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for RunArgsRegex {}
+
+impl Hash for RunArgsRegex {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.source.hash(state);
+    }
+}
+
+/// Formats a command line for display in prompts, quoting arguments that
+/// would otherwise be ambiguous.
+pub(crate) fn format_command_line(cmd: &str, args: &[String]) -> String {
+    if args.is_empty() {
+        return cmd.to_string();
+    }
+    format!("{cmd} {}", format_args(args))
+}
+
+/// Joins arguments by spaces, quoting the empty ones and those containing
+/// whitespace or `"`.
+fn format_args(args: &[String]) -> String {
+    let args = args.iter().map(|arg| {
+        if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || c == '"') {
+            format!("{arg:?}")
+        } else {
+            arg.clone()
+        }
+    });
+    args.collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,145 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::error::type_error;
+use crate::AnyError;
+use crate::BlsPermissionsContainer;
+use crate::CustomKinds;
+use crate::CustomPermissions;
+use crate::EnvDescriptor;
+use crate::FfiQueryDescriptor;
+use crate::ImportDescriptor;
+use crate::NetDescriptor;
+use crate::PermissionDescriptorParser;
+use crate::PermissionState;
+use crate::Permissions;
+use crate::ReadQueryDescriptor;
+use crate::RunQueryDescriptor;
+use crate::SpecifierPolicy;
+use crate::SysDescriptor;
+use crate::SysKinds;
+use crate::UnaryPermission;
+use crate::UnixSocketDescriptor;
+use crate::WriteQueryDescriptor;
+
+/// The snapshot format version written by this crate. Bump it whenever the
+/// shape of `PermissionsSnapshot` changes in an incompatible way.
+pub const PERMISSIONS_SNAPSHOT_VERSION: u32 = 2;
+
+/// A serializable copy of the live state of a `Permissions`, including
+/// everything granted or denied at a prompt.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PermissionsSnapshot {
+    pub version: u32,
+    pub read: UnaryPermission<ReadQueryDescriptor>,
+    pub write: UnaryPermission<WriteQueryDescriptor>,
+    pub net: UnaryPermission<NetDescriptor>,
+    pub env: UnaryPermission<EnvDescriptor>,
+    pub sys: UnaryPermission<SysDescriptor>,
+    pub run: UnaryPermission<RunQueryDescriptor>,
+    pub ffi: UnaryPermission<FfiQueryDescriptor>,
+    pub import: UnaryPermission<ImportDescriptor>,
+    #[serde(default)]
+    pub unix_socket: UnaryPermission<UnixSocketDescriptor>,
+    pub all: UnitPermissionSnapshot,
+    #[serde(default)]
+    pub specifier_policy: SpecifierPolicy,
+    #[serde(default)]
+    pub sys_kinds: SysKinds,
+    /// The runtime-registered kinds, taken over by the restored permissions
+    /// once the kinds are registered on them again.
+    #[serde(default)]
+    pub custom: CustomPermissions,
+}
+
+/// The mutable part of a `UnitPermission`, its name and description are fixed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct UnitPermissionSnapshot {
+    pub state: PermissionState,
+    pub prompt: bool,
+}
+
+impl Permissions {
+    pub fn snapshot(&self) -> PermissionsSnapshot {
+        PermissionsSnapshot {
+            version: PERMISSIONS_SNAPSHOT_VERSION,
+            read: self.read.clone(),
+            write: self.write.clone(),
+            net: self.net.clone(),
+            env: self.env.clone(),
+            sys: self.sys.clone(),
+            run: self.run.clone(),
+            ffi: self.ffi.clone(),
+            import: self.import.clone(),
+            unix_socket: self.unix_socket.clone(),
+            all: UnitPermissionSnapshot {
+                state: self.all.state,
+                prompt: self.all.prompt,
+            },
+            specifier_policy: self.specifier_policy,
+            sys_kinds: self.sys_kinds.clone(),
+            custom: self.custom.clone(),
+        }
+    }
+
+    /// Rebuilds permissions from a snapshot taken with `snapshot`. Fails if
+    /// the snapshot was written in a different format version. Count-limited
+    /// grants with no uses left are dropped.
+    pub fn from_snapshot(snapshot: PermissionsSnapshot) -> Result<Self, AnyError> {
+        if snapshot.version != PERMISSIONS_SNAPSHOT_VERSION {
+            return Err(type_error(format!(
+                "Unsupported permissions snapshot version {}, expected {}",
+                snapshot.version, PERMISSIONS_SNAPSHOT_VERSION
+            )));
+        }
+        let mut all = Permissions::new_all(false);
+        all.state = snapshot.all.state;
+        all.prompt = snapshot.all.prompt;
+        let mut perms = Permissions {
+            read: snapshot.read,
+            write: snapshot.write,
+            net: snapshot.net,
+            env: snapshot.env,
+            sys: snapshot.sys,
+            run: snapshot.run,
+            ffi: snapshot.ffi,
+            import: snapshot.import,
+            unix_socket: snapshot.unix_socket,
+            all,
+            specifier_policy: snapshot.specifier_policy,
+            sys_kinds: snapshot.sys_kinds,
+            custom_kinds: CustomKinds::default(),
+            custom: snapshot.custom,
+        };
+        perms.read.drop_used_up();
+        perms.write.drop_used_up();
+        perms.net.drop_used_up();
+        perms.env.drop_used_up();
+        perms.sys.drop_used_up();
+        perms.run.drop_used_up();
+        perms.ffi.drop_used_up();
+        perms.import.drop_used_up();
+        perms.unix_socket.drop_used_up();
+        Ok(perms)
+    }
+}
+
+impl BlsPermissionsContainer {
+    /// Takes a snapshot of the current permissions state.
+    pub fn snapshot(&self) -> PermissionsSnapshot {
+        self.inner.lock().snapshot()
+    }
+
+    /// Creates a container with the state captured in `snapshot`.
+    pub fn restore(
+        descriptor_parser: Arc<dyn PermissionDescriptorParser>,
+        snapshot: PermissionsSnapshot,
+    ) -> Result<Self, AnyError> {
+        Ok(Self::new(
+            descriptor_parser,
+            Permissions::from_snapshot(snapshot)?,
+        ))
+    }
+}
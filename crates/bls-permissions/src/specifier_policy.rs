@@ -0,0 +1,101 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::CheckSpecifierKind;
+
+/// What `check_specifier` does with a module specifier. Actions are ordered
+/// from the most to the least permissive.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpecifierAction {
+    /// Load the module without any check.
+    Allow,
+    /// Check `file:` modules as reads and everything else as imports.
+    /// `data:` and `blob:` modules have no host, so they need the whole
+    /// import permission.
+    Check,
+    /// Never load the module.
+    Deny,
+}
+
+/// The actions for static and dynamic imports of a scheme.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct SpecifierActions {
+    #[serde(rename = "static")]
+    pub static_import: SpecifierAction,
+    #[serde(rename = "dynamic")]
+    pub dynamic_import: SpecifierAction,
+}
+
+impl SpecifierActions {
+    pub const fn new(static_import: SpecifierAction, dynamic_import: SpecifierAction) -> Self {
+        Self {
+            static_import,
+            dynamic_import,
+        }
+    }
+
+    /// The same action for static and dynamic imports.
+    pub const fn all(action: SpecifierAction) -> Self {
+        Self::new(action, action)
+    }
+
+    pub fn get(&self, kind: CheckSpecifierKind) -> SpecifierAction {
+        match kind {
+            CheckSpecifierKind::Static => self.static_import,
+            CheckSpecifierKind::Dynamic => self.dynamic_import,
+        }
+    }
+
+    fn strictest(&self, other: &Self) -> Self {
+        Self::new(
+            self.static_import.max(other.static_import),
+            self.dynamic_import.max(other.dynamic_import),
+        )
+    }
+}
+
+/// How `check_specifier` handles each scheme. `remote` applies to every
+/// scheme other than `file`, `data` and `blob`. The default only checks
+/// dynamic `file:` imports and remote imports.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpecifierPolicy {
+    pub file: SpecifierActions,
+    pub data: SpecifierActions,
+    pub blob: SpecifierActions,
+    pub remote: SpecifierActions,
+}
+
+impl Default for SpecifierPolicy {
+    fn default() -> Self {
+        Self {
+            file: SpecifierActions::new(SpecifierAction::Allow, SpecifierAction::Check),
+            data: SpecifierActions::all(SpecifierAction::Allow),
+            blob: SpecifierActions::all(SpecifierAction::Allow),
+            remote: SpecifierActions::all(SpecifierAction::Check),
+        }
+    }
+}
+
+impl SpecifierPolicy {
+    pub fn action(&self, scheme: &str, kind: CheckSpecifierKind) -> SpecifierAction {
+        let actions = match scheme {
+            "file" => &self.file,
+            "data" => &self.data,
+            "blob" => &self.blob,
+            _ => &self.remote,
+        };
+        actions.get(kind)
+    }
+
+    /// Combines two policies, keeping the strictest action of each.
+    pub fn strictest(&self, other: &Self) -> Self {
+        Self {
+            file: self.file.strictest(&other.file),
+            data: self.data.strictest(&other.data),
+            blob: self.blob.strictest(&other.blob),
+            remote: self.remote.strictest(&other.remote),
+        }
+    }
+}
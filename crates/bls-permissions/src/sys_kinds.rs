@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::error::type_error;
+use crate::AnyError;
+use crate::SysDescriptor;
+
+/// The kinds every runtime supports, the same ones as Deno.
+const BUILTIN_SYS_KINDS: [&str; 15] = [
+    "hostname",
+    "osRelease",
+    "osUptime",
+    "loadavg",
+    "networkInterfaces",
+    "systemMemoryInfo",
+    "uid",
+    "gid",
+    "cpus",
+    "homedir",
+    "getegid",
+    "username",
+    "statfs",
+    "getPriority",
+    "setPriority",
+];
+
+/// A system info kind gated by the sys permission.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SysKind {
+    pub name: String,
+    /// Shown in prompts next to the name. Built-in kinds have none.
+    pub description: Option<String>,
+}
+
+/// The system info kinds known to a `Permissions`: the built-in ones and
+/// those registered by the runtime with `register`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SysKinds(BTreeMap<String, Option<String>>);
+
+impl Default for SysKinds {
+    fn default() -> Self {
+        Self(
+            BUILTIN_SYS_KINDS
+                .iter()
+                .map(|kind| (kind.to_string(), None))
+                .collect(),
+        )
+    }
+}
+
+impl SysKinds {
+    /// Registers a system info kind exposed by the runtime, e.g. `nodeId`.
+    /// Kinds can be grouped with a `group:` prefix, e.g. `memory:total`, so
+    /// that `memory:*` covers every kind of the group.
+    pub fn register(&mut self, name: &str, description: &str) -> Result<(), AnyError> {
+        if !is_valid_name(name) || name.ends_with(":*") {
+            return Err(type_error(format!("Invalid system info kind \"{name}\"")));
+        }
+        if BUILTIN_SYS_KINDS.contains(&name) {
+            return Err(type_error(format!(
+                "System info kind \"{name}\" is built-in"
+            )));
+        }
+        self.0
+            .insert(name.to_string(), Some(description.to_string()));
+        Ok(())
+    }
+
+    /// Every registered kind, built-in ones included, sorted by name.
+    pub fn list(&self) -> Vec<SysKind> {
+        self.0
+            .iter()
+            .map(|(name, description)| SysKind {
+                name: name.clone(),
+                description: description.clone(),
+            })
+            .collect()
+    }
+
+    /// Checks that `desc` is a registered kind, or a `group:*` of registered
+    /// kinds, and attaches the kind's description shown in prompts.
+    pub fn resolve(&self, desc: SysDescriptor) -> Result<SysDescriptor, AnyError> {
+        let kind = desc.as_str();
+        let known = match kind.strip_suffix('*') {
+            Some(group) => self.0.keys().any(|name| name.starts_with(group)),
+            None => self.0.contains_key(kind),
+        };
+        if !known {
+            return Err(type_error(format!("unknown system info kind \"{kind}\"")));
+        }
+        let description = self.0.get(kind).cloned().flatten();
+        Ok(desc.with_description(description))
+    }
+}
+
+/// Gets if `name` is a kind name, a `group:kind` or a `group:*`.
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    let valid_part = |part: &str| {
+        part.starts_with(|c: char| c.is_ascii_alphabetic())
+            && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    match name.split_once(':') {
+        Some((group, "*")) => valid_part(group),
+        Some((group, kind)) => valid_part(group) && valid_part(kind),
+        None => valid_part(name),
+    }
+}
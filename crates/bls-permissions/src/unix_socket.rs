@@ -0,0 +1,150 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::error::type_error;
+use crate::glob;
+use crate::AllowPartial;
+use crate::AnyError;
+use crate::PermissionState;
+use crate::QueryDescriptor;
+use crate::UnaryPermission;
+
+/// A Unix domain socket: a path on the filesystem, or a name in the Linux
+/// abstract namespace written with a leading `@`, e.g. `@/tmp/.X11-unix/X0`.
+/// Paths match the sockets below them like read paths, abstract names only
+/// match themselves.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub enum UnixSocketDescriptor {
+    Path(PathBuf),
+    Abstract(String),
+}
+
+impl UnixSocketDescriptor {
+    /// Parses `text`, resolving socket paths with `resolve_path`.
+    pub fn parse(
+        text: &str,
+        resolve_path: impl FnOnce(&str) -> Result<PathBuf, AnyError>,
+    ) -> Result<Self, AnyError> {
+        match text.strip_prefix('@') {
+            Some("") => Err(type_error("Empty abstract socket name not allowed")),
+            Some(name) => Ok(Self::Abstract(name.to_string())),
+            None if text.is_empty() => Err(type_error("Empty socket path not allowed")),
+            None => Ok(Self::Path(resolve_path(text)?)),
+        }
+    }
+}
+
+impl fmt::Display for UnixSocketDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnixSocketDescriptor::Path(path) => write!(f, "{}", path.display()),
+            UnixSocketDescriptor::Abstract(name) => write!(f, "@{name}"),
+        }
+    }
+}
+
+impl QueryDescriptor for UnixSocketDescriptor {
+    type AllowDesc = UnixSocketDescriptor;
+    type DenyDesc = UnixSocketDescriptor;
+
+    fn flag_name() -> &'static str {
+        "unix_socket"
+    }
+
+    fn display_name(&self) -> Cow<'_, str> {
+        Cow::Owned(self.to_string())
+    }
+
+    fn from_allow(allow: &Self::AllowDesc) -> Self {
+        allow.clone()
+    }
+
+    fn as_allow(&self) -> Option<Self::AllowDesc> {
+        Some(self.clone())
+    }
+
+    fn as_deny(&self) -> Self::DenyDesc {
+        self.clone()
+    }
+
+    fn check_in_permission(
+        &self,
+        perm: &mut UnaryPermission<Self>,
+        api_name: Option<&str>,
+    ) -> Result<(), AnyError> {
+        if perm.is_allow_all() {
+            return Ok(());
+        }
+        perm.check_desc(Some(self), true, api_name)
+    }
+
+    fn matches_allow(&self, other: &Self::AllowDesc) -> bool {
+        match (self, other) {
+            (Self::Path(path), Self::Path(pattern)) => glob::path_matches(pattern, path),
+            (Self::Abstract(name), Self::Abstract(other)) => name == other,
+            _ => false,
+        }
+    }
+
+    fn matches_deny(&self, other: &Self::DenyDesc) -> bool {
+        self.matches_allow(other)
+    }
+
+    fn revokes(&self, other: &Self::AllowDesc) -> bool {
+        self.matches_allow(other)
+    }
+
+    fn stronger_than_deny(&self, other: &Self::DenyDesc) -> bool {
+        match (self, other) {
+            (Self::Path(path), Self::Path(pattern)) => glob::path_contains_match(path, pattern),
+            (Self::Abstract(name), Self::Abstract(other)) => name == other,
+            _ => false,
+        }
+    }
+
+    fn overlaps_deny(&self, other: &Self::DenyDesc) -> bool {
+        self.stronger_than_deny(other)
+    }
+
+    fn overlaps_deny_for_check(&self, other: &Self::DenyDesc) -> bool {
+        match (self, other) {
+            (Self::Path(path), Self::Path(pattern)) => {
+                glob::path_contains_fixed_match(path, pattern)
+            }
+            _ => self.overlaps_deny(other),
+        }
+    }
+}
+
+impl UnaryPermission<UnixSocketDescriptor> {
+    pub fn query(&self, socket: Option<&UnixSocketDescriptor>) -> PermissionState {
+        self.query_desc(socket, AllowPartial::TreatAsPartialGranted)
+    }
+
+    pub fn request(&mut self, socket: Option<&UnixSocketDescriptor>) -> PermissionState {
+        self.request_desc(socket)
+    }
+
+    pub fn revoke(&mut self, socket: Option<&UnixSocketDescriptor>) -> PermissionState {
+        self.revoke_desc(socket)
+    }
+
+    pub fn check(
+        &mut self,
+        socket: &UnixSocketDescriptor,
+        api_name: Option<&str>,
+    ) -> Result<(), AnyError> {
+        socket.check_in_permission(self, api_name)
+    }
+
+    pub fn check_all(&mut self) -> Result<(), AnyError> {
+        if self.is_allow_all() {
+            return Ok(());
+        }
+        self.check_desc(None, false, None)
+    }
+}
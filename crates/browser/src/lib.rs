@@ -5,28 +5,38 @@ use bls_permissions::AnyError;
 use bls_permissions::BlsPermissionsContainer;
 use bls_permissions::CheckSpecifierKind;
 use bls_permissions::ChildPermissionsArg;
+use bls_permissions::Clock;
+use bls_permissions::CustomPermissionDescriptor;
 use bls_permissions::DenyRunDescriptor;
 use bls_permissions::EnvDescriptor;
 use bls_permissions::FfiDescriptor;
 use bls_permissions::ImportDescriptor;
 use bls_permissions::ModuleSpecifier;
 use bls_permissions::NetDescriptor;
+use bls_permissions::NetResolver;
 use bls_permissions::PathQueryDescriptor;
 use bls_permissions::PermissionDescriptorParser;
 use bls_permissions::PermissionState;
 use bls_permissions::Permissions;
+use bls_permissions::PermissionsSnapshot;
+use bls_permissions::QuotaState;
 use bls_permissions::ReadDescriptor;
+use bls_permissions::RunArgsPattern;
 use bls_permissions::RunQueryDescriptor;
+use bls_permissions::Sha256Digest;
 use bls_permissions::SysDescriptor;
+use bls_permissions::SysKind;
 use bls_permissions::Url;
 use bls_permissions::WriteDescriptor;
 pub use macros::*;
 use once_cell::sync::Lazy;
 use prompter::init_browser_prompter;
 use std::borrow::Cow;
+use std::net::SocketAddr;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use wasm_bindgen::prelude::wasm_bindgen;
 
 #[macro_use]
@@ -40,20 +50,48 @@ pub struct PermissionsContainer(pub bls_permissions::BlsPermissionsContainer);
 impl PermissionsContainer {
     pub fn new(descriptor_parser: Arc<dyn PermissionDescriptorParser>, perms: Permissions) -> Self {
         init_browser_prompter();
-        Self(BlsPermissionsContainer::new(descriptor_parser, perms))
+        Self(
+            BlsPermissionsContainer::new(descriptor_parser, perms)
+                .with_clock(Arc::new(BrowserClock)),
+        )
     }
 
     pub fn create_child_permissions(
         &self,
         child_permissions_arg: ChildPermissionsArg,
     ) -> Result<PermissionsContainer, AnyError> {
-        Ok(PermissionsContainer(self.0.create_child_permissions(child_permissions_arg)?))
+        Ok(PermissionsContainer(
+            self.0.create_child_permissions(child_permissions_arg)?,
+        ))
+    }
+
+    pub fn with_net_resolver(self, net_resolver: Arc<dyn NetResolver>) -> Self {
+        Self(self.0.with_net_resolver(net_resolver))
+    }
+
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self(self.0.with_clock(clock))
     }
 
     pub fn allow_all(descriptor_parser: Arc<dyn PermissionDescriptorParser>) -> Self {
         Self::new(descriptor_parser, Permissions::allow_all())
     }
 
+    pub fn restore(
+        descriptor_parser: Arc<dyn PermissionDescriptorParser>,
+        snapshot: PermissionsSnapshot,
+    ) -> Result<Self, AnyError> {
+        Ok(Self::new(
+            descriptor_parser,
+            Permissions::from_snapshot(snapshot)?,
+        ))
+    }
+
+    #[inline(always)]
+    pub fn snapshot(&self) -> PermissionsSnapshot {
+        self.0.snapshot()
+    }
+
     #[inline(always)]
     pub fn check_specifier(
         &self,
@@ -63,6 +101,16 @@ impl PermissionsContainer {
         self.0.check_specifier(specifier, kind)
     }
 
+    #[inline(always)]
+    pub fn check_specifier_with_digest(
+        &self,
+        specifier: &ModuleSpecifier,
+        kind: CheckSpecifierKind,
+        digest: &Sha256Digest,
+    ) -> Result<(), AnyError> {
+        self.0.check_specifier_with_digest(specifier, kind, digest)
+    }
+
     #[inline(always)]
     pub fn check_read(&self, path: &str, api_name: &str) -> Result<PathBuf, AnyError> {
         self.0.check_read(path, api_name)
@@ -173,6 +221,16 @@ impl PermissionsContainer {
         self.0.check_sys(kind, api_name)
     }
 
+    #[inline(always)]
+    pub fn check_custom(
+        &self,
+        kind: &str,
+        value: Option<&str>,
+        api_name: &str,
+    ) -> Result<(), AnyError> {
+        self.0.check_custom(kind, value, api_name)
+    }
+
     #[inline(always)]
     pub fn check_env(&self, var: &str) -> Result<(), AnyError> {
         self.0.check_env(var)
@@ -220,11 +278,40 @@ impl PermissionsContainer {
         self.0.check_net(host, api_name)
     }
 
+    #[inline(always)]
+    pub fn check_net_resolved<T: AsRef<str>>(
+        &self,
+        host: &(T, Option<u16>),
+        addrs: &[SocketAddr],
+    ) -> Result<(), AnyError> {
+        self.0.check_net_resolved(host, addrs)
+    }
+
+    #[must_use = "the resolved return value to mitigate time-of-check to time-of-use issues"]
+    #[inline(always)]
+    pub fn resolve_net<T: AsRef<str>>(
+        &self,
+        host: &(T, Option<u16>),
+        api_name: &str,
+    ) -> Result<Vec<SocketAddr>, AnyError> {
+        self.0.resolve_net(host, api_name)
+    }
+
+    #[inline(always)]
+    pub fn check_unix_socket(&mut self, path: &str, api_name: &str) -> Result<(), AnyError> {
+        self.0.check_unix_socket(path, api_name)
+    }
+
     #[inline(always)]
     pub fn check_ffi(&mut self, path: &str) -> Result<PathBuf, AnyError> {
         self.0.check_ffi(path)
     }
 
+    #[inline(always)]
+    pub fn check_ffi_symbol(&mut self, path: &str, symbol: &str) -> Result<PathBuf, AnyError> {
+        self.0.check_ffi_symbol(path, symbol)
+    }
+
     #[inline(always)]
     pub fn check_ffi_partial_no_path(&mut self) -> Result<(), AnyError> {
         self.0.check_ffi_partial_no_path()
@@ -272,6 +359,70 @@ impl PermissionsContainer {
         self.0.query_ffi(path)
     }
 
+    #[inline(always)]
+    pub fn query_unix_socket(&self, path: Option<&str>) -> Result<PermissionState, AnyError> {
+        self.0.query_unix_socket(path)
+    }
+
+    #[inline(always)]
+    pub fn query_import(&self, host: Option<&str>) -> Result<PermissionState, AnyError> {
+        self.0.query_import(host)
+    }
+
+    #[inline(always)]
+    pub fn query_uses(&self, kind: &str, value: Option<&str>) -> Result<Option<u32>, AnyError> {
+        self.0.query_uses(kind, value)
+    }
+
+    #[inline(always)]
+    pub fn query_quota(&self, kind: &str, value: &str) -> Result<Vec<QuotaState>, AnyError> {
+        self.0.query_quota(kind, value)
+    }
+
+    #[inline(always)]
+    pub fn report_bytes(&self, kind: &str, value: &str, bytes: u64) -> Result<(), AnyError> {
+        self.0.report_bytes(kind, value, bytes)
+    }
+
+    #[inline(always)]
+    pub fn grant_for(
+        &self,
+        kind: &str,
+        value: Option<&str>,
+        ttl: Duration,
+    ) -> Result<(), AnyError> {
+        self.0.grant_for(kind, value, ttl)
+    }
+
+    #[inline(always)]
+    pub fn register_sys_kind(&self, name: &str, description: &str) -> Result<(), AnyError> {
+        self.0.register_sys_kind(name, description)
+    }
+
+    #[inline(always)]
+    pub fn sys_kinds(&self) -> Vec<SysKind> {
+        self.0.sys_kinds()
+    }
+
+    #[inline(always)]
+    pub fn register_permission_kind<D: CustomPermissionDescriptor>(&self) -> Result<(), AnyError> {
+        self.0.register_permission_kind::<D>()
+    }
+
+    #[inline(always)]
+    pub fn permission_kinds(&self) -> Vec<&'static str> {
+        self.0.permission_kinds()
+    }
+
+    #[inline(always)]
+    pub fn query_custom(
+        &self,
+        kind: &str,
+        value: Option<&str>,
+    ) -> Result<PermissionState, AnyError> {
+        self.0.query_custom(kind, value)
+    }
+
     // revoke
 
     #[inline(always)]
@@ -309,6 +460,25 @@ impl PermissionsContainer {
         self.0.revoke_ffi(path)
     }
 
+    #[inline(always)]
+    pub fn revoke_unix_socket(&self, path: Option<&str>) -> Result<PermissionState, AnyError> {
+        self.0.revoke_unix_socket(path)
+    }
+
+    #[inline(always)]
+    pub fn revoke_import(&self, host: Option<&str>) -> Result<PermissionState, AnyError> {
+        self.0.revoke_import(host)
+    }
+
+    #[inline(always)]
+    pub fn revoke_custom(
+        &self,
+        kind: &str,
+        value: Option<&str>,
+    ) -> Result<PermissionState, AnyError> {
+        self.0.revoke_custom(kind, value)
+    }
+
     // request
 
     #[inline(always)]
@@ -345,6 +515,25 @@ impl PermissionsContainer {
     pub fn request_ffi(&self, path: Option<&str>) -> Result<PermissionState, AnyError> {
         self.0.request_ffi(path)
     }
+
+    #[inline(always)]
+    pub fn request_unix_socket(&self, path: Option<&str>) -> Result<PermissionState, AnyError> {
+        self.0.request_unix_socket(path)
+    }
+
+    #[inline(always)]
+    pub fn request_import(&self, host: Option<&str>) -> Result<PermissionState, AnyError> {
+        self.0.request_import(host)
+    }
+
+    #[inline(always)]
+    pub fn request_custom(
+        &self,
+        kind: &str,
+        value: Option<&str>,
+    ) -> Result<PermissionState, AnyError> {
+        self.0.request_custom(kind, value)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -378,7 +567,7 @@ impl PermissionDescriptorParser for BrowserPermissionDescriptorParser {
     }
 
     fn parse_env_descriptor(&self, text: &str) -> Result<EnvDescriptor, AnyError> {
-        Ok(EnvDescriptor::new(text))
+        EnvDescriptor::parse(text)
     }
 
     fn parse_sys_descriptor(&self, text: &str) -> Result<SysDescriptor, AnyError> {
@@ -389,8 +578,10 @@ impl PermissionDescriptorParser for BrowserPermissionDescriptorParser {
         &self,
         text: &str,
     ) -> Result<AllowRunDescriptorParseResult, AnyError> {
+        let (text, args) = RunArgsPattern::split_descriptor(text)?;
+        let (text, digest) = Sha256Digest::split_descriptor(text)?;
         Ok(AllowRunDescriptorParseResult::Descriptor(
-            AllowRunDescriptor(self.join_path_with_root(text)),
+            AllowRunDescriptor(self.join_path_with_root(text), args, digest),
         ))
     }
 
@@ -403,7 +594,13 @@ impl PermissionDescriptorParser for BrowserPermissionDescriptorParser {
     }
 
     fn parse_ffi_descriptor(&self, text: &str) -> Result<FfiDescriptor, AnyError> {
-        Ok(FfiDescriptor(self.join_path_with_root(text)))
+        let (text, symbols) = FfiDescriptor::split_symbols(text)?;
+        let (text, digest) = Sha256Digest::split_descriptor(text)?;
+        Ok(FfiDescriptor(
+            self.join_path_with_root(text),
+            digest,
+            symbols,
+        ))
     }
 
     fn parse_path_query(&self, path: &str) -> Result<PathQueryDescriptor, AnyError> {
@@ -421,14 +618,26 @@ impl PermissionDescriptorParser for BrowserPermissionDescriptorParser {
 static PERMSSIONSCONTAINER: Lazy<PermissionsContainer> =
     Lazy::new(|| PermissionsContainer::allow_all(Arc::new(BrowserPermissionDescriptorParser)));
 
+/// `SystemTime` isn't available in the browser, grants with a TTL are timed
+/// with `Date.now()`.
+struct BrowserClock;
+
+impl Clock for BrowserClock {
+    fn now(&self) -> Duration {
+        Duration::from_millis(date_now() as u64)
+    }
+}
+
 #[wasm_bindgen]
 pub fn init_permissions_prompt(b: bool) {
     info!("init_permissions_prompt: {b}");
-    *PERMSSIONSCONTAINER.0.lock() = if b {
+    let mut perms = if b {
         Permissions::none_with_prompt()
     } else {
         Permissions::none_without_prompt()
     };
+    perms.set_clock(Arc::new(BrowserClock));
+    *PERMSSIONSCONTAINER.0.lock() = perms;
 }
 
 #[derive(Clone, Copy)]
@@ -10,6 +10,9 @@ extern "C" {
 
     #[wasm_bindgen(js_namespace = console, js_name = error)]
     pub fn console_error(s: &str);
+
+    #[wasm_bindgen(js_namespace = Date, js_name = now)]
+    pub fn date_now() -> f64;
 }
 
 #[wasm_bindgen(module = "/module.js")]
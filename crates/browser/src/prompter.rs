@@ -58,7 +58,7 @@ impl PermissionPrompter for BrowserPrompter {
         }
 
         let opts: String = if is_unary {
-            format!("[y/n/A] (y = yes, allow; n = no, deny; A = allow all {name} permissions)")
+            format!("[y/o/n/A] (y = yes, allow; o = allow once; n = no, deny; A = allow all {name} permissions)")
         } else {
             "[y/n] (y = yes, allow; n = no, deny)".to_string()
         };
@@ -127,6 +127,11 @@ impl PermissionPrompter for BrowserPrompter {
                     blsrt_show_tips!(fail: "❌ {msg}");
                     break PromptResponse::Deny;
                 }
+                'o' | 'O' if is_unary => {
+                    let msg = format!("Granted {message} access once.");
+                    blsrt_show_tips!(success: "✅ {msg}");
+                    break PromptResponse::AllowOnce;
+                }
                 'A' | 'a' if is_unary => {
                     let msg = format!("Granted all {name} access.");
                     blsrt_show_tips!(success: "✅ {msg}");
@@ -2,9 +2,11 @@ use prompter::init_tty_prompter;
 
 use std::borrow::Cow;
 use std::fmt::Debug;
+use std::net::SocketAddr;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 pub mod prompter;
 pub use prompter::set_prompt_callbacks;
@@ -25,13 +27,38 @@ impl PermissionsContainer {
         &self,
         child_permissions_arg: ChildPermissionsArg,
     ) -> Result<PermissionsContainer, AnyError> {
-        Ok(PermissionsContainer(self.0.create_child_permissions(child_permissions_arg)?))
+        Ok(PermissionsContainer(
+            self.0.create_child_permissions(child_permissions_arg)?,
+        ))
+    }
+
+    pub fn with_net_resolver(self, net_resolver: Arc<dyn NetResolver>) -> Self {
+        Self(self.0.with_net_resolver(net_resolver))
+    }
+
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self(self.0.with_clock(clock))
     }
 
     pub fn allow_all(descriptor_parser: Arc<dyn PermissionDescriptorParser>) -> Self {
         Self::new(descriptor_parser, Permissions::allow_all())
     }
 
+    pub fn restore(
+        descriptor_parser: Arc<dyn PermissionDescriptorParser>,
+        snapshot: PermissionsSnapshot,
+    ) -> Result<Self, AnyError> {
+        Ok(Self::new(
+            descriptor_parser,
+            Permissions::from_snapshot(snapshot)?,
+        ))
+    }
+
+    #[inline(always)]
+    pub fn snapshot(&self) -> PermissionsSnapshot {
+        self.0.snapshot()
+    }
+
     #[inline(always)]
     pub fn check_specifier(
         &self,
@@ -41,6 +68,16 @@ impl PermissionsContainer {
         self.0.check_specifier(specifier, kind)
     }
 
+    #[inline(always)]
+    pub fn check_specifier_with_digest(
+        &self,
+        specifier: &ModuleSpecifier,
+        kind: CheckSpecifierKind,
+        digest: &Sha256Digest,
+    ) -> Result<(), AnyError> {
+        self.0.check_specifier_with_digest(specifier, kind, digest)
+    }
+
     #[inline(always)]
     pub fn check_read(&self, path: &str, api_name: &str) -> Result<PathBuf, AnyError> {
         self.0.check_read(path, api_name)
@@ -151,6 +188,16 @@ impl PermissionsContainer {
         self.0.check_sys(kind, api_name)
     }
 
+    #[inline(always)]
+    pub fn check_custom(
+        &self,
+        kind: &str,
+        value: Option<&str>,
+        api_name: &str,
+    ) -> Result<(), AnyError> {
+        self.0.check_custom(kind, value, api_name)
+    }
+
     #[inline(always)]
     pub fn check_env(&mut self, var: &str) -> Result<(), AnyError> {
         self.0.check_env(var)
@@ -198,11 +245,45 @@ impl PermissionsContainer {
         self.0.check_net(host, api_name)
     }
 
+    #[inline(always)]
+    pub fn check_net_resolved<T: AsRef<str>>(
+        &self,
+        host: &(T, Option<u16>),
+        addrs: &[SocketAddr],
+    ) -> Result<(), AnyError> {
+        self.0.check_net_resolved(host, addrs)
+    }
+
+    #[inline(always)]
+    pub fn check_net_url_resolved(&self, url: &Url, addrs: &[SocketAddr]) -> Result<(), AnyError> {
+        self.0.check_net_url_resolved(url, addrs)
+    }
+
+    #[must_use = "the resolved return value to mitigate time-of-check to time-of-use issues"]
+    #[inline(always)]
+    pub fn resolve_net<T: AsRef<str>>(
+        &mut self,
+        host: &(T, Option<u16>),
+        api_name: &str,
+    ) -> Result<Vec<SocketAddr>, AnyError> {
+        self.0.resolve_net(host, api_name)
+    }
+
+    #[inline(always)]
+    pub fn check_unix_socket(&mut self, path: &str, api_name: &str) -> Result<(), AnyError> {
+        self.0.check_unix_socket(path, api_name)
+    }
+
     #[inline(always)]
     pub fn check_ffi(&mut self, path: &str) -> Result<PathBuf, AnyError> {
         self.0.check_ffi(path)
     }
 
+    #[inline(always)]
+    pub fn check_ffi_symbol(&mut self, path: &str, symbol: &str) -> Result<PathBuf, AnyError> {
+        self.0.check_ffi_symbol(path, symbol)
+    }
+
     #[inline(always)]
     pub fn check_ffi_partial_no_path(&mut self) -> Result<(), AnyError> {
         self.0.check_ffi_partial_no_path()
@@ -250,6 +331,70 @@ impl PermissionsContainer {
         self.0.query_ffi(path)
     }
 
+    #[inline(always)]
+    pub fn query_unix_socket(&self, path: Option<&str>) -> Result<PermissionState, AnyError> {
+        self.0.query_unix_socket(path)
+    }
+
+    #[inline(always)]
+    pub fn query_import(&self, host: Option<&str>) -> Result<PermissionState, AnyError> {
+        self.0.query_import(host)
+    }
+
+    #[inline(always)]
+    pub fn query_uses(&self, kind: &str, value: Option<&str>) -> Result<Option<u32>, AnyError> {
+        self.0.query_uses(kind, value)
+    }
+
+    #[inline(always)]
+    pub fn query_quota(&self, kind: &str, value: &str) -> Result<Vec<QuotaState>, AnyError> {
+        self.0.query_quota(kind, value)
+    }
+
+    #[inline(always)]
+    pub fn report_bytes(&self, kind: &str, value: &str, bytes: u64) -> Result<(), AnyError> {
+        self.0.report_bytes(kind, value, bytes)
+    }
+
+    #[inline(always)]
+    pub fn grant_for(
+        &self,
+        kind: &str,
+        value: Option<&str>,
+        ttl: Duration,
+    ) -> Result<(), AnyError> {
+        self.0.grant_for(kind, value, ttl)
+    }
+
+    #[inline(always)]
+    pub fn register_sys_kind(&self, name: &str, description: &str) -> Result<(), AnyError> {
+        self.0.register_sys_kind(name, description)
+    }
+
+    #[inline(always)]
+    pub fn sys_kinds(&self) -> Vec<SysKind> {
+        self.0.sys_kinds()
+    }
+
+    #[inline(always)]
+    pub fn register_permission_kind<D: CustomPermissionDescriptor>(&self) -> Result<(), AnyError> {
+        self.0.register_permission_kind::<D>()
+    }
+
+    #[inline(always)]
+    pub fn permission_kinds(&self) -> Vec<&'static str> {
+        self.0.permission_kinds()
+    }
+
+    #[inline(always)]
+    pub fn query_custom(
+        &self,
+        kind: &str,
+        value: Option<&str>,
+    ) -> Result<PermissionState, AnyError> {
+        self.0.query_custom(kind, value)
+    }
+
     // revoke
 
     #[inline(always)]
@@ -287,6 +432,25 @@ impl PermissionsContainer {
         self.0.revoke_ffi(path)
     }
 
+    #[inline(always)]
+    pub fn revoke_unix_socket(&self, path: Option<&str>) -> Result<PermissionState, AnyError> {
+        self.0.revoke_unix_socket(path)
+    }
+
+    #[inline(always)]
+    pub fn revoke_import(&self, host: Option<&str>) -> Result<PermissionState, AnyError> {
+        self.0.revoke_import(host)
+    }
+
+    #[inline(always)]
+    pub fn revoke_custom(
+        &self,
+        kind: &str,
+        value: Option<&str>,
+    ) -> Result<PermissionState, AnyError> {
+        self.0.revoke_custom(kind, value)
+    }
+
     // request
 
     #[inline(always)]
@@ -323,6 +487,25 @@ impl PermissionsContainer {
     pub fn request_ffi(&self, path: Option<&str>) -> Result<PermissionState, AnyError> {
         self.0.request_ffi(path)
     }
+
+    #[inline(always)]
+    pub fn request_unix_socket(&self, path: Option<&str>) -> Result<PermissionState, AnyError> {
+        self.0.request_unix_socket(path)
+    }
+
+    #[inline(always)]
+    pub fn request_import(&self, host: Option<&str>) -> Result<PermissionState, AnyError> {
+        self.0.request_import(host)
+    }
+
+    #[inline(always)]
+    pub fn request_custom(
+        &self,
+        kind: &str,
+        value: Option<&str>,
+    ) -> Result<PermissionState, AnyError> {
+        self.0.request_custom(kind, value)
+    }
 }
 
 #[cfg(test)]
@@ -333,8 +516,10 @@ mod tests {
     use fqdn::fqdn;
     use prompter::{set_prompter, tests::*};
     use std::{
-        collections::HashSet,
+        collections::{BTreeMap, HashSet},
         net::{IpAddr, Ipv4Addr, Ipv6Addr},
+        sync::atomic::{AtomicU64, Ordering},
+        time::Duration,
     };
 
     // Creates vector of strings, Vec<String>
@@ -375,7 +560,7 @@ mod tests {
         }
 
         fn parse_env_descriptor(&self, text: &str) -> Result<EnvDescriptor, AnyError> {
-            Ok(EnvDescriptor::new(text))
+            EnvDescriptor::parse(text)
         }
 
         fn parse_sys_descriptor(&self, text: &str) -> Result<SysDescriptor, AnyError> {
@@ -386,8 +571,10 @@ mod tests {
             &self,
             text: &str,
         ) -> Result<AllowRunDescriptorParseResult, AnyError> {
+            let (text, args) = RunArgsPattern::split_descriptor(text)?;
+            let (text, digest) = Sha256Digest::split_descriptor(text)?;
             Ok(AllowRunDescriptorParseResult::Descriptor(
-                AllowRunDescriptor(self.join_path_with_root(text)),
+                AllowRunDescriptor(self.join_path_with_root(text), args, digest),
             ))
         }
 
@@ -400,7 +587,13 @@ mod tests {
         }
 
         fn parse_ffi_descriptor(&self, text: &str) -> Result<FfiDescriptor, AnyError> {
-            Ok(FfiDescriptor(self.join_path_with_root(text)))
+            let (text, symbols) = FfiDescriptor::split_symbols(text)?;
+            let (text, digest) = Sha256Digest::split_descriptor(text)?;
+            Ok(FfiDescriptor(
+                self.join_path_with_root(text),
+                digest,
+                symbols,
+            ))
         }
 
         fn parse_path_query(&self, path: &str) -> Result<PathQueryDescriptor, AnyError> {
@@ -462,6 +655,79 @@ mod tests {
         }
     }
 
+    #[test]
+    fn check_path_globs() {
+        let _locked = TESTMUTEX.lock();
+        let parser = TestPermissionDescriptorParser;
+        let perms = Permissions::from_options(
+            &parser,
+            &PermissionsOptions {
+                allow_read: Some(svec!["/data/**/*.json", "/home"]),
+                deny_read: Some(svec!["**/.env", "/home/*/.ssh"]),
+                allow_write: Some(vec![]),
+                deny_write: Some(svec!["/tmp/*.lock"]),
+                allow_ffi: Some(svec!["/lib/lib[a-c].so"]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut perms = PermissionsContainer::new(Arc::new(parser), perms);
+        set_prompter(Box::new(TestPrompter));
+
+        #[rustfmt::skip]
+        let cases = [
+            ("/data/a.json", true),
+            ("/data/x/y/z.json", true),
+            ("/data/x/y/z.txt", false),
+            ("/data", false),
+            ("/home/user/notes.txt", true),
+            ("/home/user/.env", false),
+            ("/home/user/project/.env", false),
+            ("/home/user/.ssh/id_rsa", false),
+            ("/home/user/docs/.ssh", true),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(perms.check_read(path, "api").is_ok(), expected, "{path}");
+        }
+        assert!(perms.check_write("/tmp/foo.txt", "api").is_ok());
+        assert!(perms.check_write("/tmp/foo.lock", "api").is_err());
+        assert!(perms.check_write("/tmp/sub/foo.lock", "api").is_ok());
+        assert!(perms.check_ffi("/lib/libb.so").is_ok());
+        assert!(perms.check_ffi("/lib/libd.so").is_err());
+
+        assert_eq!(
+            perms.query_read(Some("/home")).unwrap(),
+            PermissionState::GrantedPartial
+        );
+        assert_eq!(
+            perms.query_read(Some("/home/user")).unwrap(),
+            PermissionState::GrantedPartial
+        );
+        // `/home/user/docs/.env` is denied
+        assert_eq!(
+            perms.query_read(Some("/home/user/docs")).unwrap(),
+            PermissionState::GrantedPartial
+        );
+        assert_eq!(
+            perms.query_read(Some("/home/user/.ssh")).unwrap(),
+            PermissionState::Denied
+        );
+        assert_eq!(
+            perms.query_write(Some("/tmp")).unwrap(),
+            PermissionState::GrantedPartial
+        );
+        assert_eq!(
+            perms.query_write(Some("/var")).unwrap(),
+            PermissionState::Granted
+        );
+
+        let err = perms
+            .check_read_blind(Path::new("/home/user/.env"), "env file", "api")
+            .unwrap_err();
+        assert!(err.to_string().contains("<env file>"), "{err}");
+        assert!(!err.to_string().contains("/home/user"), "{err}");
+    }
+
     #[test]
     fn test_check_net_with_values() {
         let _locked = TESTMUTEX.lock();
@@ -513,7 +779,7 @@ mod tests {
 
         for (host, port, is_ok) in domain_tests {
             let host = Host::parse(host).unwrap();
-            let descriptor = NetDescriptor(host, Some(port));
+            let descriptor = NetDescriptor(host, Some(port.into()), None);
             assert_eq!(
                 is_ok,
                 perms.net.check(&descriptor, None).is_ok(),
@@ -560,7 +826,7 @@ mod tests {
 
         for (host_str, port) in domain_tests {
             let host = Host::parse(host_str).unwrap();
-            let descriptor = NetDescriptor(host, Some(port));
+            let descriptor = NetDescriptor(host, Some(port.into()), None);
             assert!(
                 perms.net.check(&descriptor, None).is_ok(),
                 "expected {host_str}:{port} to pass"
@@ -606,7 +872,7 @@ mod tests {
 
         for (host_str, port) in domain_tests {
             let host = Host::parse(host_str).unwrap();
-            let descriptor = NetDescriptor(host, Some(port));
+            let descriptor = NetDescriptor(host, Some(port.into()), None);
             assert!(
                 perms.net.check(&descriptor, None).is_err(),
                 "expected {host_str}:{port} to fail"
@@ -768,40 +1034,487 @@ mod tests {
     }
 
     #[test]
-    fn test_query() {
+    fn test_specifier_policy() {
+        let _locked = TESTMUTEX.lock();
         set_prompter(Box::new(TestPrompter));
         let parser = TestPermissionDescriptorParser;
-        let perms1 = Permissions::allow_all();
-        let perms2 = Permissions::from_options(
+        let policy = r#"
+allow_import = ["deno.land"]
+
+[specifier_policy]
+data = { static = "deny", dynamic = "deny" }
+blob = { static = "allow", dynamic = "check" }
+file = { static = "check", dynamic = "check" }
+"#;
+        let opts =
+            PermissionsOptions::from_policy_str(&parser, policy, PolicyFormat::Toml, "policy")
+                .unwrap();
+        assert_eq!(
+            opts.specifier_policy,
+            SpecifierPolicy {
+                data: SpecifierActions::all(SpecifierAction::Deny),
+                blob: SpecifierActions::new(SpecifierAction::Allow, SpecifierAction::Check),
+                file: SpecifierActions::all(SpecifierAction::Check),
+                ..Default::default()
+            }
+        );
+        let perms = Permissions::from_options(&parser, &opts).unwrap();
+        let perms = PermissionsContainer::new(Arc::new(parser), perms);
+
+        #[rustfmt::skip]
+        let fixtures = [
+            ("data:text/javascript,export default 1", CheckSpecifierKind::Static, false),
+            ("data:text/javascript,export default 1", CheckSpecifierKind::Dynamic, false),
+            ("blob:https://deno.land/1234", CheckSpecifierKind::Static, true),
+            // blob urls have no host, so they need the whole import permission
+            ("blob:https://deno.land/1234", CheckSpecifierKind::Dynamic, false),
+            ("file:///a/mod.ts", CheckSpecifierKind::Static, false),
+            ("https://deno.land/x/mod.ts", CheckSpecifierKind::Static, true),
+            ("https://example.com/mod.ts", CheckSpecifierKind::Static, false),
+        ];
+        for (specifier, kind, expected) in fixtures {
+            let specifier = ModuleSpecifier::parse(specifier).unwrap();
+            assert_eq!(
+                perms.check_specifier(&specifier, kind).is_ok(),
+                expected,
+                "{specifier} {kind:?}"
+            );
+        }
+        let specifier = ModuleSpecifier::parse("data:text/javascript,1").unwrap();
+        let err = perms
+            .check_specifier(&specifier, CheckSpecifierKind::Static)
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("denied by the specifier policy"),
+            "{err}"
+        );
+
+        // children keep the policy
+        let child = perms
+            .0
+            .create_child_permissions(ChildPermissionsArg::inherit())
+            .unwrap();
+        assert!(child
+            .check_specifier(&specifier, CheckSpecifierKind::Dynamic)
+            .is_err());
+
+        // layers can only make the policy stricter
+        let merged = PermissionsOptions::merge_layers(&[
+            PermissionsLayer::new("base", opts),
+            PermissionsLayer::new(
+                "project",
+                PermissionsOptions {
+                    specifier_policy: SpecifierPolicy {
+                        data: SpecifierActions::all(SpecifierAction::Allow),
+                        remote: SpecifierActions::all(SpecifierAction::Deny),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            ),
+        ]);
+        let policy = merged.options.specifier_policy;
+        assert_eq!(
+            policy.action("data", CheckSpecifierKind::Dynamic),
+            SpecifierAction::Deny
+        );
+        assert_eq!(
+            policy.action("https", CheckSpecifierKind::Static),
+            SpecifierAction::Deny
+        );
+        assert_eq!(
+            policy.action("file", CheckSpecifierKind::Static),
+            SpecifierAction::Check
+        );
+    }
+
+    #[test]
+    fn test_import_permission() {
+        let _locked = TESTMUTEX.lock();
+        let parser = TestPermissionDescriptorParser;
+        let perms = Permissions::from_options(
             &parser,
             &PermissionsOptions {
-                allow_read: Some(svec!["/foo"]),
-                allow_write: Some(svec!["/foo"]),
-                allow_ffi: Some(svec!["/foo"]),
-                allow_net: Some(svec!["127.0.0.1:8000"]),
-                allow_env: Some(svec!["HOME"]),
-                allow_sys: Some(svec!["hostname"]),
-                allow_run: Some(svec!["/deno"]),
-                allow_all: false,
+                allow_import: Some(vec![]),
+                deny_import: Some(svec!["evil.com", "cdn.example.com:8080"]),
+                prompt: true,
                 ..Default::default()
             },
         )
         .unwrap();
-        let perms3 = Permissions::from_options(
+        let perms = PermissionsContainer::new(Arc::new(parser), perms);
+        set_prompter(Box::new(TestPrompter));
+        #[rustfmt::skip]
+        let fixtures = [
+            ("https://deno.land/x/mod.ts", CheckSpecifierKind::Static, true),
+            ("https://evil.com/mod.ts", CheckSpecifierKind::Static, false),
+            ("https://evil.com/mod.ts", CheckSpecifierKind::Dynamic, false),
+            ("http://cdn.example.com:8080/mod.ts", CheckSpecifierKind::Static, false),
+            ("https://cdn.example.com/mod.ts", CheckSpecifierKind::Static, true),
+        ];
+        for (specifier, kind, expected) in fixtures {
+            let specifier = ModuleSpecifier::parse(specifier).unwrap();
+            assert_eq!(
+                perms.check_specifier(&specifier, kind).is_ok(),
+                expected,
+                "{specifier}"
+            );
+        }
+
+        assert_eq!(
+            perms.query_import(None).unwrap(),
+            PermissionState::GrantedPartial
+        );
+        assert_eq!(
+            perms.query_import(Some("deno.land")).unwrap(),
+            PermissionState::Granted
+        );
+        assert_eq!(
+            perms.query_import(Some("evil.com")).unwrap(),
+            PermissionState::Denied
+        );
+        assert_eq!(perms.revoke_import(None).unwrap(), PermissionState::Prompt);
+        {
+            let prompt_value = PERMISSION_PROMPT_STUB_VALUE_SETTER.lock();
+            prompt_value.set(false);
+            assert_eq!(
+                perms.request_import(Some("jsr.io")).unwrap(),
+                PermissionState::Denied
+            );
+            prompt_value.set(true);
+            assert_eq!(
+                perms.request_import(Some("deno.land")).unwrap(),
+                PermissionState::Granted
+            );
+            assert_eq!(
+                perms.request_import(Some("evil.com")).unwrap(),
+                PermissionState::Denied
+            );
+        }
+        assert_eq!(
+            perms.query_import(Some("jsr.io")).unwrap(),
+            PermissionState::Denied
+        );
+        assert!(perms.query_import(Some("bad host")).is_err());
+    }
+
+    #[test]
+    fn test_import_pinned_digest() {
+        let _locked = TESTMUTEX.lock();
+        set_prompter(Box::new(TestPrompter));
+        let content = b"export const a = 1;";
+        let digest = Sha256Digest::of_bytes(content);
+        let other = Sha256Digest::of_bytes(b"export const a = 2;");
+        let parser = TestPermissionDescriptorParser;
+        let perms = Permissions::from_options(
             &parser,
             &PermissionsOptions {
-                deny_read: Some(svec!["/foo"]),
-                deny_write: Some(svec!["/foo"]),
-                deny_ffi: Some(svec!["/foo"]),
-                deny_net: Some(svec!["127.0.0.1:8000"]),
-                deny_env: Some(svec!["HOME"]),
-                deny_sys: Some(svec!["hostname"]),
-                deny_run: Some(svec!["deno"]),
+                allow_import: Some(vec![
+                    format!("https://deno.land/x/mod.ts@{digest}"),
+                    "https://jsr.io/@std/".to_string(),
+                ]),
                 ..Default::default()
             },
         )
         .unwrap();
-        let perms4 = Permissions::from_options(
+        let perms = PermissionsContainer::new(Arc::new(parser), perms);
+
+        let check = |specifier: &str, digest: &Sha256Digest| {
+            let specifier = ModuleSpecifier::parse(specifier).unwrap();
+            perms.check_specifier_with_digest(&specifier, CheckSpecifierKind::Static, digest)
+        };
+        assert!(check("https://deno.land/x/mod.ts", &digest).is_ok());
+        let err = check("https://deno.land/x/mod.ts", &other).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("does not match the pinned sha256 digest"),
+            "{err}"
+        );
+        // unpinned grants accept any content
+        assert!(check("https://jsr.io/@std/path/mod.ts", &other).is_ok());
+        let err = check("https://deno.land/x/other.ts", &digest).unwrap_err();
+        assert!(
+            err.to_string().starts_with("Requires import access to"),
+            "{err}"
+        );
+        assert!(!err.to_string().contains("digest"), "{err}");
+        // the host is checked before the content is fetched
+        let specifier = ModuleSpecifier::parse("https://deno.land/x/mod.ts").unwrap();
+        assert!(perms
+            .check_specifier(&specifier, CheckSpecifierKind::Static)
+            .is_ok());
+
+        // the specifier policy applies before the content is verified
+        let denied = PermissionsContainer::new(
+            Arc::new(TestPermissionDescriptorParser),
+            Permissions::from_options(
+                &TestPermissionDescriptorParser,
+                &PermissionsOptions {
+                    allow_import: Some(vec![format!("https://deno.land/x/mod.ts@{digest}")]),
+                    specifier_policy: SpecifierPolicy {
+                        remote: SpecifierActions::all(SpecifierAction::Deny),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            )
+            .unwrap(),
+        );
+        let err = denied
+            .check_specifier_with_digest(&specifier, CheckSpecifierKind::Static, &other)
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("denied by the specifier policy"),
+            "{err}"
+        );
+
+        let desc =
+            ImportDescriptor::parse(&format!("https://deno.land/x/mod.ts@{digest}")).unwrap();
+        assert_eq!(desc.digest(), Some(&digest));
+        assert_eq!(
+            desc.to_string(),
+            format!("https://deno.land/x/mod.ts@{digest}")
+        );
+        assert!(Permissions::from_options(
+            &TestPermissionDescriptorParser,
+            &PermissionsOptions {
+                deny_import: Some(vec![format!("https://deno.land/x/mod.ts@{digest}")]),
+                ..Default::default()
+            },
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_import_packages() {
+        let _locked = TESTMUTEX.lock();
+        set_prompter(Box::new(TestPrompter));
+        let parser = TestPermissionDescriptorParser;
+        let perms = Permissions::from_options(
+            &parser,
+            &PermissionsOptions {
+                allow_import: Some(svec!["npm:lodash", "npm:@types/*", "jsr:@std/*"]),
+                deny_import: Some(svec!["jsr:@std/internal"]),
+                prompt: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let perms = PermissionsContainer::new(Arc::new(parser), perms);
+
+        for (text, expected) in [
+            ("npm:lodash", true),
+            ("npm:@types/node", true),
+            ("jsr:@std/*", true),
+            ("npm:*", true),
+            ("jsr:std", false),
+            ("npm:lodash@4", false),
+            ("npm:lodash/fp", false),
+            ("npm:@types", false),
+            ("npm:", false),
+            ("pypi:requests", false),
+        ] {
+            assert_eq!(PackageDescriptor::parse(text).is_ok(), expected, "{text}");
+        }
+
+        {
+            let prompt_value = PERMISSION_PROMPT_STUB_VALUE_SETTER.lock();
+            prompt_value.set(false);
+            #[rustfmt::skip]
+            let fixtures = [
+                ("npm:lodash@4.17.21", true),
+                ("npm:/lodash@4/fp", true),
+                ("npm:lodash-es", false),
+                ("npm:@types/node@^20/fs", true),
+                ("jsr:@std/path@1.0.0/posix", true),
+                ("jsr:@std/internal@1", false),
+                ("jsr:@luca/flag", false),
+            ];
+            for (specifier, expected) in fixtures {
+                let specifier = ModuleSpecifier::parse(specifier).unwrap();
+                assert_eq!(
+                    perms
+                        .check_specifier(&specifier, CheckSpecifierKind::Static)
+                        .is_ok(),
+                    expected,
+                    "{specifier}"
+                );
+            }
+        }
+
+        let url = ModuleSpecifier::parse("npm:@types/node@20/fs").unwrap();
+        let desc = ImportDescriptor::from_url(&url).unwrap();
+        assert_eq!(desc.display_name(), "npm:@types/node");
+
+        assert_eq!(
+            perms.query_import(Some("npm:lodash")).unwrap(),
+            PermissionState::Granted
+        );
+        assert_eq!(
+            perms.query_import(Some("jsr:@std/*")).unwrap(),
+            PermissionState::GrantedPartial
+        );
+        assert_eq!(
+            perms.query_import(Some("jsr:@std/internal")).unwrap(),
+            PermissionState::Denied
+        );
+        assert_eq!(
+            perms.query_import(Some("npm:react")).unwrap(),
+            PermissionState::Prompt
+        );
+        {
+            let prompt_value = PERMISSION_PROMPT_STUB_VALUE_SETTER.lock();
+            prompt_value.set(true);
+            assert_eq!(
+                perms.request_import(Some("npm:react")).unwrap(),
+                PermissionState::Granted
+            );
+        }
+        assert_eq!(
+            perms.query_import(Some("npm:react")).unwrap(),
+            PermissionState::Granted
+        );
+        assert_eq!(
+            perms.query_import(Some("npm:react-dom")).unwrap(),
+            PermissionState::Prompt
+        );
+        assert_eq!(
+            perms.revoke_import(Some("npm:@types/node")).unwrap(),
+            PermissionState::Prompt
+        );
+        assert_eq!(
+            perms.query_import(Some("npm:@types/react")).unwrap(),
+            PermissionState::Prompt
+        );
+        assert_eq!(
+            perms.query_import(Some("npm:lodash")).unwrap(),
+            PermissionState::Granted
+        );
+    }
+
+    #[test]
+    fn test_import_path_scopes() {
+        let _locked = TESTMUTEX.lock();
+        set_prompter(Box::new(TestPrompter));
+        let parser = TestPermissionDescriptorParser;
+        let perms = Permissions::from_options(
+            &parser,
+            &PermissionsOptions {
+                allow_import: Some(svec![
+                    "https://jsr.io/@std/",
+                    "https://deno.land/x/oak",
+                    "https://esm.sh/"
+                ]),
+                deny_import: Some(svec![
+                    "https://jsr.io/@std/internal/",
+                    "https://esm.sh/@evil/"
+                ]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let perms = PermissionsContainer::new(Arc::new(parser), perms);
+
+        #[rustfmt::skip]
+        let fixtures = [
+            ("https://jsr.io/@std/path/1.0.0/mod.ts", true),
+            ("https://jsr.io/@std/internal/1.0.0/mod.ts", false),
+            ("https://jsr.io/@evil/path/mod.ts", false),
+            ("http://jsr.io/@std/path/mod.ts", false),
+            ("https://deno.land/x/oak/mod.ts", true),
+            ("https://deno.land/x/oak", true),
+            ("https://deno.land/x/oakland/mod.ts", false),
+            // paths are compared percent-decoded
+            ("https://esm.sh/@evil/mod.ts", false),
+            ("https://esm.sh/%40evil/mod.ts", false),
+            ("https://esm.sh/%40good/mod.ts", true),
+            ("https://jsr.io/%40std/path/mod.ts", true),
+            ("https://jsr.io/@std%2Finternal/mod.ts", false),
+        ];
+        for (specifier, expected) in fixtures {
+            let specifier = ModuleSpecifier::parse(specifier).unwrap();
+            assert_eq!(
+                perms
+                    .check_specifier(&specifier, CheckSpecifierKind::Static)
+                    .is_ok(),
+                expected,
+                "{specifier}"
+            );
+        }
+
+        assert_eq!(
+            perms.query_import(Some("https://jsr.io/@std/fs/")).unwrap(),
+            PermissionState::Granted
+        );
+        assert_eq!(
+            perms.query_import(Some("https://jsr.io/@std/")).unwrap(),
+            PermissionState::GrantedPartial
+        );
+        assert_eq!(
+            perms
+                .query_import(Some("https://jsr.io/@std/internal/x"))
+                .unwrap(),
+            PermissionState::Denied
+        );
+        assert_eq!(
+            perms.query_import(Some("jsr.io")).unwrap(),
+            PermissionState::Prompt
+        );
+        assert_eq!(
+            perms
+                .query_import(Some("https://esm.sh/%40evil/x"))
+                .unwrap(),
+            PermissionState::Denied
+        );
+        // a `/` after a scheme is read as a network first
+        assert_eq!(
+            ImportDescriptor::parse("https://10.0.0.0/8").unwrap(),
+            ImportDescriptor::parse("https://10.0.0.0/8/").unwrap()
+        );
+        assert!(ImportDescriptor::parse("jsr.io/@std/").is_err());
+
+        let url = Url::parse("https://jsr.io/@std/path/mod.ts").unwrap();
+        let desc = ImportDescriptor::from_url(&url).unwrap();
+        assert_eq!(desc.display_name(), "https://jsr.io/@std/path/mod.ts");
+        assert_eq!(desc.to_string(), "https://jsr.io:443/@std/path/mod.ts");
+        assert_eq!(ImportDescriptor::parse(&desc.to_string()).unwrap(), desc);
+    }
+
+    #[test]
+    fn test_query() {
+        set_prompter(Box::new(TestPrompter));
+        let parser = TestPermissionDescriptorParser;
+        let perms1 = Permissions::allow_all();
+        let perms2 = Permissions::from_options(
+            &parser,
+            &PermissionsOptions {
+                allow_read: Some(svec!["/foo"]),
+                allow_write: Some(svec!["/foo"]),
+                allow_ffi: Some(svec!["/foo"]),
+                allow_net: Some(svec!["127.0.0.1:8000"]),
+                allow_env: Some(svec!["HOME"]),
+                allow_sys: Some(svec!["hostname"]),
+                allow_run: Some(svec!["/deno"]),
+                allow_all: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let perms3 = Permissions::from_options(
+            &parser,
+            &PermissionsOptions {
+                deny_read: Some(svec!["/foo"]),
+                deny_write: Some(svec!["/foo"]),
+                deny_ffi: Some(svec!["/foo"]),
+                deny_net: Some(svec!["127.0.0.1:8000"]),
+                deny_env: Some(svec!["HOME"]),
+                deny_sys: Some(svec!["hostname"]),
+                deny_run: Some(svec!["deno"]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let perms4 = Permissions::from_options(
             &parser,
             &PermissionsOptions {
                 allow_read: Some(vec![]),
@@ -864,14 +1577,14 @@ mod tests {
             assert_eq!(perms4.ffi.query(Some(&ffi_query("/foo/bar"))), PermissionState::Denied);
             assert_eq!(perms4.ffi.query(Some(&ffi_query("/bar"))), PermissionState::Granted);
             assert_eq!(perms1.net.query(None), PermissionState::Granted);
-            assert_eq!(perms1.net.query(Some(&NetDescriptor(Host::must_parse("127.0.0.1"), None))), PermissionState::Granted);
+            assert_eq!(perms1.net.query(Some(&NetDescriptor(Host::must_parse("127.0.0.1"), None, None))), PermissionState::Granted);
             assert_eq!(perms2.net.query(None), PermissionState::Prompt);
-            assert_eq!(perms2.net.query(Some(&NetDescriptor(Host::must_parse("127.0.0.1"), Some(8000)))), PermissionState::Granted);
+            assert_eq!(perms2.net.query(Some(&NetDescriptor(Host::must_parse("127.0.0.1"), Some(8000.into()), None))), PermissionState::Granted);
             assert_eq!(perms3.net.query(None), PermissionState::Prompt);
-            assert_eq!(perms3.net.query(Some(&NetDescriptor(Host::must_parse("127.0.0.1"), Some(8000)))), PermissionState::Denied);
+            assert_eq!(perms3.net.query(Some(&NetDescriptor(Host::must_parse("127.0.0.1"), Some(8000.into()), None))), PermissionState::Denied);
             assert_eq!(perms4.net.query(None), PermissionState::GrantedPartial);
-            assert_eq!(perms4.net.query(Some(&NetDescriptor(Host::must_parse("127.0.0.1"), Some(8000)))), PermissionState::Denied);
-            assert_eq!(perms4.net.query(Some(&NetDescriptor(Host::must_parse("192.168.0.1"), Some(8000)))), PermissionState::Granted);
+            assert_eq!(perms4.net.query(Some(&NetDescriptor(Host::must_parse("127.0.0.1"), Some(8000.into()), None))), PermissionState::Denied);
+            assert_eq!(perms4.net.query(Some(&NetDescriptor(Host::must_parse("192.168.0.1"), Some(8000.into()), None))), PermissionState::Granted);
             assert_eq!(perms1.env.query(None), PermissionState::Granted);
             assert_eq!(perms1.env.query(Some("HOME")), PermissionState::Granted);
             assert_eq!(perms2.env.query(None), PermissionState::Prompt);
@@ -895,10 +1608,12 @@ mod tests {
             let deno_run_query = RunQueryDescriptor::Path {
                 requested: "deno".to_string(),
                 resolved: PathBuf::from("/deno"),
+                args: None,
             };
             let node_run_query = RunQueryDescriptor::Path {
                 requested: "node".to_string(),
                 resolved: PathBuf::from("/node"),
+                args: None,
             };
             assert_eq!(perms1.run.query(Some(&deno_run_query)), PermissionState::Granted);
             assert_eq!(perms1.write.query(Some(&write_query("/deno"))), PermissionState::Granted);
@@ -942,9 +1657,9 @@ mod tests {
             prompt_value.set(true);
             assert_eq!(perms.ffi.request(None), PermissionState::Denied);
             prompt_value.set(true);
-            assert_eq!(perms.net.request(Some(&NetDescriptor(Host::must_parse("127.0.0.1"), None))), PermissionState::Granted);
+            assert_eq!(perms.net.request(Some(&NetDescriptor(Host::must_parse("127.0.0.1"), None, None))), PermissionState::Granted);
             prompt_value.set(false);
-            assert_eq!(perms.net.request(Some(&NetDescriptor(Host::must_parse("127.0.0.1"), Some(8000)))), PermissionState::Granted);
+            assert_eq!(perms.net.request(Some(&NetDescriptor(Host::must_parse("127.0.0.1"), Some(8000.into()), None))), PermissionState::Granted);
             prompt_value.set(true);
             assert_eq!(perms.env.request(Some("HOME")), PermissionState::Granted);
             assert_eq!(perms.env.query(None), PermissionState::Prompt);
@@ -960,6 +1675,7 @@ mod tests {
             let run_query = RunQueryDescriptor::Path {
                 requested: "deno".to_string(),
                 resolved: PathBuf::from("/deno"),
+                args: None,
             };
             assert_eq!(perms.run.request(Some(&run_query)), PermissionState::Granted);
             assert_eq!(perms.run.query(None), PermissionState::Prompt);
@@ -1002,14 +1718,15 @@ mod tests {
         assert_eq!(perms.ffi.revoke(Some(&ffi_query("/foo/bar"))), PermissionState::Prompt);
         assert_eq!(perms.ffi.query(Some(&ffi_query("/foo"))), PermissionState::Prompt);
         assert_eq!(perms.ffi.query(Some(&ffi_query("/foo/baz"))), PermissionState::Granted);
-        assert_eq!(perms.net.revoke(Some(&NetDescriptor(Host::must_parse("127.0.0.1"), Some(9000)))), PermissionState::Prompt);
-        assert_eq!(perms.net.query(Some(&NetDescriptor(Host::must_parse("127.0.0.1"), None))), PermissionState::Prompt);
-        assert_eq!(perms.net.query(Some(&NetDescriptor(Host::must_parse("127.0.0.1"), Some(8000)))), PermissionState::Granted);
+        assert_eq!(perms.net.revoke(Some(&NetDescriptor(Host::must_parse("127.0.0.1"), Some(9000.into()), None))), PermissionState::Prompt);
+        assert_eq!(perms.net.query(Some(&NetDescriptor(Host::must_parse("127.0.0.1"), None, None))), PermissionState::Prompt);
+        assert_eq!(perms.net.query(Some(&NetDescriptor(Host::must_parse("127.0.0.1"), Some(8000.into()), None))), PermissionState::Granted);
         assert_eq!(perms.env.revoke(Some("HOME")), PermissionState::Prompt);
         assert_eq!(perms.env.revoke(Some("hostname")), PermissionState::Prompt);
         let run_query = RunQueryDescriptor::Path {
             requested: "deno".to_string(),
             resolved: PathBuf::from("/deno"),
+            args: None,
         };
         assert_eq!(perms.run.revoke(Some(&run_query)), PermissionState::Prompt);
     };
@@ -1047,7 +1764,7 @@ mod tests {
         assert!(perms
             .net
             .check(
-                &NetDescriptor(Host::must_parse("127.0.0.1"), Some(8000)),
+                &NetDescriptor(Host::must_parse("127.0.0.1"), Some(8000.into()), None),
                 None
             )
             .is_ok());
@@ -1055,31 +1772,37 @@ mod tests {
         assert!(perms
             .net
             .check(
-                &NetDescriptor(Host::must_parse("127.0.0.1"), Some(8000)),
+                &NetDescriptor(Host::must_parse("127.0.0.1"), Some(8000.into()), None),
                 None
             )
             .is_ok());
         assert!(perms
             .net
             .check(
-                &NetDescriptor(Host::must_parse("127.0.0.1"), Some(8001)),
+                &NetDescriptor(Host::must_parse("127.0.0.1"), Some(8001.into()), None),
                 None
             )
             .is_err());
         assert!(perms
             .net
-            .check(&NetDescriptor(Host::must_parse("127.0.0.1"), None), None)
+            .check(
+                &NetDescriptor(Host::must_parse("127.0.0.1"), None, None),
+                None
+            )
             .is_err());
         assert!(perms
             .net
             .check(
-                &NetDescriptor(Host::must_parse("deno.land"), Some(8000)),
+                &NetDescriptor(Host::must_parse("deno.land"), Some(8000.into()), None),
                 None
             )
             .is_err());
         assert!(perms
             .net
-            .check(&NetDescriptor(Host::must_parse("deno.land"), None), None)
+            .check(
+                &NetDescriptor(Host::must_parse("deno.land"), None, None),
+                None
+            )
             .is_err());
 
         #[allow(clippy::disallowed_methods)]
@@ -1090,7 +1813,8 @@ mod tests {
             .check(
                 &RunQueryDescriptor::Path {
                     requested: "cat".to_string(),
-                    resolved: cwd.join("cat")
+                    resolved: cwd.join("cat"),
+                    args: None
                 },
                 None
             )
@@ -1101,7 +1825,8 @@ mod tests {
             .check(
                 &RunQueryDescriptor::Path {
                     requested: "cat".to_string(),
-                    resolved: cwd.join("cat")
+                    resolved: cwd.join("cat"),
+                    args: None
                 },
                 None
             )
@@ -1111,7 +1836,8 @@ mod tests {
             .check(
                 &RunQueryDescriptor::Path {
                     requested: "ls".to_string(),
-                    resolved: cwd.join("ls")
+                    resolved: cwd.join("ls"),
+                    args: None
                 },
                 None
             )
@@ -1169,7 +1895,7 @@ mod tests {
         assert!(perms
             .net
             .check(
-                &NetDescriptor(Host::must_parse("127.0.0.1"), Some(8000)),
+                &NetDescriptor(Host::must_parse("127.0.0.1"), Some(8000.into()), None),
                 None
             )
             .is_err());
@@ -1177,21 +1903,21 @@ mod tests {
         assert!(perms
             .net
             .check(
-                &NetDescriptor(Host::must_parse("127.0.0.1"), Some(8000)),
+                &NetDescriptor(Host::must_parse("127.0.0.1"), Some(8000.into()), None),
                 None
             )
             .is_err());
         assert!(perms
             .net
             .check(
-                &NetDescriptor(Host::must_parse("127.0.0.1"), Some(8001)),
+                &NetDescriptor(Host::must_parse("127.0.0.1"), Some(8001.into()), None),
                 None
             )
             .is_ok());
         assert!(perms
             .net
             .check(
-                &NetDescriptor(Host::must_parse("deno.land"), Some(8000)),
+                &NetDescriptor(Host::must_parse("deno.land"), Some(8000.into()), None),
                 None
             )
             .is_ok());
@@ -1199,14 +1925,14 @@ mod tests {
         assert!(perms
             .net
             .check(
-                &NetDescriptor(Host::must_parse("127.0.0.1"), Some(8001)),
+                &NetDescriptor(Host::must_parse("127.0.0.1"), Some(8001.into()), None),
                 None
             )
             .is_ok());
         assert!(perms
             .net
             .check(
-                &NetDescriptor(Host::must_parse("deno.land"), Some(8000)),
+                &NetDescriptor(Host::must_parse("deno.land"), Some(8000.into()), None),
                 None
             )
             .is_ok());
@@ -1219,7 +1945,8 @@ mod tests {
             .check(
                 &RunQueryDescriptor::Path {
                     requested: "cat".to_string(),
-                    resolved: cwd.join("cat")
+                    resolved: cwd.join("cat"),
+                    args: None
                 },
                 None
             )
@@ -1230,7 +1957,8 @@ mod tests {
             .check(
                 &RunQueryDescriptor::Path {
                     requested: "cat".to_string(),
-                    resolved: cwd.join("cat")
+                    resolved: cwd.join("cat"),
+                    args: None
                 },
                 None
             )
@@ -1240,7 +1968,8 @@ mod tests {
             .check(
                 &RunQueryDescriptor::Path {
                     requested: "ls".to_string(),
-                    resolved: cwd.join("ls")
+                    resolved: cwd.join("ls"),
+                    args: None
                 },
                 None
             )
@@ -1251,7 +1980,8 @@ mod tests {
             .check(
                 &RunQueryDescriptor::Path {
                     requested: "ls".to_string(),
-                    resolved: cwd.join("ls")
+                    resolved: cwd.join("ls"),
+                    args: None
                 },
                 None
             )
@@ -1302,90 +2032,1918 @@ mod tests {
     }
 
     #[test]
-    fn test_check_partial_denied() {
+    fn test_run_args() {
         let _locked = TESTMUTEX.lock();
+        set_prompter(Box::new(TestPrompter));
+        let prompt_value = PERMISSION_PROMPT_STUB_VALUE_SETTER.lock();
         let parser = TestPermissionDescriptorParser;
         let mut perms = Permissions::from_options(
             &parser,
             &PermissionsOptions {
-                allow_read: Some(vec![]),
-                deny_read: Some(svec!["/foo/bar"]),
-                allow_write: Some(vec![]),
-                deny_write: Some(svec!["/foo/bar"]),
+                allow_run: Some(svec![
+                    "git[sub=status,log]",
+                    "npm[prefix=run build]",
+                    "deno[regex=run( --quiet)? \\S+\\.ts]",
+                    "ls"
+                ]),
                 ..Default::default()
             },
         )
         .unwrap();
+        let cmd = |name: &str, args: Option<&[&str]>| {
+            let query = RunQueryDescriptor::Path {
+                requested: name.to_string(),
+                resolved: PathBuf::from("/").join(name),
+                args: None,
+            };
+            match args {
+                Some(args) => query.with_args(args.iter().map(|arg| arg.to_string()).collect()),
+                None => query,
+            }
+        };
 
-        let read_query = parser.parse_path_query("/foo").unwrap().into_read();
-        perms.read.check_partial(&read_query, None).unwrap();
-        assert!(perms.read.check(&read_query, None).is_err());
+        prompt_value.set(false);
+        #[rustfmt::skip]
+        let cases: [(&str, &[&str], bool); 11] = [
+            ("git", &["status"], true),
+            ("git", &["log", "--oneline"], true),
+            ("git", &["push", "--force"], false),
+            ("git", &[], false),
+            ("npm", &["run", "build", "--prod"], true),
+            ("npm", &["run", "test"], false),
+            ("deno", &["run", "main.ts"], true),
+            ("deno", &["run", "--quiet", "main.ts"], true),
+            ("deno", &["run", "main.ts;", "rm"], false),
+            ("deno", &["run --quiet", "main.ts"], false),
+            ("ls", &["-la"], true),
+        ];
+        for (name, args, expected) in cases {
+            let query = cmd(name, Some(args));
+            assert_eq!(
+                perms.run.check(&query, None).is_ok(),
+                expected,
+                "{name} {args:?}"
+            );
+        }
+        // without arguments only unrestricted commands are granted
+        assert!(perms.run.check(&cmd("git", None), None).is_err());
+        assert!(perms.run.check(&cmd("ls", None), None).is_ok());
+        assert_eq!(
+            perms.run.query(Some(&cmd("git", None))),
+            PermissionState::GrantedPartial
+        );
+        assert_eq!(
+            perms.run.query(Some(&cmd("ls", None))),
+            PermissionState::Granted
+        );
 
-        let write_query = parser.parse_path_query("/foo").unwrap().into_write();
-        perms.write.check_partial(&write_query, None).unwrap();
-        assert!(perms.write.check(&write_query, None).is_err());
+        // the prompt shows and grants the full command line only
+        let push = cmd("git", Some(&["push", "--force", "origin main"]));
+        assert_eq!(push.display_name(), "git push --force \"origin main\"");
+        perms.run.prompt = true;
+        prompt_value.set(true);
+        assert!(perms.run.check(&push, None).is_ok());
+        perms.run.prompt = false;
+        assert!(perms.run.check(&push, None).is_ok());
+        assert!(perms.run.check(&cmd("git", Some(&["push"])), None).is_err());
+
+        // revoking a command line only drops the grants allowing it
+        assert_eq!(
+            perms.run.revoke(Some(&cmd("git", Some(&["status"])))),
+            PermissionState::Prompt
+        );
+        assert!(perms.run.check(&push, None).is_ok());
+        assert!(perms.run.check(&cmd("git", Some(&["log"])), None).is_err());
+        assert_eq!(
+            perms.run.revoke(Some(&cmd("git", None))),
+            PermissionState::Prompt
+        );
+        assert!(perms.run.check(&push, None).is_err());
+
+        for text in [
+            "git[sub=]",
+            "git[sub=a,,b]",
+            "git[prefix=]",
+            "git[regex=(]",
+            "git[sub=status",
+        ] {
+            assert!(parser.parse_allow_run_descriptor(text).is_err(), "{text}");
+        }
+        let lints = PermissionsOptions {
+            allow_run: Some(svec!["git", "git[sub=status]"]),
+            ..Default::default()
+        }
+        .lint(&parser);
+        assert!(lints
+            .iter()
+            .any(|lint| lint.kind == LintKind::Redundant && lint.entry == "git[sub=status]"));
     }
 
     #[test]
-    fn test_net_fully_qualified_domain_name() {
+    fn test_run_pinned_digest() {
         let _locked = TESTMUTEX.lock();
+        set_prompter(Box::new(TestPrompter));
+        let dir =
+            std::env::temp_dir().join(format!("bls_permissions_pinned_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let tool = dir.join("tool");
+        let other = dir.join("other");
+        std::fs::write(&tool, "#!/bin/sh\necho tool\n").unwrap();
+        std::fs::write(&other, "#!/bin/sh\necho other\n").unwrap();
+        let digest = Sha256Digest::of_bytes(b"#!/bin/sh\necho tool\n");
+        assert_eq!(Sha256Digest::of_file(&tool).unwrap(), digest);
+        assert_eq!(Sha256Digest::parse(&digest.to_string()).unwrap(), digest);
+
         let parser = TestPermissionDescriptorParser;
-        let perms = Permissions::from_options(
+        let mut perms = Permissions::from_options(
             &parser,
             &PermissionsOptions {
-                allow_net: Some(svec!["allowed.domain", "1.1.1.1"]),
-                deny_net: Some(svec!["denied.domain", "2.2.2.2"]),
+                allow_run: Some(vec![
+                    format!("{}@{digest}[sub=build]", tool.display()),
+                    format!("{}@{digest}", other.display()),
+                ]),
+                quotas: QuotaOptions {
+                    run: BTreeMap::from([(
+                        format!("{}@{digest}", other.display()),
+                        Quota {
+                            total_checks: Some(1),
+                            ..Default::default()
+                        },
+                    )]),
+                    ..Default::default()
+                },
                 ..Default::default()
             },
         )
         .unwrap();
-        let mut perms = PermissionsContainer::new(Arc::new(parser), perms);
-        set_prompter(Box::new(TestPrompter));
-        let cases = [
-            ("allowed.domain.", true),
-            ("1.1.1.1", true),
-            ("denied.domain.", false),
-            ("2.2.2.2", false),
-        ];
+        let cmd = |path: &Path, args: &[&str]| {
+            RunQueryDescriptor::Path {
+                requested: path.display().to_string(),
+                resolved: path.to_path_buf(),
+                args: None,
+            }
+            .with_args(args.iter().map(|arg| arg.to_string()).collect())
+        };
 
-        for (host, is_ok) in cases {
-            assert_eq!(perms.check_net(&(host, None), "api").is_ok(), is_ok);
+        assert!(perms.run.check(&cmd(&tool, &["build"]), None).is_ok());
+        assert!(perms.run.check(&cmd(&tool, &["test"]), None).is_err());
+        // a mismatch is found before the quota is used up
+        for _ in 0..2 {
+            let err = perms.run.check(&cmd(&other, &[]), None).unwrap_err();
+            assert!(
+                err.to_string()
+                    .contains("does not match its pinned sha256 digest"),
+                "{err}"
+            );
         }
+
+        // replacing the binary invalidates the cached digest, even with the
+        // same size and modification time
+        let modified = std::fs::metadata(&tool).unwrap().modified().unwrap();
+        std::fs::write(&tool, "#!/bin/sh\necho toot\n").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&tool)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let err = perms.run.check(&cmd(&tool, &["build"]), None).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("does not match its pinned sha256 digest"),
+            "{err}"
+        );
+
+        // an unpinned grant for the same executable doesn't require a digest
+        perms
+            .run
+            .granted_list
+            .insert(AllowRunDescriptor(tool.clone(), None, None));
+        assert!(perms.run.check(&cmd(&tool, &["build"]), None).is_ok());
+
+        assert!(parser
+            .parse_allow_run_descriptor("/tool@sha256:abc")
+            .is_err());
+        assert!(Sha256Digest::parse("md5:00").is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
-    fn test_deserialize_child_permissions_arg() {
+    fn test_ffi_pinned_symbols() {
         let _locked = TESTMUTEX.lock();
         set_prompter(Box::new(TestPrompter));
+        let dir = std::env::temp_dir().join(format!("bls_permissions_ffi_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("libmath.so");
+        let plain = dir.join("libplain.so");
+        std::fs::write(&lib, b"math").unwrap();
+        std::fs::write(&plain, b"plain").unwrap();
+        let digest = Sha256Digest::of_bytes(b"math");
+
+        let parser = TestPermissionDescriptorParser;
+        let perms = Permissions::from_options(
+            &parser,
+            &PermissionsOptions {
+                allow_ffi: Some(vec![
+                    format!("{}@{digest}[symbols=add,sub]", lib.display()),
+                    plain.display().to_string(),
+                ]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut perms = PermissionsContainer::new(Arc::new(TestPermissionDescriptorParser), perms);
+        let lib_path = lib.to_str().unwrap();
+        let plain_path = plain.to_str().unwrap();
+
+        assert!(perms.check_ffi(lib_path).is_ok());
+        assert!(perms.check_ffi_partial_with_path(lib_path).is_ok());
+        assert!(perms.check_ffi_symbol(lib_path, "add").is_ok());
+        let err = perms.check_ffi_symbol(lib_path, "mul").unwrap_err();
+        assert!(
+            err.to_string().contains("not in the allowed symbols"),
+            "{err}"
+        );
+        assert!(perms.check_ffi_symbol(plain_path, "anything").is_ok());
+
+        // a replaced library no longer matches its pinned digest
+        std::fs::write(&lib, b"evil math").unwrap();
+        let err = perms.check_ffi(lib_path).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("does not match its pinned sha256 digest"),
+            "{err}"
+        );
+        assert!(perms.check_ffi_symbol(lib_path, "add").is_err());
+        assert!(perms.check_ffi_partial_with_path(lib_path).is_err());
+
+        // deny entries can't be pinned
+        assert!(Permissions::from_options(
+            &parser,
+            &PermissionsOptions {
+                deny_ffi: Some(svec!["/lib/libfoo.so[symbols=add]"]),
+                ..Default::default()
+            },
+        )
+        .is_err());
+        assert!(parser
+            .parse_ffi_descriptor("/lib/libfoo.so[symbols=a,,b]")
+            .is_err());
+        assert!(parser
+            .parse_ffi_descriptor("/lib/libfoo.so[symbols=a")
+            .is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_env_patterns() {
+        let _locked = TESTMUTEX.lock();
+        set_prompter(Box::new(TestPrompter));
+        let parser = TestPermissionDescriptorParser;
+        let mut perms = Permissions::from_options(
+            &parser,
+            &PermissionsOptions {
+                allow_env: Some(svec!["AWS_*", "BLS_*", "HOME"]),
+                deny_env: Some(svec!["*_SECRET", "BLS_TOKEN"]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        #[rustfmt::skip]
+        let cases = [
+            ("AWS_REGION", true),
+            ("AWS_", true),
+            ("BLS_NODE", true),
+            ("HOME", true),
+            ("HOME_DIR", false),
+            ("PATH", false),
+            ("AWS_SECRET", false),
+            ("BLS_TOKEN", false),
+            ("XAWS_REGION", false),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(perms.env.check(name, None).is_ok(), expected, "{name}");
+        }
+
         assert_eq!(
-            ChildPermissionsArg::inherit(),
-            ChildPermissionsArg {
-                env: ChildUnaryPermissionArg::Inherit,
-                net: ChildUnaryPermissionArg::Inherit,
-                ffi: ChildUnaryPermissionArg::Inherit,
-                import: ChildUnaryPermissionArg::Inherit,
-                read: ChildUnaryPermissionArg::Inherit,
-                run: ChildUnaryPermissionArg::Inherit,
-                sys: ChildUnaryPermissionArg::Inherit,
-                write: ChildUnaryPermissionArg::Inherit,
-            }
+            perms.env.query(Some("AWS_*")),
+            PermissionState::GrantedPartial
         );
         assert_eq!(
-            ChildPermissionsArg::none(),
-            ChildPermissionsArg {
-                env: ChildUnaryPermissionArg::NotGranted,
-                net: ChildUnaryPermissionArg::NotGranted,
-                ffi: ChildUnaryPermissionArg::NotGranted,
-                import: ChildUnaryPermissionArg::NotGranted,
-                read: ChildUnaryPermissionArg::NotGranted,
-                run: ChildUnaryPermissionArg::NotGranted,
-                sys: ChildUnaryPermissionArg::NotGranted,
-                write: ChildUnaryPermissionArg::NotGranted,
-            }
+            perms.env.query(Some("AWS_REGION_*")),
+            PermissionState::GrantedPartial
         );
         assert_eq!(
-            serde_json::from_value::<ChildPermissionsArg>(json!("inherit")).unwrap(),
-            ChildPermissionsArg::inherit()
+            perms.env.query(Some("BLS_NODE_*")),
+            PermissionState::GrantedPartial
+        );
+        assert_eq!(perms.env.query(Some("BLS_TOKEN")), PermissionState::Denied);
+        assert_eq!(perms.env.query(Some("*_SECRET")), PermissionState::Denied);
+        assert_eq!(perms.env.query(Some("DB_*")), PermissionState::Prompt);
+        assert_eq!(perms.env.query(Some("HOME")), PermissionState::Granted);
+
+        // revoking a single variable drops the pattern that granted it
+        assert_eq!(
+            perms.env.revoke(Some("AWS_REGION")),
+            PermissionState::Prompt
+        );
+        assert_eq!(
+            perms.env.query(Some("AWS_PROFILE")),
+            PermissionState::Prompt
+        );
+        assert_eq!(perms.env.query(Some("BLS_NODE")), PermissionState::Granted);
+
+        assert!(EnvDescriptor::parse("*").is_ok());
+        assert!(EnvDescriptor::parse("").is_err());
+        assert!(EnvDescriptor::parse("A*B").is_err());
+        assert!(EnvDescriptor::parse("*A*").is_err());
+        assert!(EnvDescriptor::parse("A**").is_err());
+    }
+
+    #[test]
+    fn test_check_partial_denied() {
+        let _locked = TESTMUTEX.lock();
+        let parser = TestPermissionDescriptorParser;
+        let mut perms = Permissions::from_options(
+            &parser,
+            &PermissionsOptions {
+                allow_read: Some(vec![]),
+                deny_read: Some(svec!["/foo/bar"]),
+                allow_write: Some(vec![]),
+                deny_write: Some(svec!["/foo/bar"]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let read_query = parser.parse_path_query("/foo").unwrap().into_read();
+        perms.read.check_partial(&read_query, None).unwrap();
+        assert!(perms.read.check(&read_query, None).is_err());
+
+        let write_query = parser.parse_path_query("/foo").unwrap().into_write();
+        perms.write.check_partial(&write_query, None).unwrap();
+        assert!(perms.write.check(&write_query, None).is_err());
+    }
+
+    #[test]
+    fn test_net_wildcard_host() {
+        let _locked = TESTMUTEX.lock();
+        set_prompter(Box::new(TestPrompter));
+        let parser = TestPermissionDescriptorParser;
+        let perms = Permissions::from_options(
+            &parser,
+            &PermissionsOptions {
+                allow_net: Some(svec!["*.blockless.network", "*.example.com:443"]),
+                deny_net: Some(svec!["evil.blockless.network", "*.internal.example.com"]),
+                allow_import: Some(svec!["*.esm.sh"]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut perms = PermissionsContainer::new(Arc::new(parser), perms);
+
+        #[rustfmt::skip]
+        let cases = [
+            ("api.blockless.network", None, true),
+            ("a.b.blockless.network", Some(8080), true),
+            ("blockless.network", None, false),
+            ("evil.blockless.network", None, false),
+            ("notblockless.network", None, false),
+            ("www.example.com", Some(443), true),
+            ("www.example.com", Some(80), false),
+            ("db.internal.example.com", Some(443), false),
+        ];
+        for (host, port, expected) in cases {
+            assert_eq!(
+                perms.check_net(&(host, port), "api").is_ok(),
+                expected,
+                "{host}:{port:?}"
+            );
+        }
+
+        assert_eq!(
+            perms.query_net(Some("*.blockless.network")).unwrap(),
+            PermissionState::GrantedPartial
+        );
+        assert_eq!(
+            perms.query_net(Some("api.blockless.network")).unwrap(),
+            PermissionState::Granted
+        );
+        assert_eq!(
+            perms.query_net(Some("*.evil.blockless.network")).unwrap(),
+            PermissionState::Granted
+        );
+        assert_eq!(
+            perms.query_net(Some("internal.example.com:443")).unwrap(),
+            PermissionState::GrantedPartial
+        );
+        assert_eq!(
+            perms.query_net(Some("*.internal.example.com:443")).unwrap(),
+            PermissionState::Denied
+        );
+
+        let specifier = ModuleSpecifier::parse("https://esm.sh/preact").unwrap();
+        assert!(perms
+            .check_specifier(&specifier, CheckSpecifierKind::Static)
+            .is_err());
+        let specifier = ModuleSpecifier::parse("https://cdn.esm.sh/preact").unwrap();
+        assert!(perms
+            .check_specifier(&specifier, CheckSpecifierKind::Static)
+            .is_ok());
+
+        // a denied port on a host only partially denies the host
+        let mut perms = Permissions::from_options(
+            &TestPermissionDescriptorParser,
+            &PermissionsOptions {
+                allow_net: Some(vec![]),
+                deny_net: Some(svec!["example.com:22"]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let query = |host: &str| NetDescriptor::parse(host).unwrap();
+        assert_eq!(
+            perms.net.query(Some(&query("example.com"))),
+            PermissionState::GrantedPartial
+        );
+        assert_eq!(
+            perms.net.query(Some(&query("example.com:443"))),
+            PermissionState::Granted
+        );
+        assert_eq!(
+            perms.net.query(Some(&query("example.com:22"))),
+            PermissionState::Denied
+        );
+        assert!(perms.net.check(&query("example.com:443"), None).is_ok());
+    }
+
+    #[test]
+    fn test_net_cidr() {
+        let _locked = TESTMUTEX.lock();
+        set_prompter(Box::new(TestPrompter));
+        let parser = TestPermissionDescriptorParser;
+        let perms = Permissions::from_options(
+            &parser,
+            &PermissionsOptions {
+                allow_net: Some(svec!["10.0.0.0/8:443", "[fd00::/8]", "169.254.0.0/16"]),
+                deny_net: Some(svec!["169.254.169.254", "10.1.0.0/16"]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut perms = PermissionsContainer::new(Arc::new(parser), perms);
+
+        #[rustfmt::skip]
+        let cases = [
+            ("10.2.3.4", Some(443), true),
+            ("10.2.3.4", Some(80), false),
+            ("10.1.3.4", Some(443), false),
+            ("11.0.0.1", Some(443), false),
+            ("fd12::1", Some(80), true),
+            ("fe80::1", Some(80), false),
+            ("169.254.1.1", None, true),
+            ("169.254.169.254", None, false),
+            // IPv4-mapped IPv6 addresses are the same host as their IPv4 form
+            ("::ffff:169.254.169.254", None, false),
+            ("[::ffff:169.254.169.254]", None, false),
+            ("::ffff:10.2.3.4", Some(443), true),
+        ];
+        for (host, port, expected) in cases {
+            assert_eq!(
+                perms.check_net(&(host, port), "api").is_ok(),
+                expected,
+                "{host}:{port:?}"
+            );
+        }
+
+        assert_eq!(
+            perms.query_net(Some("10.2.0.0/16:443")).unwrap(),
+            PermissionState::Granted
+        );
+        assert_eq!(
+            perms.query_net(Some("10.0.0.0/8:443")).unwrap(),
+            PermissionState::GrantedPartial
+        );
+        assert_eq!(
+            perms.query_net(Some("10.1.2.0/24:443")).unwrap(),
+            PermissionState::Denied
+        );
+        assert_eq!(
+            perms.query_net(Some("169.254.0.0/16")).unwrap(),
+            PermissionState::GrantedPartial
+        );
+        assert_eq!(
+            perms.query_net(Some("[::ffff:169.254.169.254]")).unwrap(),
+            PermissionState::Denied
+        );
+    }
+
+    #[test]
+    fn test_net_port_ranges() {
+        let _locked = TESTMUTEX.lock();
+        set_prompter(Box::new(TestPrompter));
+        let parser = TestPermissionDescriptorParser;
+        let mut perms = Permissions::from_options(
+            &parser,
+            &PermissionsOptions {
+                allow_net: Some(svec!["localhost:8000-8100", "deno.land:80,443"]),
+                deny_net: Some(svec!["localhost:8080"]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        for (text, display) in [
+            ("localhost:8000-8100", "localhost:8000-8100"),
+            ("deno.land:443,80,81-90", "deno.land:80-90,443"),
+            ("[::1]:80,443", "[::1]:80,443"),
+        ] {
+            assert_eq!(NetDescriptor::parse(text).unwrap().to_string(), display);
+        }
+
+        #[rustfmt::skip]
+        let cases = [
+            ("localhost", Some(8000), true),
+            ("localhost", Some(8100), true),
+            ("localhost", Some(8101), false),
+            ("localhost", Some(8080), false),
+            ("deno.land", Some(443), true),
+            ("deno.land", Some(8080), false),
+            ("deno.land", None, false),
+        ];
+        for (host, port, expected) in cases {
+            let desc = NetDescriptor(Host::must_parse(host), port.map(NetPort::from), None);
+            assert_eq!(
+                perms.net.check(&desc, None).is_ok(),
+                expected,
+                "{host}:{port:?}"
+            );
+        }
+
+        let query = |host: &str| NetDescriptor::parse(host).unwrap();
+        assert_eq!(
+            perms.net.query(Some(&query("localhost:8000-8090"))),
+            PermissionState::GrantedPartial
+        );
+        assert_eq!(
+            perms.net.query(Some(&query("localhost:8000-8010"))),
+            PermissionState::Granted
+        );
+        assert_eq!(
+            perms.net.query(Some(&query("localhost:8090-8200"))),
+            PermissionState::GrantedPartial
+        );
+        assert_eq!(
+            perms.net.query(Some(&query("localhost:8080"))),
+            PermissionState::Denied
+        );
+        assert_eq!(
+            perms.net.query(Some(&query("localhost:9000-9100"))),
+            PermissionState::Prompt
+        );
+        assert_eq!(
+            perms.net.query(Some(&query("localhost"))),
+            PermissionState::Prompt
+        );
+        assert_eq!(
+            perms.net.query(Some(&query("deno.land:80,443"))),
+            PermissionState::Granted
+        );
+        assert_eq!(
+            perms.net.query(Some(&query("deno.land:80-443"))),
+            PermissionState::GrantedPartial
+        );
+
+        // revoking part of a range drops the whole grant
+        assert_eq!(
+            perms.net.revoke(Some(&query("localhost:8000"))),
+            PermissionState::Prompt
+        );
+        assert_eq!(
+            perms.net.query(Some(&query("localhost:8001"))),
+            PermissionState::Prompt
+        );
+        // revoking a range drops the grants for any of its ports
+        assert_eq!(
+            perms.net.revoke(Some(&query("deno.land:1-100"))),
+            PermissionState::Prompt
+        );
+        assert_eq!(
+            perms.net.query(Some(&query("deno.land:443"))),
+            PermissionState::Prompt
+        );
+    }
+
+    #[test]
+    fn test_net_schemes() {
+        let _locked = TESTMUTEX.lock();
+        set_prompter(Box::new(TestPrompter));
+        let parser = TestPermissionDescriptorParser;
+        let perms = Permissions::from_options(
+            &parser,
+            &PermissionsOptions {
+                allow_net: Some(svec![
+                    "https://api.example.com",
+                    "wss://api.example.com",
+                    "deno.land"
+                ]),
+                deny_net: Some(svec!["http://deno.land"]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut perms = PermissionsContainer::new(Arc::new(parser), perms);
+
+        assert_eq!(
+            NetDescriptor::parse("HTTPS://api.example.com:443")
+                .unwrap()
+                .to_string(),
+            "https://api.example.com:443"
+        );
+
+        let url_tests = [
+            ("https://api.example.com/v1", true),
+            ("wss://api.example.com:8443", true),
+            ("http://api.example.com/v1", false),
+            ("ws://api.example.com", false),
+            ("https://deno.land/x", true),
+            ("http://deno.land/x", false),
+        ];
+        for (url_str, is_ok) in url_tests {
+            let url = Url::parse(url_str).unwrap();
+            assert_eq!(perms.check_net_url(&url, "api()").is_ok(), is_ok, "{url}");
+        }
+
+        // without a scheme, only scheme-less grants apply
+        assert!(perms
+            .check_net(&("api.example.com", Some(443)), "api()")
+            .is_err());
+        // a connection without a scheme could use the denied one
+        assert!(perms.check_net(&("deno.land", Some(443)), "api()").is_err());
+
+        let mut inner = perms.0.inner.lock();
+        let query = |text: &str| NetDescriptor::parse(text).unwrap();
+        assert_eq!(
+            inner.net.query(Some(&query("https://api.example.com:443"))),
+            PermissionState::Granted
+        );
+        assert_eq!(
+            inner.net.query(Some(&query("api.example.com:443"))),
+            PermissionState::Prompt
+        );
+        assert_eq!(
+            inner.net.query(Some(&query("deno.land"))),
+            PermissionState::Denied
+        );
+        assert_eq!(
+            inner.net.query(Some(&query("https://deno.land"))),
+            PermissionState::Granted
+        );
+        assert_eq!(
+            inner.net.query(Some(&query("http://deno.land"))),
+            PermissionState::Denied
+        );
+        // revoking every scheme drops the scheme-specific grants too
+        inner.net.revoke(Some(&query("api.example.com")));
+        assert_eq!(
+            inner.net.query(Some(&query("wss://api.example.com"))),
+            PermissionState::Prompt
+        );
+    }
+
+    #[test]
+    fn test_sys_kind_registry() {
+        let _locked = TESTMUTEX.lock();
+        let mut kinds = SysKinds::default();
+        kinds.register("nodeId", "the id of this node").unwrap();
+        assert!(kinds.register("hostname", "").is_err());
+        assert!(kinds.register("memory:*", "").is_err());
+        assert!(kinds.register("", "").is_err());
+        assert!(kinds.list().iter().any(|kind| kind.name == "nodeId"
+            && kind.description.as_deref() == Some("the id of this node")));
+        assert!(SysKinds::default()
+            .resolve(SysDescriptor::parse("nodeId".to_string()).unwrap())
+            .is_err());
+        assert_eq!(
+            kinds
+                .resolve(SysDescriptor::parse("nodeId".to_string()).unwrap())
+                .unwrap()
+                .display_name(),
+            "nodeId (the id of this node)"
+        );
+        assert!(SysDescriptor::parse("cpu:".to_string()).is_err());
+        assert!(SysDescriptor::parse("cpu:*".to_string()).is_ok());
+
+        let parser = TestPermissionDescriptorParser;
+        let mut opts = PermissionsOptions {
+            allow_sys: Some(svec!["nodeId", "memory:*"]),
+            deny_sys: Some(svec!["memory:free"]),
+            prompt: true,
+            ..Default::default()
+        };
+        // entries must name a known kind
+        assert!(Permissions::from_options(&parser, &opts).is_err());
+        let lints = opts.lint(&parser);
+        assert!(lints
+            .iter()
+            .any(|lint| lint.kind == LintKind::Invalid && lint.entry == "nodeId"));
+        let policy = "allow_sys = [\"nodeId\"]";
+        assert!(
+            PermissionsOptions::from_policy_str(&parser, policy, PolicyFormat::Toml, "policy")
+                .is_err()
+        );
+        let typo = PermissionsOptions {
+            allow_sys: Some(svec!["hostnme"]),
+            ..Default::default()
+        };
+        let err = Permissions::from_options(&parser, &typo).unwrap_err();
+        assert!(err.to_string().contains("\"hostnme\""), "{err}");
+        assert_eq!(typo.lint(&parser)[0].kind, LintKind::Invalid);
+        let policy = "allow_sys = [\"hostnme\"]";
+        let err =
+            PermissionsOptions::from_policy_str(&parser, policy, PolicyFormat::Toml, "policy")
+                .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("invalid allow_sys entry 'hostnme'"),
+            "{err}"
+        );
+
+        opts.sys_kinds
+            .register("nodeId", "the id of this node")
+            .unwrap();
+        opts.sys_kinds
+            .register("memory:total", "the total memory")
+            .unwrap();
+        opts.sys_kinds
+            .register("memory:free", "the free memory")
+            .unwrap();
+        assert!(opts
+            .lint(&parser)
+            .iter()
+            .all(|lint| lint.kind != LintKind::Invalid));
+        let policy = "allow_sys = [\"nodeId\"]\ndeny_sys = [\"memory:free\"]";
+        let loaded = opts
+            .clone()
+            .with_policy_str(&parser, policy, PolicyFormat::Toml, "policy")
+            .unwrap();
+        assert_eq!(loaded.sys_kinds, opts.sys_kinds);
+        let perms = Permissions::from_options(&parser, &opts).unwrap();
+        let perms = PermissionsContainer::new(Arc::new(parser), perms);
+        set_prompter(Box::new(TestPrompter));
+        assert!(perms
+            .sys_kinds()
+            .iter()
+            .any(|kind| kind.name == "memory:free"));
+        // the registry belongs to this container only
+        let other = PermissionsContainer::new(
+            Arc::new(TestPermissionDescriptorParser),
+            Permissions::none_without_prompt(),
+        );
+        assert!(other.query_sys(Some("nodeId")).is_err());
+
+        assert!(perms.check_sys("nodeId", "Bls.nodeId").is_ok());
+        assert!(perms.check_sys("memory:total", "Bls.memory").is_ok());
+        assert!(perms.check_sys("memory:free", "Bls.memory").is_err());
+        assert!(perms.check_sys("gpu", "Bls.gpu").is_err());
+        assert_eq!(
+            perms.query_sys(Some("memory:total")).unwrap(),
+            PermissionState::Granted
+        );
+        assert_eq!(
+            perms.query_sys(Some("memory:*")).unwrap(),
+            PermissionState::GrantedPartial
+        );
+        assert_eq!(
+            perms.query_sys(Some("memory:free")).unwrap(),
+            PermissionState::Denied
+        );
+        assert_eq!(
+            perms.query_sys(Some("hostname")).unwrap(),
+            PermissionState::Prompt
+        );
+        assert!(perms.query_sys(Some("gpu")).is_err());
+
+        {
+            let prompt_value = PERMISSION_PROMPT_STUB_VALUE_SETTER.lock();
+            prompt_value.set(false);
+            let err = perms.check_sys("hostname", "Deno.hostname").unwrap_err();
+            assert!(err.to_string().contains("\"hostname\""), "{err}");
+        }
+        // a prompt-denied kind keeps its whole group from reading as granted
+        {
+            let prompt_value = PERMISSION_PROMPT_STUB_VALUE_SETTER.lock();
+            prompt_value.set(false);
+            perms
+                .register_sys_kind("disk:free", "the free disk space")
+                .unwrap();
+            assert!(perms.check_sys("disk:free", "Bls.disk").is_err());
+        }
+        assert_eq!(
+            perms.query_sys(Some("disk:*")).unwrap(),
+            PermissionState::Denied
+        );
+
+        let child = perms
+            .create_child_permissions(ChildPermissionsArg::inherit())
+            .unwrap();
+        assert!(child.check_sys("nodeId", "Bls.nodeId").is_ok());
+        let restored = PermissionsContainer::new(
+            Arc::new(TestPermissionDescriptorParser),
+            Permissions::from_snapshot(perms.0.snapshot()).unwrap(),
+        );
+        assert_eq!(
+            restored.query_sys(Some("memory:total")).unwrap(),
+            PermissionState::Granted
+        );
+
+        assert_eq!(
+            perms.revoke_sys(Some("memory:total")).unwrap(),
+            PermissionState::Prompt
+        );
+        assert_eq!(
+            perms.query_sys(Some("memory:total")).unwrap(),
+            PermissionState::Prompt
+        );
+        assert_eq!(
+            perms.query_sys(Some("nodeId")).unwrap(),
+            PermissionState::Granted
+        );
+    }
+
+    #[derive(Clone, Debug, Eq, PartialEq, Hash, serde::Serialize, serde::Deserialize)]
+    struct KvDescriptor(String);
+
+    impl QueryDescriptor for KvDescriptor {
+        type AllowDesc = KvDescriptor;
+        type DenyDesc = KvDescriptor;
+
+        fn flag_name() -> &'static str {
+            "kv"
+        }
+
+        fn display_name(&self) -> Cow<'_, str> {
+            Cow::from(self.0.as_str())
+        }
+
+        fn from_allow(allow: &Self::AllowDesc) -> Self {
+            allow.clone()
+        }
+
+        fn as_allow(&self) -> Option<Self::AllowDesc> {
+            Some(self.clone())
+        }
+
+        fn as_deny(&self) -> Self::DenyDesc {
+            self.clone()
+        }
+
+        fn check_in_permission(
+            &self,
+            perm: &mut UnaryPermission<Self>,
+            api_name: Option<&str>,
+        ) -> Result<(), AnyError> {
+            perm.check(self, api_name)
+        }
+
+        fn matches_allow(&self, other: &Self::AllowDesc) -> bool {
+            self == other
+        }
+
+        fn matches_deny(&self, other: &Self::DenyDesc) -> bool {
+            self == other
+        }
+
+        fn revokes(&self, other: &Self::AllowDesc) -> bool {
+            self == other
+        }
+
+        fn stronger_than_deny(&self, other: &Self::DenyDesc) -> bool {
+            self == other
+        }
+
+        fn overlaps_deny(&self, _other: &Self::DenyDesc) -> bool {
+            false
+        }
+    }
+
+    impl CustomPermissionDescriptor for KvDescriptor {
+        fn parse(text: &str) -> Result<Self, AnyError> {
+            if text.is_empty() {
+                return Err(AnyError::msg("Empty kv store name"));
+            }
+            Ok(KvDescriptor(text.to_string()))
+        }
+    }
+
+    #[test]
+    fn test_custom_permission_kinds() {
+        let _locked = TESTMUTEX.lock();
+        set_prompter(Box::new(TestPrompter));
+        let mut kinds = CustomKinds::default();
+        kinds.register::<KvDescriptor>().unwrap();
+        assert!(kinds.register::<KvDescriptor>().is_err());
+        assert_eq!(kinds.names(), vec!["kv"]);
+
+        let parser = TestPermissionDescriptorParser;
+        let options = |kind: &str| PermissionsOptions {
+            custom: BTreeMap::from([(
+                kind.to_string(),
+                CustomPermissionOptions {
+                    allow: Some(svec!["users"]),
+                    deny: Some(svec!["secrets"]),
+                },
+            )]),
+            prompt: true,
+            custom_kinds: kinds.clone(),
+            ..Default::default()
+        };
+        assert!(Permissions::from_options(&parser, &options("llm")).is_err());
+        let perms = Permissions::from_options(&parser, &options("kv")).unwrap();
+        assert!(perms.custom.get::<KvDescriptor>().is_some());
+        let perms = PermissionsContainer::new(Arc::new(parser.clone()), perms);
+        // the kinds belong to the permissions they're registered on
+        let other = PermissionsContainer::new(
+            Arc::new(TestPermissionDescriptorParser),
+            Permissions::none_without_prompt(),
+        );
+        assert!(other.permission_kinds().is_empty());
+        assert!(other.query_custom("kv", None).is_err());
+
+        assert!(perms
+            .check_custom("kv", Some("users"), "Bls.kv.get")
+            .is_ok());
+        assert!(perms
+            .check_custom("kv", Some("secrets"), "Bls.kv.get")
+            .is_err());
+        assert!(perms.check_custom("kv", Some(""), "Bls.kv.get").is_err());
+        assert!(perms.check_custom("llm", None, "Bls.llm.infer").is_err());
+        assert_eq!(
+            perms.query_custom("kv", Some("users")).unwrap(),
+            PermissionState::Granted
+        );
+        assert_eq!(
+            perms.query_custom("kv", Some("secrets")).unwrap(),
+            PermissionState::Denied
+        );
+        assert_eq!(
+            perms.query_custom("kv", Some("orders")).unwrap(),
+            PermissionState::Prompt
+        );
+        {
+            let prompt_value = PERMISSION_PROMPT_STUB_VALUE_SETTER.lock();
+            prompt_value.set(true);
+            assert!(perms
+                .check_custom("kv", Some("orders"), "Bls.kv.get")
+                .is_ok());
+            prompt_value.set(false);
+            assert!(perms
+                .check_custom("kv", Some("carts"), "Bls.kv.get")
+                .is_err());
+        }
+        assert_eq!(
+            perms.query_custom("kv", Some("orders")).unwrap(),
+            PermissionState::Granted
+        );
+        assert_eq!(
+            perms.query_custom("kv", Some("carts")).unwrap(),
+            PermissionState::Denied
+        );
+
+        let snapshot = serde_json::to_value(perms.snapshot()).unwrap();
+        let restored = PermissionsContainer::restore(
+            Arc::new(parser),
+            serde_json::from_value(snapshot).unwrap(),
+        )
+        .unwrap();
+        assert!(restored.query_custom("kv", Some("users")).is_err());
+        restored.register_permission_kind::<KvDescriptor>().unwrap();
+        assert!(restored.register_permission_kind::<KvDescriptor>().is_err());
+        assert_eq!(*restored.0.lock(), *perms.0.lock());
+        // kinds registered afterwards have no grants, unless all are
+        let late = PermissionsContainer::new(
+            Arc::new(TestPermissionDescriptorParser),
+            Permissions::none_without_prompt(),
+        );
+        late.register_permission_kind::<KvDescriptor>().unwrap();
+        assert_eq!(late.permission_kinds(), vec!["kv"]);
+        assert!(late
+            .check_custom("kv", Some("users"), "Bls.kv.get")
+            .is_err());
+        let late = PermissionsContainer::allow_all(Arc::new(TestPermissionDescriptorParser));
+        late.register_permission_kind::<KvDescriptor>().unwrap();
+        assert!(late.check_custom("kv", Some("users"), "Bls.kv.get").is_ok());
+
+        let child = perms
+            .create_child_permissions(ChildPermissionsArg::inherit())
+            .unwrap();
+        assert_eq!(
+            child.query_custom("kv", Some("orders")).unwrap(),
+            PermissionState::Granted
+        );
+        assert_eq!(
+            perms.revoke_custom("kv", Some("orders")).unwrap(),
+            PermissionState::Prompt
+        );
+        let child = perms
+            .create_child_permissions(ChildPermissionsArg::none())
+            .unwrap();
+        assert_eq!(
+            child.query_custom("kv", Some("users")).unwrap(),
+            PermissionState::Prompt
+        );
+        assert_eq!(
+            child.query_custom("kv", Some("secrets")).unwrap(),
+            PermissionState::Denied
+        );
+        let child = perms
+            .create_child_permissions(serde_json::from_value(json!({ "kv": ["users"] })).unwrap())
+            .unwrap();
+        assert_eq!(
+            child.query_custom("kv", Some("users")).unwrap(),
+            PermissionState::Granted
+        );
+        assert!(perms
+            .create_child_permissions(serde_json::from_value(json!({ "kv": ["orders"] })).unwrap())
+            .is_err());
+        assert!(perms
+            .create_child_permissions(serde_json::from_value(json!({ "llm": true })).unwrap())
+            .is_err());
+
+        // custom lists are linted and validated like the built-in ones
+        let parser = TestPermissionDescriptorParser;
+        let mut options = options("kv");
+        options.custom.get_mut("kv").unwrap().deny = Some(svec!["secrets", ""]);
+        let lints = options
+            .lint(&parser)
+            .into_iter()
+            .map(|lint| (lint.kind, lint.flag_name, lint.entry))
+            .collect::<Vec<_>>();
+        assert_eq!(lints, vec![(LintKind::Invalid, "kv", String::new())]);
+        let policy = "[custom.kv]\nallow = [\"users\"]\ndeny = [\"\"]\n";
+        assert!(
+            PermissionsOptions::from_policy_str(&parser, policy, PolicyFormat::Toml, "policy")
+                .is_err()
+        );
+        let err = PermissionsOptions {
+            custom_kinds: kinds.clone(),
+            ..Default::default()
+        }
+        .with_policy_str(&parser, policy, PolicyFormat::Toml, "policy")
+        .unwrap_err();
+        let err = err.downcast_ref::<PolicyError>().unwrap();
+        assert_eq!(err.field.as_deref(), Some("deny_kv"));
+        assert_eq!(err.descriptor.as_deref(), Some(""));
+        let policy = "[custom.llm]\nallow = [\"gpt\"]\n";
+        assert!(
+            PermissionsOptions::from_policy_str(&parser, policy, PolicyFormat::Toml, "policy")
+                .is_err()
+        );
+    }
+
+    static TEST_CLOCK_MILLIS: AtomicU64 = AtomicU64::new(0);
+
+    struct TestClock;
+
+    impl Clock for TestClock {
+        fn now(&self) -> Duration {
+            Duration::from_millis(TEST_CLOCK_MILLIS.load(Ordering::SeqCst))
+        }
+    }
+
+    fn advance_test_clock(secs: u64) {
+        TEST_CLOCK_MILLIS.fetch_add(secs * 1000, Ordering::SeqCst);
+    }
+
+    /// A clock that can't be read, like `SystemClock` on wasm.
+    struct PanicClock;
+
+    impl Clock for PanicClock {
+        fn now(&self) -> Duration {
+            panic!("the clock was read")
+        }
+    }
+
+    #[test]
+    fn test_checks_without_ttls_or_quotas_dont_read_the_clock() {
+        let _locked = TESTMUTEX.lock();
+        let parser = TestPermissionDescriptorParser;
+        let perms = Permissions::from_options(
+            &parser,
+            &PermissionsOptions {
+                allow_env: Some(vec![]),
+                allow_net: Some(svec!["deno.land"]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut perms =
+            PermissionsContainer::new(Arc::new(parser), perms).with_clock(Arc::new(PanicClock));
+        assert!(perms.check_env("HOME").is_ok());
+        assert!(perms.check_net(&("deno.land", None), "api").is_ok());
+        assert!(perms.check_net(&("example.com", None), "api").is_err());
+    }
+
+    #[test]
+    fn test_grant_ttl() {
+        let _locked = TESTMUTEX.lock();
+        set_prompter(Box::new(TestPrompter));
+        TEST_CLOCK_MILLIS.store(1_000_000, Ordering::SeqCst);
+        let parser = TestPermissionDescriptorParser;
+        let perms = Permissions::from_options(
+            &parser,
+            &PermissionsOptions {
+                allow_net: Some(svec!["deno.land"]),
+                prompt: true,
+                prompt_grant_ttl: Some(60),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut perms = PermissionsContainer::new(Arc::new(parser.clone()), perms)
+            .with_clock(Arc::new(TestClock));
+        let net = |host: &str| NetDescriptor::parse(host).unwrap();
+
+        perms
+            .grant_for("net", Some("jsr.io"), Duration::from_secs(600))
+            .unwrap();
+        assert!(perms.check_net(&("jsr.io", None), "api").is_ok());
+        advance_test_clock(599);
+        assert!(perms.check_net(&("jsr.io", None), "api").is_ok());
+        advance_test_clock(1);
+        assert_eq!(
+            perms.query_net(Some("jsr.io")).unwrap(),
+            PermissionState::Prompt
+        );
+        // expired grants are dropped by the next check
+        assert!(perms.check_net(&("deno.land", None), "api").is_ok());
+        assert!(!perms.0.lock().net.granted_list.contains(&net("jsr.io")));
+
+        {
+            let prompt_value = PERMISSION_PROMPT_STUB_VALUE_SETTER.lock();
+            prompt_value.set(true);
+            assert!(perms.check_net(&("example.com", None), "api").is_ok());
+        }
+        assert_eq!(
+            perms.query_net(Some("example.com")).unwrap(),
+            PermissionState::Granted
+        );
+        advance_test_clock(60);
+        assert_eq!(
+            perms.query_net(Some("example.com")).unwrap(),
+            PermissionState::Prompt
+        );
+
+        // grants without a TTL aren't shortened
+        perms
+            .grant_for("net", Some("deno.land"), Duration::from_secs(1))
+            .unwrap();
+        advance_test_clock(2);
+        assert_eq!(
+            perms.query_net(Some("deno.land")).unwrap(),
+            PermissionState::Granted
+        );
+
+        // a whole kind can be granted for a while too
+        perms
+            .grant_for("env", None, Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(perms.query_env(Some("HOME")), PermissionState::Granted);
+        advance_test_clock(10);
+        assert_eq!(perms.query_env(Some("HOME")), PermissionState::Prompt);
+        assert!(perms
+            .grant_for("nope", None, Duration::from_secs(10))
+            .is_err());
+
+        perms
+            .grant_for("net", Some("example.org"), Duration::from_secs(100))
+            .unwrap();
+        let snapshot = serde_json::to_value(perms.snapshot()).unwrap();
+        let restored = PermissionsContainer::restore(
+            Arc::new(parser),
+            serde_json::from_value(snapshot).unwrap(),
+        )
+        .unwrap()
+        .with_clock(Arc::new(TestClock));
+        assert_eq!(*restored.0.lock(), *perms.0.lock());
+
+        let child = perms
+            .create_child_permissions(ChildPermissionsArg {
+                net: ChildUnaryPermissionArg::GrantedList(svec!["example.org", "deno.land"]),
+                ..ChildPermissionsArg::none()
+            })
+            .unwrap();
+        assert_eq!(
+            child.query_net(Some("example.org")).unwrap(),
+            PermissionState::Granted
+        );
+        advance_test_clock(100);
+        assert_eq!(
+            child.query_net(Some("example.org")).unwrap(),
+            PermissionState::Prompt
+        );
+        assert_eq!(
+            child.query_net(Some("deno.land")).unwrap(),
+            PermissionState::Granted
+        );
+        assert_eq!(
+            restored.query_net(Some("example.org")).unwrap(),
+            PermissionState::Prompt
+        );
+    }
+
+    struct AllowOncePrompter;
+
+    impl PermissionPrompter for AllowOncePrompter {
+        fn prompt(
+            &mut self,
+            _message: &str,
+            _name: &str,
+            _api_name: Option<&str>,
+            _is_unary: bool,
+        ) -> PromptResponse {
+            PromptResponse::AllowOnce
+        }
+    }
+
+    #[test]
+    fn test_count_limited_grants() {
+        let _locked = TESTMUTEX.lock();
+        let parser = TestPermissionDescriptorParser;
+        let perms = Permissions::from_options(
+            &parser,
+            &PermissionsOptions {
+                allow_net: Some(svec!["deno.land"]),
+                prompt: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut perms = PermissionsContainer::new(Arc::new(parser.clone()), perms);
+        let net = |host: &str| NetDescriptor::parse(host).unwrap();
+
+        set_prompter(Box::new(AllowOncePrompter));
+        assert!(perms.check_net(&("example.com", None), "api").is_ok());
+        assert_eq!(
+            perms.query_net(Some("example.com")).unwrap(),
+            PermissionState::Prompt
+        );
+        assert_eq!(
+            perms.request_net(Some("example.com")).unwrap(),
+            PermissionState::Granted
+        );
+        assert_eq!(
+            perms.query_uses("net", Some("example.com")).unwrap(),
+            Some(1)
+        );
+        // a command that can't be granted on its own is prompted for again
+        let name = RunQueryDescriptor::Name("unresolved-cmd".to_string());
+        assert_eq!(
+            perms.0.lock().run.request(Some(&name)),
+            PermissionState::Prompt
+        );
+        assert_eq!(
+            perms.0.lock().run.query(Some(&name)),
+            PermissionState::Prompt
+        );
+        set_prompter(Box::new(TestPrompter));
+        let prompt_value = PERMISSION_PROMPT_STUB_VALUE_SETTER.lock();
+        prompt_value.set(false);
+        assert!(perms.check_net(&("example.com", None), "api").is_ok());
+        assert_eq!(
+            perms.query_net(Some("example.com")).unwrap(),
+            PermissionState::Prompt
+        );
+        assert_eq!(perms.query_uses("net", Some("example.com")).unwrap(), None);
+
+        perms.0.lock().net.grant_uses(Some(net("jsr.io")), 1);
+        perms.0.lock().net.grant_uses(Some(net("jsr.io")), 1);
+        assert_eq!(perms.query_uses("net", Some("jsr.io")).unwrap(), Some(2));
+        perms
+            .0
+            .lock()
+            .env
+            .grant_uses(Some(EnvDescriptor::new("PATH")), u32::MAX);
+        perms
+            .0
+            .lock()
+            .env
+            .grant_uses(Some(EnvDescriptor::new("PATH")), 1);
+        assert_eq!(
+            perms.query_uses("env", Some("PATH")).unwrap(),
+            Some(u32::MAX)
+        );
+        let snapshot = serde_json::to_value(perms.snapshot()).unwrap();
+        let restored = PermissionsContainer::restore(
+            Arc::new(parser),
+            serde_json::from_value(snapshot).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(*restored.0.lock(), *perms.0.lock());
+        let child = perms
+            .create_child_permissions(ChildPermissionsArg {
+                net: ChildUnaryPermissionArg::GrantedList(svec!["jsr.io"]),
+                ..ChildPermissionsArg::none()
+            })
+            .unwrap();
+        assert_eq!(child.query_uses("net", Some("jsr.io")).unwrap(), Some(2));
+        assert!(perms.check_net(&("jsr.io", None), "api").is_ok());
+        assert_eq!(perms.query_uses("net", Some("jsr.io")).unwrap(), Some(1));
+        assert!(perms.check_net(&("jsr.io", None), "api").is_ok());
+        assert!(perms.check_net(&("jsr.io", None), "api").is_err());
+        assert_eq!(
+            perms.query_net(Some("jsr.io")).unwrap(),
+            PermissionState::Denied
+        );
+
+        // grants without a count aren't limited
+        perms.0.lock().net.grant_uses(Some(net("deno.land")), 1);
+        assert_eq!(perms.query_uses("net", Some("deno.land")).unwrap(), None);
+        assert!(perms.check_net(&("deno.land", None), "api").is_ok());
+        assert!(perms.check_net(&("deno.land", None), "api").is_ok());
+
+        perms.0.lock().env.grant_uses(None, 1);
+        assert_eq!(perms.query_uses("env", None).unwrap(), Some(1));
+        assert!(perms.check_env("HOME").is_ok());
+        assert_eq!(perms.query_env(Some("HOME")), PermissionState::Prompt);
+        assert!(perms.query_uses("nope", None).is_err());
+
+        // children spend the parent's count-limited grants
+        perms.0.lock().net.grant_uses(Some(net("esm.sh")), 1);
+        let children = [
+            ChildPermissionsArg::inherit(),
+            ChildPermissionsArg {
+                net: ChildUnaryPermissionArg::GrantedList(svec!["esm.sh"]),
+                ..ChildPermissionsArg::none()
+            },
+        ]
+        .map(|arg| perms.create_child_permissions(arg).unwrap());
+        let [mut first, mut second] = children;
+        assert!(first.check_net(&("esm.sh", None), "api").is_ok());
+        assert!(second.check_net(&("esm.sh", None), "api").is_err());
+        assert!(perms.check_net(&("esm.sh", None), "api").is_err());
+        assert_eq!(
+            first.query_net(Some("esm.sh")).unwrap(),
+            PermissionState::Prompt
+        );
+
+        // a snapshot with no uses left doesn't grant anything
+        let mut snapshot = serde_json::to_value(PermissionsSnapshot {
+            env: Default::default(),
+            ..perms.snapshot()
+        })
+        .unwrap();
+        snapshot["env"]["granted_global"] = true.into();
+        snapshot["env"]["granted_global_uses"] = 0.into();
+        snapshot["env"]["granted_list"] = serde_json::json!(["HOME"]);
+        snapshot["env"]["granted_uses"] = serde_json::json!([["HOME", 0]]);
+        let restored = PermissionsContainer::restore(
+            Arc::new(TestPermissionDescriptorParser),
+            serde_json::from_value(snapshot).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(restored.query_env(Some("HOME")), PermissionState::Prompt);
+        assert_eq!(restored.query_uses("env", None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_quotas() {
+        let _locked = TESTMUTEX.lock();
+        TEST_CLOCK_MILLIS.store(1_000_000, Ordering::SeqCst);
+        let parser = TestPermissionDescriptorParser;
+        let quota = |checks_per_second, total_checks, total_bytes| Quota {
+            checks_per_second,
+            total_checks,
+            total_bytes,
+        };
+        let perms = Permissions::from_options(
+            &parser,
+            &PermissionsOptions {
+                allow_net: Some(svec!["deno.land", "jsr.io", "example.com"]),
+                quotas: QuotaOptions {
+                    net: BTreeMap::from([
+                        ("deno.land".to_string(), quota(Some(2), None, None)),
+                        ("jsr.io".to_string(), quota(None, Some(3), Some(100))),
+                    ]),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut perms = PermissionsContainer::new(Arc::new(parser.clone()), perms)
+            .with_clock(Arc::new(TestClock));
+        let is_quota_exceeded =
+            |err: AnyError| get_custom_error_class(&err) == Some("QuotaExceeded");
+
+        assert!(perms.check_net(&("deno.land", None), "api").is_ok());
+        assert!(perms.check_net(&("deno.land", None), "api").is_ok());
+        let err = perms.check_net(&("deno.land", None), "api").unwrap_err();
+        assert!(
+            err.to_string().contains("more than 2 checks per second"),
+            "{err}"
+        );
+        assert!(is_quota_exceeded(err));
+        advance_test_clock(1);
+        assert!(perms.check_net(&("deno.land", None), "api").is_ok());
+
+        for _ in 0..3 {
+            assert!(perms.check_net(&("jsr.io", None), "api").is_ok());
+        }
+        assert!(is_quota_exceeded(
+            perms.check_net(&("jsr.io", None), "api").unwrap_err()
+        ));
+        let state = perms.query_quota("net", "jsr.io").unwrap();
+        assert_eq!(state.len(), 1);
+        assert_eq!(state[0].remaining_checks(), Some(0));
+        assert!(perms.report_bytes("net", "jsr.io", 60).is_ok());
+        assert!(is_quota_exceeded(
+            perms.report_bytes("net", "jsr.io", 60).unwrap_err()
+        ));
+        assert_eq!(
+            perms.query_quota("net", "jsr.io").unwrap()[0].remaining_bytes(),
+            Some(40)
+        );
+
+        // entries without a quota aren't limited
+        assert!(perms.query_quota("net", "example.com").unwrap().is_empty());
+        for _ in 0..5 {
+            assert!(perms.check_net(&("example.com", None), "api").is_ok());
+        }
+        assert!(perms.report_bytes("net", "example.com", u64::MAX).is_ok());
+        // kinds without quotas have none, unknown kinds are an error
+        assert!(perms.query_quota("env", "HOME").unwrap().is_empty());
+        assert!(perms.report_bytes("env", "HOME", 1).is_ok());
+        let err = perms.report_bytes("nope", "x", 1).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            perms.query_uses("nope", None).unwrap_err().to_string()
+        );
+        assert!(perms.query_quota("nope", "x").is_err());
+
+        let snapshot = serde_json::to_value(perms.snapshot()).unwrap();
+        let restored = PermissionsContainer::restore(
+            Arc::new(parser.clone()),
+            serde_json::from_value(snapshot).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(*restored.0.lock(), *perms.0.lock());
+
+        // children get the parent's quotas
+        let child = perms
+            .create_child_permissions(ChildPermissionsArg {
+                net: ChildUnaryPermissionArg::GrantedList(svec!["deno.land"]),
+                ..ChildPermissionsArg::none()
+            })
+            .unwrap();
+        assert_eq!(
+            child.query_quota("net", "deno.land").unwrap()[0].quota,
+            quota(Some(2), None, None)
+        );
+
+        // and spend them along with the parent
+        let mut perms = PermissionsContainer::new(
+            Arc::new(parser.clone()),
+            Permissions::from_options(
+                &parser,
+                &PermissionsOptions {
+                    allow_net: Some(svec!["deno.land"]),
+                    quotas: QuotaOptions {
+                        net: BTreeMap::from([(
+                            "deno.land".to_string(),
+                            quota(None, Some(1), None),
+                        )]),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            )
+            .unwrap(),
+        );
+        let children = [
+            ChildPermissionsArg::inherit(),
+            ChildPermissionsArg {
+                net: ChildUnaryPermissionArg::GrantedList(svec!["deno.land"]),
+                ..ChildPermissionsArg::none()
+            },
+        ]
+        .map(|arg| perms.create_child_permissions(arg).unwrap());
+        let [mut first, mut second] = children;
+        assert!(first.check_net(&("deno.land", None), "api").is_ok());
+        assert!(is_quota_exceeded(
+            second.check_net(&("deno.land", None), "api").unwrap_err()
+        ));
+        assert!(is_quota_exceeded(
+            perms.check_net(&("deno.land", None), "api").unwrap_err()
+        ));
+
+        let err = Permissions::from_options(
+            &parser,
+            &PermissionsOptions {
+                allow_net: Some(svec!["deno.land"]),
+                quotas: QuotaOptions {
+                    net: BTreeMap::from([("jsr.io".to_string(), Quota::default())]),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("allow_net"), "{err}");
+
+        // a broader allow entry of another layer doesn't lift a quota, nor
+        // does allowing the whole kind
+        let layers = [
+            PermissionsLayer::new(
+                "system",
+                PermissionsOptions {
+                    allow_net: Some(svec!["deno.land"]),
+                    allow_write: Some(svec!["/data/logs"]),
+                    quotas: QuotaOptions {
+                        net: BTreeMap::from([(
+                            "deno.land".to_string(),
+                            quota(None, Some(1), None),
+                        )]),
+                        write: BTreeMap::from([(
+                            "/data/logs".to_string(),
+                            quota(None, Some(2), None),
+                        )]),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            ),
+            PermissionsLayer::new(
+                "project",
+                PermissionsOptions {
+                    allow_net: Some(vec![]),
+                    allow_write: Some(svec!["/data"]),
+                    quotas: QuotaOptions {
+                        write: BTreeMap::from([(
+                            "/data/logs".to_string(),
+                            quota(None, Some(5), Some(10)),
+                        )]),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            ),
+        ];
+        let merged = PermissionsOptions::merge_layers(&layers);
+        assert_eq!(
+            merged.options.quotas.write["/data/logs"],
+            quota(None, Some(2), Some(10))
+        );
+        let mut perms = PermissionsContainer::new(
+            Arc::new(parser.clone()),
+            Permissions::from_options(&parser, &merged.options).unwrap(),
+        );
+        assert!(perms.check_net(&("deno.land", Some(443)), "api").is_ok());
+        assert!(is_quota_exceeded(
+            perms.check_net(&("deno.land", None), "api").unwrap_err()
+        ));
+        assert!(perms.check_net(&("example.com", None), "api").is_ok());
+        assert!(perms.check_write("/data/logs/a.log", "api").is_ok());
+        assert!(perms.check_write("/data/logs/b.log", "api").is_ok());
+        assert!(is_quota_exceeded(
+            perms.check_write("/data/logs/a.log", "api").unwrap_err()
+        ));
+        assert!(perms.check_write("/data/db", "api").is_ok());
+        // revoking the grant doesn't reset the quota
+        assert_eq!(
+            perms.revoke_net(Some("deno.land")).unwrap(),
+            PermissionState::Granted
+        );
+        assert_eq!(
+            perms.query_quota("net", "deno.land").unwrap()[0].remaining_checks(),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn test_check_net_resolved() {
+        let _locked = TESTMUTEX.lock();
+        let resolver = Arc::new(
+            FakeResolver::new()
+                .with_host("deno.land", ["104.21.0.1".parse().unwrap()])
+                .with_host("rebind.example.com", ["127.0.0.1".parse().unwrap()])
+                .with_host(
+                    "metadata.example.com",
+                    [
+                        "104.21.0.2".parse().unwrap(),
+                        "::ffff:169.254.169.254".parse().unwrap(),
+                    ],
+                )
+                .with_host("ssh.example.com", ["10.0.0.1".parse().unwrap()])
+                .with_host("intranet.example.com", ["10.0.0.2".parse().unwrap()]),
+        );
+        let parser = TestPermissionDescriptorParser;
+        let perms = Permissions::from_options(
+            &parser,
+            &PermissionsOptions {
+                allow_net: Some(svec![
+                    "deno.land",
+                    "rebind.example.com",
+                    "metadata.example.com",
+                    "ssh.example.com",
+                    "intranet.example.com"
+                ]),
+                deny_net: Some(svec![
+                    "127.0.0.1",
+                    "169.254.0.0/16",
+                    "10.0.0.1:22",
+                    "http://10.0.0.2"
+                ]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut perms = PermissionsContainer::new(Arc::new(parser.clone()), perms)
+            .with_net_resolver(resolver.clone());
+
+        let addrs = perms.resolve_net(&("deno.land", Some(443)), "api").unwrap();
+        assert_eq!(addrs, vec!["104.21.0.1:443".parse::<SocketAddr>().unwrap()]);
+        let err = perms
+            .resolve_net(&("rebind.example.com", Some(443)), "api")
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("denied address \"127.0.0.1:443\""),
+            "{err}"
+        );
+        assert!(perms
+            .resolve_net(&("metadata.example.com", Some(80)), "api")
+            .is_err());
+        assert!(perms
+            .resolve_net(&("ssh.example.com", Some(443)), "api")
+            .is_ok());
+        assert!(perms
+            .resolve_net(&("ssh.example.com", Some(22)), "api")
+            .is_err());
+        // a deny scoped to a scheme covers connections without one
+        assert!(perms
+            .resolve_net(&("intranet.example.com", Some(443)), "api")
+            .is_err());
+        let url = Url::parse("https://intranet.example.com/").unwrap();
+        let intranet = ["10.0.0.2:443".parse::<SocketAddr>().unwrap()];
+        assert!(perms.check_net_url_resolved(&url, &intranet).is_ok());
+
+        // the host passes the addresses it resolved itself
+        let internal = ["127.0.0.1:8080".parse::<SocketAddr>().unwrap()];
+        assert!(perms
+            .check_net_resolved(&("deno.land", None), &internal)
+            .is_err());
+        let url = Url::parse("https://deno.land/x").unwrap();
+        assert!(perms.check_net_url_resolved(&url, &addrs).is_ok());
+        assert!(perms.check_net_url_resolved(&url, &internal).is_err());
+        // hosts that weren't approved aren't approved by their addresses
+        let public = ["104.21.0.1:443".parse::<SocketAddr>().unwrap()];
+        assert!(perms
+            .check_net_resolved(&("jsr.io", None), &public)
+            .is_err());
+        assert!(perms
+            .resolve_net(&("unknown.example.com", Some(443)), "api")
+            .is_err());
+        // a deny entry with a port can't be checked without one
+        assert!(perms
+            .resolve_net(&("ssh.example.com", None), "api")
+            .is_err());
+
+        // child containers resolve with the same resolver
+        let mut child = perms
+            .create_child_permissions(ChildPermissionsArg::inherit())
+            .unwrap();
+        assert!(child
+            .resolve_net(&("rebind.example.com", Some(443)), "api")
+            .is_err());
+
+        // the addresses are covered by the host check, which is done once
+        let perms = Permissions::from_options(
+            &parser,
+            &PermissionsOptions {
+                allow_net: Some(svec!["deno.land"]),
+                deny_net: Some(svec!["127.0.0.1"]),
+                prompt: true,
+                quotas: QuotaOptions {
+                    net: BTreeMap::from([(
+                        "deno.land".to_string(),
+                        Quota {
+                            checks_per_second: None,
+                            total_checks: Some(1),
+                            total_bytes: None,
+                        },
+                    )]),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut perms = PermissionsContainer::new(Arc::new(parser), perms)
+            .with_net_resolver(resolver)
+            .with_clock(Arc::new(TestClock));
+        assert!(perms.resolve_net(&("deno.land", Some(443)), "api").is_ok());
+        assert_eq!(
+            perms.query_quota("net", "deno.land").unwrap()[0].remaining_checks(),
+            Some(0)
+        );
+        set_prompter(Box::new(AllowOncePrompter));
+        assert!(perms
+            .resolve_net(&("ssh.example.com", Some(443)), "api")
+            .is_ok());
+        assert!(perms
+            .resolve_net(&("rebind.example.com", Some(443)), "api")
+            .is_err());
+        set_prompter(Box::new(TestPrompter));
+        let prompt_value = PERMISSION_PROMPT_STUB_VALUE_SETTER.lock();
+        prompt_value.set(false);
+        let net = |host: &str| NetDescriptor::parse(host).unwrap();
+        perms
+            .0
+            .lock()
+            .net
+            .grant_uses(Some(net("intranet.example.com")), 1);
+        assert!(perms
+            .resolve_net(&("intranet.example.com", Some(443)), "api")
+            .is_ok());
+        assert_eq!(
+            perms.query_net(Some("intranet.example.com")).unwrap(),
+            PermissionState::Prompt
+        );
+        perms
+            .0
+            .lock()
+            .net
+            .grant_uses(Some(net("metadata.example.com")), 2);
+        assert!(perms
+            .resolve_net(&("metadata.example.com", Some(443)), "api")
+            .is_ok());
+        assert_eq!(
+            perms
+                .query_uses("net", Some("metadata.example.com"))
+                .unwrap(),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_unix_socket() {
+        let _locked = TESTMUTEX.lock();
+        let parser = TestPermissionDescriptorParser;
+        let perms = Permissions::from_options(
+            &parser,
+            &PermissionsOptions {
+                allow_unix_socket: Some(svec!["/run/app", "@db"]),
+                deny_unix_socket: Some(svec!["/run/app/admin.sock"]),
+                prompt: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut perms = PermissionsContainer::new(Arc::new(parser.clone()), perms);
+        set_prompter(Box::new(TestPrompter));
+
+        let prompt_value = PERMISSION_PROMPT_STUB_VALUE_SETTER.lock();
+        prompt_value.set(false);
+        assert!(perms.check_unix_socket("/run/app/api.sock", "api").is_ok());
+        assert!(perms.check_unix_socket("@db", "api").is_ok());
+        assert!(perms
+            .check_unix_socket("/run/app/admin.sock", "api")
+            .is_err());
+        // abstract names don't match paths and only match themselves
+        assert!(perms
+            .check_unix_socket("@/run/app/api.sock", "api")
+            .is_err());
+        assert!(perms.check_unix_socket("@db2", "api").is_err());
+        assert!(perms.check_unix_socket("/db", "api").is_err());
+        assert!(perms.check_unix_socket("@", "api").is_err());
+        assert!(perms.check_unix_socket("", "api").is_err());
+
+        // the sockets denied at the prompts above
+        assert_eq!(
+            perms.query_unix_socket(None).unwrap(),
+            PermissionState::Denied
+        );
+        assert_eq!(
+            perms.query_unix_socket(Some("/run/app")).unwrap(),
+            PermissionState::GrantedPartial
+        );
+        assert_eq!(
+            perms.query_unix_socket(Some("@cache")).unwrap(),
+            PermissionState::Prompt
+        );
+        prompt_value.set(true);
+        assert_eq!(
+            perms.request_unix_socket(Some("@metrics")).unwrap(),
+            PermissionState::Granted
+        );
+        assert!(perms.check_unix_socket("@metrics", "api").is_ok());
+        assert_eq!(
+            perms.revoke_unix_socket(Some("@db")).unwrap(),
+            PermissionState::Prompt
+        );
+        prompt_value.set(false);
+        assert!(perms.check_unix_socket("@db", "api").is_err());
+        drop(prompt_value);
+
+        let snapshot = serde_json::to_value(perms.snapshot()).unwrap();
+        let restored = PermissionsContainer::restore(
+            Arc::new(parser.clone()),
+            serde_json::from_value(snapshot).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(*restored.0.lock(), *perms.0.lock());
+
+        let mut child = perms
+            .create_child_permissions(ChildPermissionsArg {
+                unix_socket: ChildUnaryPermissionArg::GrantedList(svec!["/run/app/api.sock"]),
+                ..ChildPermissionsArg::none()
+            })
+            .unwrap();
+        assert!(child.check_unix_socket("/run/app/api.sock", "api").is_ok());
+        assert!(child.check_unix_socket("@metrics", "api").is_err());
+        assert!(perms
+            .create_child_permissions(ChildPermissionsArg {
+                unix_socket: ChildUnaryPermissionArg::GrantedList(svec!["/var/run/docker.sock"]),
+                ..ChildPermissionsArg::none()
+            })
+            .is_err());
+
+        let lints = PermissionsOptions {
+            allow_unix_socket: Some(svec!["/run", "/run/app"]),
+            ..Default::default()
+        }
+        .lint(&parser);
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].kind, LintKind::Redundant);
+    }
+
+    #[test]
+    fn test_net_fully_qualified_domain_name() {
+        let _locked = TESTMUTEX.lock();
+        let parser = TestPermissionDescriptorParser;
+        let perms = Permissions::from_options(
+            &parser,
+            &PermissionsOptions {
+                allow_net: Some(svec!["allowed.domain", "1.1.1.1"]),
+                deny_net: Some(svec!["denied.domain", "2.2.2.2"]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut perms = PermissionsContainer::new(Arc::new(parser), perms);
+        set_prompter(Box::new(TestPrompter));
+        let cases = [
+            ("allowed.domain.", true),
+            ("1.1.1.1", true),
+            ("denied.domain.", false),
+            ("2.2.2.2", false),
+        ];
+
+        for (host, is_ok) in cases {
+            assert_eq!(perms.check_net(&(host, None), "api").is_ok(), is_ok);
+        }
+    }
+
+    #[test]
+    fn test_deserialize_child_permissions_arg() {
+        let _locked = TESTMUTEX.lock();
+        set_prompter(Box::new(TestPrompter));
+        assert_eq!(
+            ChildPermissionsArg::inherit(),
+            ChildPermissionsArg {
+                env: ChildUnaryPermissionArg::Inherit,
+                net: ChildUnaryPermissionArg::Inherit,
+                ffi: ChildUnaryPermissionArg::Inherit,
+                import: ChildUnaryPermissionArg::Inherit,
+                read: ChildUnaryPermissionArg::Inherit,
+                run: ChildUnaryPermissionArg::Inherit,
+                sys: ChildUnaryPermissionArg::Inherit,
+                write: ChildUnaryPermissionArg::Inherit,
+                unix_socket: ChildUnaryPermissionArg::Inherit,
+                custom: BTreeMap::new(),
+                custom_default: ChildUnaryPermissionArg::Inherit,
+            }
+        );
+        assert_eq!(
+            ChildPermissionsArg::none(),
+            ChildPermissionsArg {
+                env: ChildUnaryPermissionArg::NotGranted,
+                net: ChildUnaryPermissionArg::NotGranted,
+                ffi: ChildUnaryPermissionArg::NotGranted,
+                import: ChildUnaryPermissionArg::NotGranted,
+                read: ChildUnaryPermissionArg::NotGranted,
+                run: ChildUnaryPermissionArg::NotGranted,
+                sys: ChildUnaryPermissionArg::NotGranted,
+                write: ChildUnaryPermissionArg::NotGranted,
+                unix_socket: ChildUnaryPermissionArg::NotGranted,
+                custom: BTreeMap::new(),
+                custom_default: ChildUnaryPermissionArg::NotGranted,
+            }
+        );
+        assert_eq!(
+            serde_json::from_value::<ChildPermissionsArg>(json!("inherit")).unwrap(),
+            ChildPermissionsArg::inherit()
         );
         assert_eq!(
             serde_json::from_value::<ChildPermissionsArg>(json!("none")).unwrap(),
@@ -1415,6 +3973,7 @@ mod tests {
               "run": true,
               "sys": true,
               "write": true,
+              "unix_socket": true,
             }))
             .unwrap(),
             ChildPermissionsArg {
@@ -1426,6 +3985,9 @@ mod tests {
                 run: ChildUnaryPermissionArg::Granted,
                 sys: ChildUnaryPermissionArg::Granted,
                 write: ChildUnaryPermissionArg::Granted,
+                unix_socket: ChildUnaryPermissionArg::Granted,
+                custom: BTreeMap::new(),
+                custom_default: ChildUnaryPermissionArg::NotGranted,
             }
         );
         assert_eq!(
@@ -1438,6 +4000,7 @@ mod tests {
               "run": false,
               "sys": false,
               "write": false,
+              "unix_socket": false,
             }))
             .unwrap(),
             ChildPermissionsArg {
@@ -1449,6 +4012,9 @@ mod tests {
                 run: ChildUnaryPermissionArg::NotGranted,
                 sys: ChildUnaryPermissionArg::NotGranted,
                 write: ChildUnaryPermissionArg::NotGranted,
+                unix_socket: ChildUnaryPermissionArg::NotGranted,
+                custom: BTreeMap::new(),
+                custom_default: ChildUnaryPermissionArg::NotGranted,
             }
         );
         assert_eq!(
@@ -1461,6 +4027,7 @@ mod tests {
               "run": ["foo", "file:///bar/baz", "./qux"],
               "sys": ["hostname", "osRelease"],
               "write": ["foo", "file:///bar/baz"],
+              "unix_socket": ["/run/foo.sock", "@bar"],
             }))
             .unwrap(),
             ChildPermissionsArg {
@@ -1472,6 +4039,9 @@ mod tests {
                 run: ChildUnaryPermissionArg::GrantedList(svec!["foo", "file:///bar/baz", "./qux"]),
                 sys: ChildUnaryPermissionArg::GrantedList(svec!["hostname", "osRelease"]),
                 write: ChildUnaryPermissionArg::GrantedList(svec!["foo", "file:///bar/baz"]),
+                unix_socket: ChildUnaryPermissionArg::GrantedList(svec!["/run/foo.sock", "@bar"]),
+                custom: BTreeMap::new(),
+                custom_default: ChildUnaryPermissionArg::NotGranted,
             }
         );
     }
@@ -1564,8 +4134,8 @@ mod tests {
         assert_eq!(
             main_perms.0.inner.lock().run.granted_list,
             HashSet::from([
-                AllowRunDescriptor(PathBuf::from("/bar")),
-                AllowRunDescriptor(PathBuf::from("/foo")),
+                AllowRunDescriptor(PathBuf::from("/bar"), None, None),
+                AllowRunDescriptor(PathBuf::from("/foo"), None, None),
             ])
         );
     }
@@ -1628,6 +4198,32 @@ mod tests {
             ("1::1.", None),
             ("deno.land.", Some(Host::Fqdn(fqdn!("deno.land")))),
             (".deno.land", None),
+            ("*.deno.land", Some(Host::Wildcard(fqdn!("deno.land")))),
+            ("*.DENO.land", Some(Host::Wildcard(fqdn!("deno.land")))),
+            ("*.", None),
+            ("*", None),
+            ("*.1.1.1.1", None),
+            ("*.*.deno.land", None),
+            ("api.*.deno.land", None),
+            ("deno*.land", None),
+            (
+                "10.1.2.3/8",
+                Some(Host::Cidr(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)), 8)),
+            ),
+            (
+                "fd00::/8",
+                Some(Host::Cidr(
+                    IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 0)),
+                    8,
+                )),
+            ),
+            (
+                "::ffff:169.254.0.0/112",
+                Some(Host::Cidr(IpAddr::V4(Ipv4Addr::new(169, 254, 0, 0)), 16)),
+            ),
+            ("10.0.0.0/33", None),
+            ("10.0.0.0/", None),
+            ("deno.land/8", None),
             (
                 "::ffff:1.1.1.1",
                 Some(Host::Ip(IpAddr::V6(Ipv6Addr::new(
@@ -1646,15 +4242,19 @@ mod tests {
         let cases = &[
             (
                 "deno.land",
-                Some(NetDescriptor(Host::Fqdn(fqdn!("deno.land")), None)),
+                Some(NetDescriptor(Host::Fqdn(fqdn!("deno.land")), None, None)),
             ),
             (
                 "DENO.land",
-                Some(NetDescriptor(Host::Fqdn(fqdn!("deno.land")), None)),
+                Some(NetDescriptor(Host::Fqdn(fqdn!("deno.land")), None, None)),
             ),
             (
                 "deno.land:8000",
-                Some(NetDescriptor(Host::Fqdn(fqdn!("deno.land")), Some(8000))),
+                Some(NetDescriptor(
+                    Host::Fqdn(fqdn!("deno.land")),
+                    Some(8000.into()),
+                    None,
+                )),
             ),
             ("deno.land:", None),
             ("deno.land:a", None),
@@ -1665,6 +4265,7 @@ mod tests {
                 Some(NetDescriptor(
                     Host::Ip(IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1))),
                     None,
+                    None,
                 )),
             ),
             ("1.1.1.1.", None),
@@ -1673,7 +4274,8 @@ mod tests {
                 "1.1.1.1:8000",
                 Some(NetDescriptor(
                     Host::Ip(IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1))),
-                    Some(8000),
+                    Some(8000.into()),
+                    None,
                 )),
             ),
             ("::", None),
@@ -1684,6 +4286,7 @@ mod tests {
                 Some(NetDescriptor(
                     Host::Ip(IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0))),
                     None,
+                    None,
                 )),
             ),
             ("[::1", None),
@@ -1695,11 +4298,112 @@ mod tests {
                 "[::1]:443",
                 Some(NetDescriptor(
                     Host::Ip(IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1))),
-                    Some(443),
+                    Some(443.into()),
+                    None,
                 )),
             ),
             ("", None),
             ("deno.land..", None),
+            (
+                "*.deno.land",
+                Some(NetDescriptor(
+                    Host::Wildcard(fqdn!("deno.land")),
+                    None,
+                    None,
+                )),
+            ),
+            (
+                "*.deno.land:443",
+                Some(NetDescriptor(
+                    Host::Wildcard(fqdn!("deno.land")),
+                    Some(443.into()),
+                    None,
+                )),
+            ),
+            ("*.deno.land:", None),
+            (
+                "169.254.0.0/16",
+                Some(NetDescriptor(
+                    Host::Cidr(IpAddr::V4(Ipv4Addr::new(169, 254, 0, 0)), 16),
+                    None,
+                    None,
+                )),
+            ),
+            (
+                "10.0.0.0/8:443",
+                Some(NetDescriptor(
+                    Host::Cidr(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)), 8),
+                    Some(443.into()),
+                    None,
+                )),
+            ),
+            (
+                "[fd00::/8]:443",
+                Some(NetDescriptor(
+                    Host::Cidr(IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 0)), 8),
+                    Some(443.into()),
+                    None,
+                )),
+            ),
+            (
+                "fd00::/8",
+                Some(NetDescriptor(
+                    Host::Cidr(IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 0)), 8),
+                    None,
+                    None,
+                )),
+            ),
+            ("[10.0.0.0/8]", None),
+            ("10.0.0.0/129", None),
+            (
+                "deno.land:8000-8100",
+                Some(NetDescriptor(
+                    Host::Fqdn(fqdn!("deno.land")),
+                    Some(NetPort::new([8000..=8100])),
+                    None,
+                )),
+            ),
+            (
+                "deno.land:443,80",
+                Some(NetDescriptor(
+                    Host::Fqdn(fqdn!("deno.land")),
+                    Some(NetPort::new([80..=80, 443..=443])),
+                    None,
+                )),
+            ),
+            (
+                "[::1]:80,8000-8100",
+                Some(NetDescriptor(
+                    Host::Ip(IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1))),
+                    Some(NetPort::new([80..=80, 8000..=8100])),
+                    None,
+                )),
+            ),
+            ("deno.land:8100-8000", None),
+            ("deno.land:8000-", None),
+            ("deno.land:80,", None),
+            ("deno.land:80,,443", None),
+            ("deno.land:0-65536", None),
+            (
+                "https://deno.land",
+                Some(NetDescriptor(
+                    Host::Fqdn(fqdn!("deno.land")),
+                    None,
+                    Some("https".into()),
+                )),
+            ),
+            (
+                "WSS://[::1]:8000/",
+                Some(NetDescriptor(
+                    Host::Ip(IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1))),
+                    Some(8000.into()),
+                    Some("wss".into()),
+                )),
+            ),
+            ("https://deno.land/std", None),
+            ("https://", None),
+            ("1https://deno.land", None),
+            ("://deno.land", None),
         ];
 
         for (input, expected) in cases {
@@ -1737,4 +4441,447 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let _locked = TESTMUTEX.lock();
+        set_prompter(Box::new(TestPrompter));
+        let parser = TestPermissionDescriptorParser;
+        let mut perms = Permissions::from_options(
+            &parser,
+            &PermissionsOptions {
+                allow_read: Some(svec!["/foo"]),
+                deny_read: Some(svec!["/foo/secret"]),
+                allow_net: Some(svec!["127.0.0.1:8000", "[::1]", "deno.land"]),
+                deny_env: Some(vec![]),
+                allow_run: Some(svec!["deno"]),
+                deny_run: Some(svec!["curl"]),
+                allow_import: Some(svec!["jsr.io"]),
+                prompt: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        {
+            let prompt_value = PERMISSION_PROMPT_STUB_VALUE_SETTER.lock();
+            prompt_value.set(true);
+            let query = parser.parse_path_query("/bar").unwrap().into_write();
+            assert_eq!(perms.write.request(Some(&query)), PermissionState::Granted);
+            prompt_value.set(false);
+            let sys = SysDescriptor::parse("hostname".to_string()).unwrap();
+            assert_eq!(perms.sys.request(Some(&sys)), PermissionState::Denied);
+        }
+        let container = PermissionsContainer::new(Arc::new(parser.clone()), perms.clone());
+        let snapshot = container.snapshot();
+        assert_eq!(snapshot.version, PERMISSIONS_SNAPSHOT_VERSION);
+
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let snapshot: PermissionsSnapshot = serde_json::from_str(&json).unwrap();
+        let restored = PermissionsContainer::restore(Arc::new(parser), snapshot.clone()).unwrap();
+        assert_eq!(*restored.0.inner.lock(), perms);
+        assert_eq!(
+            restored.0.query_write(Some("/bar")).unwrap(),
+            PermissionState::Granted
+        );
+        assert_eq!(
+            restored.0.query_sys(Some("hostname")).unwrap(),
+            PermissionState::Denied
+        );
+        assert_eq!(
+            restored.0.query_read(Some("/foo/secret")).unwrap(),
+            PermissionState::Denied
+        );
+        assert_eq!(
+            restored.0.query_net(Some("[::1]:80")).unwrap(),
+            PermissionState::Granted
+        );
+        assert_eq!(restored.0.query_env(None), PermissionState::Denied);
+
+        let unsupported = PermissionsSnapshot {
+            version: PERMISSIONS_SNAPSHOT_VERSION + 1,
+            ..snapshot
+        };
+        assert!(Permissions::from_snapshot(unsupported).is_err());
+    }
+
+    #[test]
+    fn test_merge_layers() {
+        let layers = [
+            PermissionsLayer::new(
+                "system",
+                PermissionsOptions {
+                    allow_read: Some(svec!["/usr"]),
+                    deny_net: Some(svec!["169.254.169.254"]),
+                    allow_net: Some(svec!["deno.land"]),
+                    ..Default::default()
+                },
+            )
+            .with_locked("net"),
+            PermissionsLayer::new(
+                "project",
+                PermissionsOptions {
+                    allow_read: Some(svec!["/project", "/usr"]),
+                    allow_net: Some(svec!["example.com"]),
+                    deny_net: Some(svec!["evil.com"]),
+                    allow_env: Some(vec![]),
+                    ..Default::default()
+                },
+            ),
+            PermissionsLayer::new(
+                "flags",
+                PermissionsOptions {
+                    allow_env: Some(svec!["HOME"]),
+                    allow_net: Some(vec![]),
+                    deny_env: Some(svec!["AWS_SECRET_ACCESS_KEY"]),
+                    allow_all: true,
+                    prompt: true,
+                    ..Default::default()
+                },
+            ),
+        ];
+        let merged = PermissionsOptions::merge_layers(&layers);
+        assert_eq!(
+            merged.options,
+            PermissionsOptions {
+                allow_read: Some(svec!["/usr", "/project"]),
+                allow_net: Some(svec!["deno.land"]),
+                deny_net: Some(svec!["169.254.169.254", "evil.com"]),
+                allow_env: Some(vec![]),
+                deny_env: Some(svec!["AWS_SECRET_ACCESS_KEY"]),
+                prompt: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(merged.origin("allow_read", Some("/usr")), Some("system"));
+        assert_eq!(
+            merged.origin("allow_read", Some("/project")),
+            Some("project")
+        );
+        assert_eq!(merged.origin("deny_net", Some("evil.com")), Some("project"));
+        assert_eq!(merged.origin("allow_env", None), Some("project"));
+        assert_eq!(merged.origin("allow_env", Some("HOME")), None);
+        assert_eq!(merged.origin("allow_all", None), None);
+        let rejected = merged
+            .rejected
+            .iter()
+            .map(|origin| {
+                (
+                    origin.field.as_str(),
+                    origin.entry.as_deref(),
+                    origin.layer.as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rejected,
+            vec![
+                ("allow_net", Some("example.com"), "project"),
+                ("allow_net", None, "flags"),
+                ("allow_all", None, "flags"),
+            ]
+        );
+
+        // deny always wins over a grant from a higher layer
+        let parser = TestPermissionDescriptorParser;
+        let mut perms = Permissions::from_options(&parser, &merged.options).unwrap();
+        assert!(perms
+            .net
+            .check(
+                &NetDescriptor(Host::must_parse("evil.com"), None, None),
+                None
+            )
+            .is_err());
+        assert!(perms.env.check("AWS_SECRET_ACCESS_KEY", None).is_err());
+        assert!(perms.env.check("HOME", None).is_ok());
+
+        let merged = PermissionsOptions::merge_layers(&[
+            PermissionsLayer::new("system", PermissionsOptions::default()),
+            PermissionsLayer::new(
+                "flags",
+                PermissionsOptions {
+                    allow_all: true,
+                    ..Default::default()
+                },
+            ),
+        ]);
+        assert!(merged.options.allow_all);
+        assert_eq!(merged.origin("allow_all", None), Some("flags"));
+
+        // custom kinds are merged and locked like the built-in ones
+        let kv = |allow: Option<Vec<String>>, deny: Option<Vec<String>>| {
+            BTreeMap::from([("kv".to_string(), CustomPermissionOptions { allow, deny })])
+        };
+        let merged = PermissionsOptions::merge_layers(&[
+            PermissionsLayer::new(
+                "system",
+                PermissionsOptions {
+                    custom: kv(None, Some(svec!["secrets"])),
+                    ..Default::default()
+                },
+            )
+            .with_locked("kv"),
+            PermissionsLayer::new(
+                "flags",
+                PermissionsOptions {
+                    custom: kv(Some(svec!["users"]), Some(svec!["tokens"])),
+                    ..Default::default()
+                },
+            ),
+        ]);
+        assert_eq!(
+            merged.options.custom,
+            kv(None, Some(svec!["secrets", "tokens"]))
+        );
+        assert_eq!(merged.origin("deny_kv", Some("secrets")), Some("system"));
+        assert_eq!(merged.origin("deny_kv", Some("tokens")), Some("flags"));
+        assert_eq!(merged.rejected.len(), 1);
+        assert_eq!(merged.rejected[0].field, "allow_kv");
+    }
+
+    #[test]
+    fn test_lint() {
+        #[derive(Debug)]
+        struct UnresolvedRunParser;
+
+        impl PermissionDescriptorParser for UnresolvedRunParser {
+            fn parse_read_descriptor(&self, text: &str) -> Result<ReadDescriptor, AnyError> {
+                TestPermissionDescriptorParser.parse_read_descriptor(text)
+            }
+            fn parse_write_descriptor(&self, text: &str) -> Result<WriteDescriptor, AnyError> {
+                TestPermissionDescriptorParser.parse_write_descriptor(text)
+            }
+            fn parse_net_descriptor(&self, text: &str) -> Result<NetDescriptor, AnyError> {
+                TestPermissionDescriptorParser.parse_net_descriptor(text)
+            }
+            fn parse_import_descriptor(&self, text: &str) -> Result<ImportDescriptor, AnyError> {
+                TestPermissionDescriptorParser.parse_import_descriptor(text)
+            }
+            fn parse_env_descriptor(&self, text: &str) -> Result<EnvDescriptor, AnyError> {
+                TestPermissionDescriptorParser.parse_env_descriptor(text)
+            }
+            fn parse_sys_descriptor(&self, text: &str) -> Result<SysDescriptor, AnyError> {
+                TestPermissionDescriptorParser.parse_sys_descriptor(text)
+            }
+            fn parse_allow_run_descriptor(
+                &self,
+                text: &str,
+            ) -> Result<AllowRunDescriptorParseResult, AnyError> {
+                if text == "missing" {
+                    Ok(AllowRunDescriptorParseResult::Unresolved(Box::new(
+                        which::Error::CannotFindBinaryPath,
+                    )))
+                } else {
+                    TestPermissionDescriptorParser.parse_allow_run_descriptor(text)
+                }
+            }
+            fn parse_deny_run_descriptor(&self, text: &str) -> Result<DenyRunDescriptor, AnyError> {
+                TestPermissionDescriptorParser.parse_deny_run_descriptor(text)
+            }
+            fn parse_ffi_descriptor(&self, text: &str) -> Result<FfiDescriptor, AnyError> {
+                TestPermissionDescriptorParser.parse_ffi_descriptor(text)
+            }
+            fn parse_path_query(&self, path: &str) -> Result<PathQueryDescriptor, AnyError> {
+                TestPermissionDescriptorParser.parse_path_query(path)
+            }
+            fn parse_run_query(&self, requested: &str) -> Result<RunQueryDescriptor, AnyError> {
+                TestPermissionDescriptorParser.parse_run_query(requested)
+            }
+        }
+
+        let opts = PermissionsOptions {
+            allow_read: Some(svec!["/data", "/data/x", "/secret/key", "/home", "/data"]),
+            deny_read: Some(svec!["/secret", "/home/user/.ssh"]),
+            allow_net: Some(svec!["deno.land", "deno.land:abc"]),
+            allow_env: Some(svec!["HOME"]),
+            deny_env: Some(vec![]),
+            allow_run: Some(svec!["deno", "missing"]),
+            ..Default::default()
+        };
+        let lints = opts
+            .lint(&UnresolvedRunParser)
+            .into_iter()
+            .map(|lint| {
+                (
+                    lint.severity,
+                    lint.kind,
+                    lint.flag_name,
+                    lint.entry,
+                    lint.related,
+                )
+            })
+            .collect::<Vec<_>>();
+        let some = |s: &str| Some(s.to_string());
+        assert_eq!(
+            lints,
+            vec![
+                (
+                    LintSeverity::Info,
+                    LintKind::Duplicate,
+                    "read",
+                    "/data".to_string(),
+                    some("/data")
+                ),
+                (
+                    LintSeverity::Info,
+                    LintKind::Redundant,
+                    "read",
+                    "/data/x".to_string(),
+                    some("/data")
+                ),
+                (
+                    LintSeverity::Warning,
+                    LintKind::Shadowed,
+                    "read",
+                    "/secret/key".to_string(),
+                    some("/secret")
+                ),
+                (
+                    LintSeverity::Info,
+                    LintKind::PartiallyShadowed,
+                    "read",
+                    "/home".to_string(),
+                    some("/home/user/.ssh")
+                ),
+                (
+                    LintSeverity::Error,
+                    LintKind::Invalid,
+                    "net",
+                    "deno.land:abc".to_string(),
+                    None
+                ),
+                (
+                    LintSeverity::Warning,
+                    LintKind::Shadowed,
+                    "env",
+                    "HOME".to_string(),
+                    None
+                ),
+                (
+                    LintSeverity::Warning,
+                    LintKind::Unresolved,
+                    "run",
+                    "missing".to_string(),
+                    None
+                ),
+            ]
+        );
+
+        let mut perms = Permissions::from_options(
+            &TestPermissionDescriptorParser,
+            &PermissionsOptions {
+                allow_write: Some(svec!["/tmp", "/tmp/cache"]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        perms
+            .write
+            .prompt_denied_list
+            .insert(WriteDescriptor(PathBuf::from("/tmp/cache/lock")));
+        let lints = perms.lint();
+        assert_eq!(lints.len(), 3, "{lints:#?}");
+        assert_eq!(
+            lints[0].to_string(),
+            "info[write]: '/tmp' is allowed, but '/tmp/cache/lock' within it is denied"
+        );
+        assert_eq!(lints[1].kind, LintKind::Redundant);
+        assert_eq!(lints[2].kind, LintKind::PartiallyShadowed);
+        assert!(Permissions::allow_all().lint().is_empty());
+    }
+
+    #[test]
+    fn test_policy_file() {
+        let parser = TestPermissionDescriptorParser;
+        let toml_policy = r#"
+# sandbox policy
+allow_read = ["foo", "bar"]
+deny_net = ["evil.com:443"]
+allow_env = []
+"#;
+        let json_policy = r#"{
+  "allow_read": ["foo", "bar"],
+  "deny_net": ["evil.com:443"],
+  "allow_env": []
+}"#;
+        let expected = PermissionsOptions {
+            allow_read: Some(svec!["foo", "bar"]),
+            deny_net: Some(svec!["evil.com:443"]),
+            allow_env: Some(vec![]),
+            ..Default::default()
+        };
+        for (text, format) in [
+            (toml_policy, PolicyFormat::Toml),
+            (json_policy, PolicyFormat::Json),
+        ] {
+            let opts =
+                PermissionsOptions::from_policy_str(&parser, text, format, "policy").unwrap();
+            assert_eq!(opts, expected, "{format}");
+        }
+        // only policy files may leave fields out
+        assert!(serde_json::from_str::<PermissionsOptions>(json_policy).is_err());
+        // options serialized before the later fields were added still load
+        let mut old_options = serde_json::to_value(&expected).unwrap();
+        for field in [
+            "deny_import",
+            "allow_unix_socket",
+            "deny_unix_socket",
+            "specifier_policy",
+            "custom",
+            "prompt_grant_ttl",
+            "quotas",
+        ] {
+            old_options.as_object_mut().unwrap().remove(field).unwrap();
+        }
+        assert_eq!(
+            serde_json::from_value::<PermissionsOptions>(old_options).unwrap(),
+            expected
+        );
+
+        let dir = std::env::temp_dir().join(format!("bls-policy-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("policy.toml");
+        std::fs::write(&path, toml_policy).unwrap();
+        let perms = Permissions::from_policy_file(&parser, &path).unwrap();
+        assert!(perms.env.is_allow_all());
+        assert!(PermissionsOptions::from_policy_file(&parser, &dir.join("policy.yaml")).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_policy_file_errors() {
+        let parser = TestPermissionDescriptorParser;
+        #[rustfmt::skip]
+        let cases = [
+            // invalid descriptor
+            ("allow_read = [\"foo\"]\nallow_net = [\"deno.land\", \"deno.land:abc\"]\n",
+             PolicyFormat::Toml, 2, 27, Some("allow_net"), Some("deno.land:abc")),
+            ("{\n  \"deny_run\": [\"\"]\n}",
+             PolicyFormat::Json, 2, 16, Some("deny_run"), Some("")),
+            ("allow_sys = [\n  # comment with \"quotes\"\n  \"hostname\",\n  \"nope:\",\n]\n",
+             PolicyFormat::Toml, 4, 3, Some("allow_sys"), Some("nope:")),
+            // unknown field
+            ("allow_read = []\nallow_reed = []\n",
+             PolicyFormat::Toml, 2, 1, Some("allow_reed"), None),
+            // wrong type
+            ("{\"allow_all\": \"yes\"}",
+             PolicyFormat::Json, 1, 2, Some("allow_all"), None),
+            // syntax error
+            ("allow_read = [\"foo\"\n",
+             PolicyFormat::Toml, 2, 1, None, None),
+            ("{\n  \"allow_read\": [\"foo\",]\n}",
+             PolicyFormat::Json, 2, 24, None, None),
+        ];
+        for (text, format, line, column, field, descriptor) in cases {
+            let err =
+                PermissionsOptions::from_policy_str(&parser, text, format, "policy").unwrap_err();
+            let err = err.downcast_ref::<PolicyError>().unwrap();
+            assert_eq!(err.file, "policy", "{text}");
+            assert_eq!((err.line, err.column), (line, column), "{err}");
+            assert_eq!(err.field.as_deref(), field, "{err}");
+            assert_eq!(err.descriptor.as_deref(), descriptor, "{err}");
+            assert!(err
+                .to_string()
+                .starts_with(&format!("policy:{line}:{column}: ")));
+        }
+    }
 }
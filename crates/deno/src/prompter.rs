@@ -264,7 +264,7 @@ impl PermissionPrompter for TtyPrompter {
 
         // print to stderr so that if stdout is piped this is still displayed.
         let opts: String = if is_unary {
-            format!("[y/n/A] (y = yes, allow; n = no, deny; A = allow all {name} permissions)")
+            format!("[y/o/n/A] (y = yes, allow; o = allow once; n = no, deny; A = allow all {name} permissions)")
         } else {
             "[y/n] (y = yes, allow; n = no, deny)".to_string()
         };
@@ -331,6 +331,12 @@ impl PermissionPrompter for TtyPrompter {
                     writeln!(stderr_lock, "❌ {}", colors::bold(&msg)).unwrap();
                     break PromptResponse::Deny;
                 }
+                'o' | 'O' if is_unary => {
+                    clear_n_lines(&mut stderr_lock, if api_name.is_some() { 5 } else { 4 });
+                    let msg = format!("Granted {message} once.");
+                    writeln!(stderr_lock, "✅ {}", colors::bold(&msg)).unwrap();
+                    break PromptResponse::AllowOnce;
+                }
                 'A' if is_unary => {
                     clear_n_lines(&mut stderr_lock, if api_name.is_some() { 5 } else { 4 });
                     let msg = format!("Granted all {name} access.");